@@ -0,0 +1,289 @@
+//! Estimation of the relative abundance of the reference records in the sequences
+//!
+//! Each sequence (or pair of sequences) is assigned to the reference records sharing the most
+//! kmers with it (see [MatchedSequence::record_counts()]), the sequences assigned to several
+//! records being ambiguous. The number of sequences coming from each record is then estimated by
+//! an expectation-maximization (EM) of the shares of the records, each ambiguous sequence being
+//! redistributed among its records according to their shares: the probability that a sequence
+//! of a record is drawn at a given position is inversely proportional to the number of distinct
+//! kmers indexed from the record (its length). The relative abundance of a record is its number
+//! of sequences normalized by its number of kmers.
+
+/* std use */
+use std::io::Write;
+
+/* crates use */
+use ahash::AHashMap as HashMap;
+
+/* project use */
+use crate::colors::KmerColors;
+use crate::kmer_counter::KmerCounter;
+use crate::matched_sequences::MatchedSequence;
+
+/// header of the abundance table, whose rows are written by [Abundances::write_table()]
+pub const HEADER: &str = "reference\tkmers\tunique_reads\treads\tabundance\n";
+
+/// maximum number of iterations of the EM
+const MAX_ITERATIONS: usize = 10_000;
+
+/// the EM stops once the shares of the records change less than this
+const TOLERANCE: f64 = 1e-9;
+
+/// Sequences assigned to the reference records, grouped by set of best records
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReadAssignments {
+    /// number of sequences of each set of best records (sorted)
+    pub classes: HashMap<Vec<u32>, usize>,
+    /// number of sequences sharing no kmer with the records
+    pub unassigned: usize,
+}
+
+impl ReadAssignments {
+    /// assign some sequences (eg. the two mates of a pair) as a whole to the records sharing the
+    /// most kmers with them
+    pub fn add<D: MatchedSequence>(&mut self, matched_sequences: &[&D]) {
+        let mut counts = HashMap::<u32, usize>::new();
+        for matched_sequence in matched_sequences {
+            for (record, count) in matched_sequence.record_counts() {
+                *counts.entry(*record).or_default() += count;
+            }
+        }
+        let Some(best) = counts.values().max().copied() else {
+            self.unassigned += 1;
+            return;
+        };
+        let mut records = counts
+            .into_iter()
+            .filter(|(_, count)| *count == best)
+            .map(|(record, _)| record)
+            .collect::<Vec<_>>();
+        records.sort_unstable();
+        *self.classes.entry(records).or_default() += 1;
+    }
+
+    /// number of assigned sequences
+    pub fn assigned(&self) -> usize {
+        self.classes.values().sum()
+    }
+
+    /// number of sequences assigned to several records
+    pub fn ambiguous(&self) -> usize {
+        self.classes
+            .iter()
+            .filter(|(records, _)| records.len() > 1)
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+/// Estimated abundances of the reference records, see [estimate_abundances()]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Abundances {
+    /// number of sequences assigned only to each record
+    pub unique_reads: Vec<usize>,
+    /// estimated number of sequences of each record
+    pub reads: Vec<f64>,
+    /// relative abundance of each record (summing to 1, unless no sequence is assigned)
+    pub abundances: Vec<f64>,
+}
+
+impl Abundances {
+    /// write the rows of the abundances of the records
+    pub fn write_table(
+        &self,
+        output: &mut impl Write,
+        names: &[String],
+        kmers: &[usize],
+    ) -> std::io::Result<()> {
+        for (record, name) in names.iter().enumerate() {
+            writeln!(
+                output,
+                "{}\t{}\t{}\t{:.2}\t{:.6}",
+                name,
+                kmers[record],
+                self.unique_reads[record],
+                self.reads[record],
+                self.abundances[record]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// number of distinct indexed kmers of each reference record of `colors`, given the counters of
+/// the indexed kmers (that store their color, see [crate::colors::Colored])
+pub fn reference_kmers<'a, T: KmerCounter + 'a>(
+    colors: &KmerColors,
+    counters: impl Iterator<Item = &'a T>,
+) -> Vec<usize> {
+    // the kmers are counted by color first, each color being shared by many kmers
+    let mut color_kmers = vec![0; colors.table.len()];
+    for color in counters.filter_map(|counter| counter.color()) {
+        color_kmers[color as usize] += 1;
+    }
+    let mut kmers = vec![0; colors.records.len()];
+    for (color, count) in color_kmers.iter().enumerate() {
+        for record in colors.table.targets(color as u32) {
+            kmers[*record as usize] += count;
+        }
+    }
+    kmers
+}
+
+/// estimate the number of sequences and the relative abundance of each record, given its number
+/// of distinct kmers (`kmers`), by redistributing the ambiguous sequences with an EM
+pub fn estimate_abundances(assignments: &ReadAssignments, kmers: &[usize]) -> Abundances {
+    let nb_records = kmers.len();
+    let mut unique_reads = vec![0; nb_records];
+    for (records, count) in &assignments.classes {
+        if let [record] = records.as_slice() {
+            unique_reads[*record as usize] += count;
+        }
+    }
+    if assignments.classes.is_empty() {
+        return Abundances {
+            unique_reads,
+            reads: vec![0.0; nb_records],
+            abundances: vec![0.0; nb_records],
+        };
+    }
+    // probability of drawing a sequence at a given position of each record
+    let weights = kmers
+        .iter()
+        .map(|kmers| 1.0 / (*kmers).max(1) as f64)
+        .collect::<Vec<_>>();
+    let total = assignments.assigned() as f64;
+
+    // share of the sequences of each record, starting from a uniform distribution
+    let mut shares = vec![1.0 / nb_records.max(1) as f64; nb_records];
+    let mut reads = vec![0.0; nb_records];
+    for _ in 0..MAX_ITERATIONS {
+        // expectation: the sequences of each class are split among its records
+        reads.fill(0.0);
+        for (records, count) in &assignments.classes {
+            let likelihood = records
+                .iter()
+                .map(|r| shares[*r as usize] * weights[*r as usize])
+                .sum::<f64>();
+            for r in records {
+                let r = *r as usize;
+                reads[r] += match likelihood > 0.0 {
+                    true => *count as f64 * shares[r] * weights[r] / likelihood,
+                    false => *count as f64 / records.len() as f64,
+                };
+            }
+        }
+        // maximization: the shares are the expected numbers of sequences
+        let mut change: f64 = 0.0;
+        for (share, reads) in shares.iter_mut().zip(&reads) {
+            let new_share = reads / total;
+            change = change.max((new_share - *share).abs());
+            *share = new_share;
+        }
+        if change < TOLERANCE {
+            break;
+        }
+    }
+
+    // the numbers of sequences are normalized by the lengths of the records
+    let normalized = reads
+        .iter()
+        .zip(&weights)
+        .map(|(reads, weight)| reads * weight)
+        .collect::<Vec<_>>();
+    let sum = normalized.iter().sum::<f64>();
+    let abundances = normalized
+        .iter()
+        .map(|value| if sum > 0.0 { value / sum } else { 0.0 })
+        .collect();
+    Abundances {
+        unique_reads,
+        reads,
+        abundances,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /* project use */
+    use super::*;
+    use crate::colors::Colored;
+    use crate::matched_sequences::MachedCount;
+
+    #[test]
+    fn abundances() -> std::io::Result<()> {
+        let mut assignments = ReadAssignments::default();
+        // 30 sequences of record 0, 10 of record 1, 20 shared by both, 10 of record 2, and 5
+        // sequences sharing no kmer
+        let matched = |records: &[u32]| {
+            let mut matched_sequence = MachedCount::new(10);
+            matched_sequence.add_records(records);
+            matched_sequence
+        };
+        for (records, count) in [
+            (&[0][..], 30),
+            (&[1], 10),
+            (&[0, 1], 20),
+            (&[2], 10),
+            (&[], 5),
+        ] {
+            for _ in 0..count {
+                assignments.add(&[&matched(records)]);
+            }
+        }
+        // the mates of a pair are assigned as a whole
+        let mut pairs = ReadAssignments::default();
+        pairs.add(&[&matched(&[0, 1]), &matched(&[1])]);
+        assert_eq!(pairs.classes, HashMap::from([(vec![1], 1)]));
+
+        assert_eq!(
+            (
+                assignments.assigned(),
+                assignments.ambiguous(),
+                assignments.unassigned
+            ),
+            (70, 20, 5)
+        );
+
+        // the ambiguous sequences are split according to the unique ones
+        let estimation = estimate_abundances(&assignments, &[100, 100, 50]);
+        assert_eq!(estimation.unique_reads, vec![30, 10, 10]);
+        assert!((estimation.reads[0] - 45.0).abs() < 1e-6);
+        assert!((estimation.reads[1] - 15.0).abs() < 1e-6);
+        assert!((estimation.reads[2] - 10.0).abs() < 1e-6);
+        // record 2 is half as long as the others
+        assert!((estimation.abundances[0] - 0.5625).abs() < 1e-6);
+        assert!((estimation.abundances[1] - 0.1875).abs() < 1e-6);
+        assert!((estimation.abundances[2] - 0.25).abs() < 1e-6);
+
+        let mut output = Vec::new();
+        let names = [
+            "gene1".to_string(),
+            "gene2".to_string(),
+            "gene3".to_string(),
+        ];
+        estimation.write_table(&mut output, &names, &[100, 100, 50])?;
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "gene1\t100\t30\t45.00\t0.562500
+gene2\t100\t10\t15.00\t0.187500
+gene3\t50\t10\t10.00\t0.250000
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn kmers_of_references() {
+        let mut colors = KmerColors::new(vec!["genes".to_string()]);
+        let gene1 = colors.add_record("gene1".to_string(), 0);
+        let gene2 = colors.add_record("gene2".to_string(), 0);
+        let mut counters = Vec::new();
+        for targets in [&[gene1][..], &[gene1, gene2], &[gene2], &[gene2]] {
+            let mut counter = Colored::<atomic_counter::RelaxedCounter>::default();
+            counter.set_color(colors.table.get_or_insert(targets));
+            counters.push(counter);
+        }
+        assert_eq!(reference_kmers(&colors, counters.iter()), vec![2, 3]);
+    }
+}
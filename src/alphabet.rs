@@ -0,0 +1,262 @@
+//! Alphabets of the sequences and of the kmers: nucleotides or amino acids
+//!
+//! Amino acid kmers are packed in the same [Kmer] types as the nucleotide kmers, each amino acid
+//! being encoded on 3 consecutive 2-bit codes (the 20 standard amino acids in alphabetical order of
+//! their one letter code, so that the order of the packed kmers is still the lexicographic one).
+//! A kmer of `kmer_size` amino acids is thus stored as a kmer of `3 * kmer_size` codes. Amino acid
+//! kmers have no strand: the reverse kmer yielded by the iterators is the forward one.
+//!
+//! DNA sequences may also be translated in 6 frames (see [six_frames()]), to be searched against
+//! amino acid kmers.
+
+/* std use */
+use std::borrow::Cow;
+
+/* crates use */
+use needletail::parser::SequenceRecord;
+use needletail::Sequence as _;
+
+/* project use */
+use crate::consts::ENCODE_MAP;
+use crate::kmer::{Kmer, KmerIter};
+
+/// number of 2-bit codes of an amino acid
+pub const AMINO_ACID_CODES: usize = 3;
+
+/// the 20 standard amino acids, in the order of their codes
+pub const AMINO_ACIDS: &[u8; 20] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// code of each amino acid (upper or lower case), 20 for the other letters
+pub static AMINO_ACID_MAP: [u8; 256] = {
+    let mut map = [20; 256];
+    let mut i = 0;
+    while i < AMINO_ACIDS.len() {
+        map[AMINO_ACIDS[i] as usize] = i as u8;
+        map[AMINO_ACIDS[i].to_ascii_lowercase() as usize] = i as u8;
+        i += 1;
+    }
+    map
+};
+
+/// Alphabet of the sequences and of the kmers
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alphabet {
+    /// nucleotides (ACGT)
+    #[default]
+    Dna,
+    /// amino acids (the 20 standard ones)
+    Protein,
+}
+
+impl Alphabet {
+    /// number of 2-bit codes of the packed kmers of `kmer_size` letters
+    pub fn packed_size(&self, kmer_size: usize) -> usize {
+        match self {
+            Self::Dna => kmer_size,
+            Self::Protein => AMINO_ACID_CODES * kmer_size,
+        }
+    }
+
+    /// largest kmer size (in letters) that can be stored in a kmer type
+    pub fn max_kmer_size<K: Kmer>(&self) -> usize {
+        K::MAX_KMER_SIZE / self.packed_size(1)
+    }
+
+    /// normalized sequence of a fastx record
+    ///
+    /// nucleotides are in upper case and the non ACGT letters are replaced by N (see
+    /// [needletail::Sequence::normalize()]), amino acids are in upper case
+    pub fn normalize<'a>(&self, record: &'a SequenceRecord) -> Cow<'a, [u8]> {
+        match self {
+            Self::Dna => record.normalize(false),
+            Self::Protein => Cow::Owned(record.seq().to_ascii_uppercase()),
+        }
+    }
+
+    /// kmers of a normalized sequence, see [KmerIter] and [AminoAcidKmerIter]
+    #[auto_enums::auto_enum(Iterator)]
+    pub fn kmers<'a, K: Kmer>(
+        &self,
+        seq: &'a [u8],
+        kmer_size: usize,
+    ) -> impl Iterator<Item = (usize, K, K)> + 'a {
+        match self {
+            Self::Dna => KmerIter::new(seq, kmer_size),
+            Self::Protein => AminoAcidKmerIter::new(seq, kmer_size),
+        }
+    }
+
+    /// number of kmers of a normalized sequence, without the kmers containing a non ACGT letter (or
+    /// a non standard amino acid) skipped by [Alphabet::kmers()]
+    pub fn nb_valid_kmers(&self, seq: &[u8], kmer_size: usize) -> usize {
+        let mut valid = 0;
+        seq.iter()
+            .filter(|&&letter| {
+                let is_valid = match self {
+                    Self::Dna => ENCODE_MAP[letter as usize] != 4,
+                    Self::Protein => AMINO_ACID_MAP[letter as usize] != 20,
+                };
+                valid = if is_valid { valid + 1 } else { 0 };
+                valid >= kmer_size
+            })
+            .count()
+    }
+
+    /// ascii (uppercase) representation of a packed kmer of `kmer_size` letters
+    pub fn kmer_to_bytes<K: Kmer>(&self, kmer: &K, kmer_size: usize) -> Vec<u8> {
+        match self {
+            Self::Dna => kmer.to_bytes(kmer_size),
+            Self::Protein => {
+                let packed_size = self.packed_size(kmer_size);
+                (0..kmer_size)
+                    .map(|i| {
+                        let code = (0..AMINO_ACID_CODES).fold(0, |acc, j| {
+                            (acc << 2) | kmer.code(AMINO_ACID_CODES * i + j, packed_size) as usize
+                        });
+                        AMINO_ACIDS.get(code).copied().unwrap_or(b'X')
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Alphabet {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Dna => write!(f, "nucleotide"),
+            Self::Protein => write!(f, "amino acid"),
+        }
+    }
+}
+
+/// Rolling iterator on the packed amino acid kmers of a sequence
+///
+/// Yields `(position, kmer, kmer)` for each kmer of the sequence (the same kmer twice, as amino
+/// acid kmers have no reverse complement), kmers containing a non standard amino acid (eg. `X` or
+/// a stop `*`) are skipped.
+pub struct AminoAcidKmerIter<'a, K> {
+    seq: &'a [u8],
+    kmer_size: usize,
+    position: usize,
+    valid: usize,
+    kmer: K,
+}
+
+impl<'a, K: Kmer> AminoAcidKmerIter<'a, K> {
+    /// Iterate on the kmers of `kmer_size` amino acids of `seq`
+    pub fn new(seq: &'a [u8], kmer_size: usize) -> Self {
+        Self {
+            seq,
+            kmer_size,
+            position: 0,
+            valid: 0,
+            kmer: K::default(),
+        }
+    }
+}
+
+impl<K: Kmer> Iterator for AminoAcidKmerIter<'_, K> {
+    type Item = (usize, K, K);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let packed_size = AMINO_ACID_CODES * self.kmer_size;
+        while self.position < self.seq.len() {
+            let code = AMINO_ACID_MAP[self.seq[self.position] as usize];
+            self.position += 1;
+            if code == 20 {
+                self.valid = 0;
+                continue;
+            }
+            for shift in (0..AMINO_ACID_CODES).rev() {
+                self.kmer = self.kmer.push((code >> (2 * shift)) & 3, packed_size);
+            }
+            self.valid += 1;
+            if self.valid >= self.kmer_size {
+                return Some((self.position - self.kmer_size, self.kmer, self.kmer));
+            }
+        }
+        None
+    }
+}
+
+/// amino acid of a codon of the standard genetic code (`*` for a stop, `X` if the codon contains
+/// a non ACGT letter)
+fn translate_codon(codon: &[u8]) -> u8 {
+    // codons in the order TCAG of each nucleotide
+    const CODE: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+    codon
+        .iter()
+        .try_fold(0, |acc, nucleotide| {
+            let index = match nucleotide {
+                b'T' | b't' => 0,
+                b'C' | b'c' => 1,
+                b'A' | b'a' => 2,
+                b'G' | b'g' => 3,
+                _ => return None,
+            };
+            Some(acc * 4 + index)
+        })
+        .map_or(b'X', |index| CODE[index])
+}
+
+/// translation of a DNA sequence from its first nucleotide (the last incomplete codon is dropped)
+pub fn translate(seq: &[u8]) -> Vec<u8> {
+    seq.chunks_exact(3).map(translate_codon).collect()
+}
+
+/// translations of a normalized DNA sequence in its 6 frames: the 3 frames of the forward strand,
+/// then the 3 frames of the reverse complement strand
+pub fn six_frames(seq: &[u8]) -> impl Iterator<Item = Vec<u8>> + '_ {
+    let mut reverse = seq.to_vec();
+    crate::count::rev_comp(&mut reverse);
+    (0..3)
+        .map(move |frame| translate(seq.get(frame..).unwrap_or_default()))
+        .chain((0..3).map(move |frame| translate(reverse.get(frame..).unwrap_or_default())))
+}
+
+#[cfg(test)]
+mod tests {
+    /* project use */
+    use super::*;
+
+    #[test]
+    fn amino_acid_kmers() {
+        let seq = b"MKVLAXWYQ*GHKV";
+        let kmers = Alphabet::Protein.kmers::<u64>(seq, 3).collect::<Vec<_>>();
+
+        let positions = kmers
+            .iter()
+            .map(|(position, _, _)| *position)
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![0, 1, 2, 6, 10, 11]);
+        let ascii = kmers
+            .iter()
+            .map(|(_, kmer, _)| Alphabet::Protein.kmer_to_bytes(kmer, 3))
+            .collect::<Vec<_>>();
+        assert_eq!(ascii, [b"MKV", b"KVL", b"VLA", b"WYQ", b"GHK", b"HKV"]);
+        assert!(kmers.iter().all(|(_, forward, reverse)| forward == reverse));
+        assert_eq!(Alphabet::Protein.nb_valid_kmers(seq, 3), kmers.len());
+        assert_eq!(Alphabet::Dna.nb_valid_kmers(b"ACGTNACGNNT", 3), 3);
+
+        // the packed kmers are in the lexicographic order
+        let (_, a, _) = Alphabet::Protein.kmers::<u64>(b"ACY", 3).next().unwrap();
+        let (_, b, _) = Alphabet::Protein.kmers::<u64>(b"ADA", 3).next().unwrap();
+        assert!(a < b);
+        assert_eq!(Alphabet::Protein.max_kmer_size::<u64>(), 10);
+    }
+
+    #[test]
+    fn translation() {
+        assert_eq!(translate(b"ATGGCCTAAAGNTT"), b"MA*X");
+        let frames = six_frames(b"ATGAAATTTGGG").collect::<Vec<_>>();
+        assert_eq!(frames[0], b"MKFG");
+        assert_eq!(frames[1], b"*NL");
+        assert_eq!(frames[2], b"EIW");
+        // reverse complement: CCCAAATTTCAT
+        assert_eq!(frames[3], b"PKFH");
+        assert_eq!(frames[4], b"PNF");
+        assert_eq!(frames[5], b"QIS");
+    }
+}
@@ -9,9 +9,12 @@ use std::ops::Range;
 use std::sync::mpsc::{SendError, sync_channel};
 
 use ahash::AHashMap as HashMap;
-use needletail::{FastxReader, Sequence};
+use needletail::parser::SequenceRecord;
+use needletail::FastxReader;
 use rayon::prelude::*;
 
+use crate::alphabet::Alphabet;
+
 const CHUNK_BUF_SIZE: usize = 65536; // total buffer size
 const CHUNK_RECORDS_SIZE: usize = 512; // initial size of the record vector
 const INPUT_CHANNEL_SIZE: usize = 8;
@@ -31,20 +34,17 @@ where X: Send,
 
 
 impl<X, O> Chunk<X, O>
-where X: Send,
-      O: Send + OptionalId,
+where
+    X: Send,
+    O: Send + OptionalId,
 {
     /// Run a faillible closure on each record, stop at the first error
     pub fn try_for_each<F, E>(&mut self, func: &mut F) -> Result<(), E>
-        where F: FnMut(Record<'_, X>) -> Result<(), E>
+    where
+        F: FnMut(Record<'_, X>) -> Result<(), E>,
     {
         for rec in &mut self.records {
-            let [id, seq] = self.buf.get_disjoint_mut([rec.id_start.get(&rec.seq), rec.seq.clone()]).unwrap();
-            func(Record{
-                read_id: rec.read_id,
-                id, seq,
-                extra: &mut rec.extra,
-            })?;
+            func(rec.as_record(&mut self.buf, 0))?;
         }
         Ok(())
     }
@@ -55,6 +55,38 @@ where X: Send,
     {
         let _ = self.try_for_each(&mut |r| -> Result<_, Never> { func(r); Ok(()) });
     }
+
+    /// Run a faillible closure on each pair of records, stop at the first error
+    ///
+    /// the chunk must have been built from paired inputs (see [from_paired_fastx_readers()])
+    pub fn try_for_each_pair<F, E>(&mut self, func: &mut F) -> Result<(), E>
+    where
+        F: FnMut(Record<'_, X>, Record<'_, X>) -> Result<(), E>,
+    {
+        for pair in self.records.chunks_exact_mut(2) {
+            let [first, second] = pair else {
+                unreachable!()
+            };
+            let offset = first.end;
+            let (first_buf, second_buf) = self.buf.split_at_mut(offset);
+            func(
+                first.as_record(first_buf, 0),
+                second.as_record(second_buf, offset),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Run a closure on each pair of records
+    pub fn for_each_pair<F>(&mut self, func: &mut F)
+    where
+        F: FnMut(Record<'_, X>, Record<'_, X>),
+    {
+        let _ = self.try_for_each_pair(&mut |r1, r2| -> Result<_, Never> {
+            func(r1, r2);
+            Ok(())
+        });
+    }
 }
 
 /// structure representing a fastx record
@@ -70,6 +102,14 @@ pub struct Record<'a, X> {
     /// normalized fastx sequence
     pub seq: &'a mut [u8],
 
+    /// quality line of a fastq record (always [None] unless the chunks are built with [WithQual]
+    /// or [WithOriginal])
+    pub qual: Option<&'a mut [u8]>,
+
+    /// original fastx sequence, not normalized and with its line breaks (always [None] unless the
+    /// chunks are built with [WithOriginal])
+    pub raw_seq: Option<&'a [u8]>,
+
     /// extra field containing user data
     pub extra: &'a mut X,
 }
@@ -79,21 +119,64 @@ struct InnerRecord<X, O> {
     /// index id of this record (as it appears in the input)
     read_id: usize,
 
-    /// position of the record id string in the chunk buffer (optional)
+    /// position of the record id string (and quality line, original sequence) in the chunk
+    /// buffer (optional)
     id_start: O,
 
     /// position of the sequence in the chunk buffer
     seq: std::ops::Range<usize>,
 
+    /// end of the data of this record in the chunk buffer
+    end: usize,
+
     /// extra field containing user data
-    extra: X
+    extra: X,
+}
+
+impl<X, O: OptionalId> InnerRecord<X, O> {
+    /// Build the [Record] presented in the API
+    ///
+    /// `buf` is the part of the chunk buffer starting at `offset` and containing this record
+    fn as_record<'a>(&'a mut self, buf: &'a mut [u8], offset: usize) -> Record<'a, X> {
+        let shift = |range: Range<usize>| range.start - offset..range.end - offset;
+        let empty = self.seq.end..self.seq.end;
+        let qual_range = self.id_start.get_qual(&self.seq);
+        let raw_range = self.id_start.get_raw_seq(&self.seq);
+        let (has_qual, has_raw) = (qual_range.is_some(), raw_range.is_some());
+        let [id, seq, qual, raw_seq] = buf
+            .get_disjoint_mut([
+                shift(self.id_start.get(&self.seq)),
+                shift(self.seq.clone()),
+                shift(qual_range.unwrap_or(empty.clone())),
+                shift(raw_range.unwrap_or(empty)),
+            ])
+            .unwrap();
+        Record {
+            read_id: self.read_id,
+            id,
+            seq,
+            qual: has_qual.then_some(qual),
+            raw_seq: has_raw.then_some(&*raw_seq),
+            extra: &mut self.extra,
+        }
+    }
 }
 
-/// Trait for including or excluding the "id" field in the fastx record
-trait OptionalId: Sized {
-    fn len(id: &[u8]) -> usize;
-    fn set(id: &[u8], buf: &mut Vec<u8>) -> Self;
+/// Trait for including or excluding the "id" (and "qual", "raw_seq") field in the fastx record
+///
+/// The id is stored in the chunk buffer just before the sequence, the quality line and the
+/// original sequence just after it.
+pub(crate) trait OptionalId: Sized {
+    fn len(record: &SequenceRecord) -> usize;
+    fn set(record: &SequenceRecord, buf: &mut Vec<u8>) -> Self;
+    fn set_tail(&mut self, _record: &SequenceRecord, _buf: &mut Vec<u8>) {}
     fn get(&self, seq: &Range<usize>) -> Range<usize>;
+    fn get_qual(&self, _seq: &Range<usize>) -> Option<Range<usize>> {
+        None
+    }
+    fn get_raw_seq(&self, _seq: &Range<usize>) -> Option<Range<usize>> {
+        None
+    }
 }
 
 /// Marker to skip the fastx record id when building the chunks
@@ -104,21 +187,33 @@ pub struct WithoutId;
 /// Marker to include a copy of the fastx record id when building the chunks
 pub struct WithId(usize);
 
-impl OptionalId for WithoutId
-{
-    fn len(_id: &[u8]) -> usize { 0 }
-    fn set(_id: &[u8], _buf: &mut Vec<u8>) -> Self { Self }
-    fn get(&self, _seq: &Range<usize>) -> Range<usize> { 0..0 }
+/// Marker to include a copy of the fastx record id and of the fastq quality line when building
+/// the chunks
+pub struct WithQual(WithId, Option<usize>);
+
+/// Marker to include a copy of the fastx record id, of the fastq quality line and of the original
+/// (not normalized) sequence when building the chunks
+pub struct WithOriginal(WithQual, usize);
+
+impl OptionalId for WithoutId {
+    fn len(_record: &SequenceRecord) -> usize {
+        0
+    }
+    fn set(_record: &SequenceRecord, _buf: &mut Vec<u8>) -> Self {
+        Self
+    }
+    fn get(&self, seq: &Range<usize>) -> Range<usize> {
+        seq.start..seq.start
+    }
 }
 
-impl OptionalId for WithId
-{
-    fn len(id: &[u8]) -> usize {
-        id.len()
+impl OptionalId for WithId {
+    fn len(record: &SequenceRecord) -> usize {
+        record.id().len()
     }
-    fn set(id: &[u8], buf: &mut Vec<u8>) -> Self {
+    fn set(record: &SequenceRecord, buf: &mut Vec<u8>) -> Self {
         let start = buf.len();
-        buf.extend_from_slice(id);
+        buf.extend_from_slice(record.id());
         Self(start)
     }
     fn get(&self, seq: &Range<usize>) -> Range<usize> {
@@ -126,12 +221,64 @@ impl OptionalId for WithId
     }
 }
 
+impl OptionalId for WithQual {
+    fn len(record: &SequenceRecord) -> usize {
+        WithId::len(record) + record.qual().map_or(0, <[u8]>::len)
+    }
+    fn set(record: &SequenceRecord, buf: &mut Vec<u8>) -> Self {
+        Self(WithId::set(record, buf), None)
+    }
+    fn set_tail(&mut self, record: &SequenceRecord, buf: &mut Vec<u8>) {
+        if let Some(qual) = record.qual() {
+            buf.extend_from_slice(qual);
+            self.1 = Some(qual.len());
+        }
+    }
+    fn get(&self, seq: &Range<usize>) -> Range<usize> {
+        self.0.get(seq)
+    }
+    fn get_qual(&self, seq: &Range<usize>) -> Option<Range<usize>> {
+        self.1.map(|len| seq.end..seq.end + len)
+    }
+}
+
+impl OptionalId for WithOriginal {
+    fn len(record: &SequenceRecord) -> usize {
+        WithQual::len(record) + record.raw_seq().len()
+    }
+    fn set(record: &SequenceRecord, buf: &mut Vec<u8>) -> Self {
+        Self(WithQual::set(record, buf), 0)
+    }
+    fn set_tail(&mut self, record: &SequenceRecord, buf: &mut Vec<u8>) {
+        self.0.set_tail(record, buf);
+        buf.extend_from_slice(record.raw_seq());
+        self.1 = record.raw_seq().len();
+    }
+    fn get(&self, seq: &Range<usize>) -> Range<usize> {
+        self.0.get(seq)
+    }
+    fn get_qual(&self, seq: &Range<usize>) -> Option<Range<usize>> {
+        self.0.get_qual(seq)
+    }
+    fn get_raw_seq(&self, seq: &Range<usize>) -> Option<Range<usize>> {
+        let start = seq.end + self.0 .1.unwrap_or(0);
+        Some(start..start + self.1)
+    }
+}
+
 /// Iterator for converting a fastx input into a sequence of [Chunk]s
 pub struct ChunksReader<X, O>
-where X: Send,
-      O: Send,
+where
+    X: Send,
+    O: Send,
 {
     reader: Box<dyn FastxReader>,
+    /// input of the second mates of paired reads (None if the input is not paired, or if it is
+    /// interleaved)
+    mates: Option<Box<dyn FastxReader>>,
+    paired: bool,
+    /// alphabet of the sequences, for their normalization
+    alphabet: Alphabet,
     chunk: Chunk<X, O>,
     chunk_id: usize,
     read_id: usize,
@@ -151,14 +298,119 @@ where X: Send,
     }
 }
 
+impl<X, O> ChunksReader<X, O>
+where
+    X: Send + Default,
+    O: Send + OptionalId,
+{
+    /// Append a record to a chunk
+    fn push_record(
+        chunk: &mut Chunk<X, O>,
+        seq_record: &SequenceRecord,
+        seq: &[u8],
+        read_id: usize,
+    ) {
+        let mut id_start = O::set(seq_record, &mut chunk.buf);
+        let seq_start = chunk.buf.len();
+        chunk.buf.extend_from_slice(seq);
+        let seq_end = chunk.buf.len();
+        id_start.set_tail(seq_record, &mut chunk.buf);
+        chunk.records.push(InnerRecord {
+            read_id,
+            id_start,
+            seq: seq_start..seq_end,
+            end: chunk.buf.len(),
+            extra: X::default(),
+        });
+    }
+
+    /// Get the next chunk of paired records (the two mates of a pair are always in the same chunk)
+    fn next_pairs(&mut self) -> Option<anyhow::Result<Chunk<X, O>>> {
+        loop {
+            let read_id = self.read_id;
+            let name = match self.reader.next() {
+                None => {
+                    if let Some(Ok(_)) = self.mates.as_mut().and_then(|mates| mates.next()) {
+                        return Some(Err(anyhow::anyhow!(
+                            "the second paired input contains more records than the first one"
+                        )));
+                    }
+                    if self.chunk.records.is_empty() {
+                        return None;
+                    }
+                    break;
+                }
+                Some(Err(e)) => return Some(Err(e.into())),
+                Some(Ok(r)) => {
+                    Self::push_record(&mut self.chunk, &r, &self.alphabet.normalize(&r), read_id);
+                    mate_name(r.id()).to_vec()
+                }
+            };
+
+            // the second mate is read from the second input, or is the next interleaved record
+            let interleaved = self.mates.is_none();
+            let mates = self.mates.as_mut().unwrap_or(&mut self.reader);
+            match mates.next() {
+                None if !interleaved => {
+                    return Some(Err(anyhow::anyhow!(
+                        "the first paired input contains more records than the second one"
+                    )))
+                }
+                None => {
+                    return Some(Err(anyhow::anyhow!(
+                        "the interleaved input contains an odd number of records"
+                    )))
+                }
+                Some(Err(e)) => return Some(Err(e.into())),
+                Some(Ok(r)) => {
+                    if mate_name(r.id()) != name {
+                        return Some(Err(anyhow::anyhow!(
+                            "the mates of pair {} have different names: {} and {}",
+                            read_id / 2 + 1,
+                            String::from_utf8_lossy(&name),
+                            String::from_utf8_lossy(mate_name(r.id()))
+                        )));
+                    }
+                    let seq = self.alphabet.normalize(&r);
+                    Self::push_record(&mut self.chunk, &r, &seq, read_id + 1)
+                }
+            }
+            self.read_id += 2;
+
+            if self.chunk.buf.len() >= CHUNK_BUF_SIZE {
+                break;
+            }
+        }
+        let new_chunk = Self::new_chunk(self.chunk_id);
+        self.chunk_id += 1;
+        Some(Ok(std::mem::replace(&mut self.chunk, new_chunk)))
+    }
+}
+
+/// Name of a paired read: the first word of its header, without its `/1` or `/2` suffix
+fn mate_name(id: &[u8]) -> &[u8] {
+    let name = id
+        .split(|c| c.is_ascii_whitespace())
+        .next()
+        .unwrap_or_default();
+    match name {
+        [name @ .., b'/', b'1' | b'2'] => name,
+        _ => name,
+    }
+}
+
 impl<X, O> Iterator for ChunksReader<X, O>
-where X: Send + Default,
-      O: Send + OptionalId,
+where
+    X: Send + Default,
+    O: Send + OptionalId,
 {
     type Item = anyhow::Result<Chunk<X, O>>;
 
-    fn next(&mut self) -> Option<Self::Item>
-    {
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.paired {
+            return self.next_pairs();
+        }
+
         let new_chunk = loop {
             // get the next record from the needletail reader
             let read_id = self.read_id;
@@ -171,23 +423,12 @@ where X: Send + Default,
             };
 
             // prepare the new InnerRecord
-            let id  = seq_record.id();
-            let seq = seq_record.normalize(false);
-
-            let push_record = |chunk: &mut Chunk<X, O>| {
-                let id_start  = O::set(id, &mut chunk.buf);
-                let seq_start = chunk.buf.len();
-                chunk.buf.extend_from_slice(&seq);
-                let end       = chunk.buf.len();
-                chunk.records.push(InnerRecord{
-                    read_id, id_start,
-                    seq: seq_start..end,
-                    extra: X::default(),
-                });
-            };
+            let seq = self.alphabet.normalize(&seq_record);
+            let push_record =
+                |chunk: &mut Chunk<X, O>| Self::push_record(chunk, &seq_record, &seq, read_id);
 
             // flush the current chunk if the new record would overfill its buffer
-            let needed_capacity = self.chunk.buf.len() + O::len(id) + seq.len();
+            let needed_capacity = self.chunk.buf.len() + O::len(&seq_record) + seq.len();
             if self.chunk.buf.capacity() < needed_capacity && !self.chunk.records.is_empty()
             {
                 let mut new_chunk = Self::new_chunk(self.chunk_id);
@@ -203,12 +444,46 @@ where X: Send + Default,
 }
 
 /// Build an iterator for converting a fastx input into a sequence of [Chunk] values
-pub fn from_fastx_reader<X, O>(reader: Box<dyn FastxReader>) -> anyhow::Result<ChunksReader<X, O>>
-where X: Send,
-      O: Send,
+///
+/// The sequences are normalized according to their `alphabet` (see [Alphabet::normalize()])
+pub fn from_fastx_reader<X, O>(
+    reader: Box<dyn FastxReader>,
+    alphabet: Alphabet,
+) -> anyhow::Result<ChunksReader<X, O>>
+where
+    X: Send,
+    O: Send,
+{
+    Ok(ChunksReader {
+        reader,
+        mates: None,
+        paired: false,
+        alphabet,
+        chunk: ChunksReader::new_chunk(0),
+        chunk_id: 1,
+        read_id: 0,
+    })
+}
+
+/// Build an iterator for converting a paired fastx input into a sequence of [Chunk] values
+///
+/// The second mates are read from `mates` if provided, else `reader` is an interleaved input.
+/// The two mates of a pair are consecutive records of the same chunk (the `read_id` of the second
+/// mate is the `read_id` of the first mate + 1).
+pub fn from_paired_fastx_readers<X, O>(
+    reader: Box<dyn FastxReader>,
+    mates: Option<Box<dyn FastxReader>>,
+    alphabet: Alphabet,
+) -> anyhow::Result<ChunksReader<X, O>>
+where
+    X: Send,
+    O: Send,
 {
     Ok(ChunksReader{
         reader,
+        mates,
+        paired: true,
+        alphabet,
         chunk: ChunksReader::new_chunk(0),
         chunk_id: 1,
         read_id: 0,
@@ -243,11 +518,35 @@ pub fn for_each<I, X, O, F>(iter: I, func: &mut F)
 
 /// Re-assemble and re-order a sequence of [Chunk] values and call a faillible closure on each
 /// record, stop at the first error
-pub fn try_for_each_reordered<I, X, O, F, E>(mut iter: I, func: &mut F) -> Result<(), E>
-    where I: Iterator<Item=Chunk<X, O>>,
-          X: Send,
-          O: Send + OptionalId,
-          F: FnMut(Record<'_, X>) -> Result<(), E>
+pub fn try_for_each_reordered<I, X, O, F, E>(iter: I, func: &mut F) -> Result<(), E>
+where
+    I: Iterator<Item = Chunk<X, O>>,
+    X: Send,
+    O: Send + OptionalId,
+    F: FnMut(Record<'_, X>) -> Result<(), E>,
+{
+    try_for_each_chunk_reordered(iter, &mut |chunk| chunk.try_for_each(func))
+}
+
+/// Re-assemble and re-order a sequence of [Chunk] values and call a faillible closure on each
+/// pair of records, stop at the first error
+pub fn try_for_each_pair_reordered<I, X, O, F, E>(iter: I, func: &mut F) -> Result<(), E>
+where
+    I: Iterator<Item = Chunk<X, O>>,
+    X: Send,
+    O: Send + OptionalId,
+    F: FnMut(Record<'_, X>, Record<'_, X>) -> Result<(), E>,
+{
+    try_for_each_chunk_reordered(iter, &mut |chunk| chunk.try_for_each_pair(func))
+}
+
+/// Re-order a sequence of [Chunk] values and call a faillible closure on each chunk
+fn try_for_each_chunk_reordered<I, X, O, F, E>(mut iter: I, func: &mut F) -> Result<(), E>
+where
+    I: Iterator<Item = Chunk<X, O>>,
+    X: Send,
+    O: Send + OptionalId,
+    F: FnMut(&mut Chunk<X, O>) -> Result<(), E>,
 {
     let mut buffer = HashMap::new();
 
@@ -266,7 +565,7 @@ pub fn try_for_each_reordered<I, X, O, F, E>(mut iter: I, func: &mut F) -> Resul
                 }
             }
         };
-        chunk.try_for_each(func)?;
+        func(&mut chunk)?;
     }
     Ok(())
 }
@@ -307,12 +606,15 @@ pub fn NO_WRITER<X>(_: Record<'_, X>) -> anyhow::Result<()> {
 pub struct Pipeline<X=(), O=WithId> (PhantomData<(X, O)>);
 
 impl<X, O> Pipeline<X, O>
-where X: Send + Default,
-      O: Send + OptionalId
+where
+    X: Send + Default,
+    O: Send + OptionalId,
 {
     /// Process a series of [needletail] fastx records over a [rayon]-parallelised map-reduce pipeline
     ///
     /// - `reader` is the [needletail::FastxReader] object providing the input sequences
+    /// - `alphabet` is the alphabet of the sequences, used to normalize them (see
+    ///   [Alphabet::normalize()])
     /// - `map` is a closure that will be called on each record
     ///   - it may mutate [Record::seq] (but not change its size) and [Record::extra]
     ///   - it may return a value to be processed by the `reduce` argument
@@ -328,54 +630,157 @@ where X: Send + Default,
     ///
     /// - if the [Record::id] is not needed then the `O` type parameter may be set to [WithoutId]
     ///   to skip it when building the chunks and speed-up the process
+    /// - if the [Record::qual] is needed then the `O` type parameter must be set to [WithQual]
+    ///   (or [WithOriginal] if the [Record::raw_seq] is needed as well)
+    ///
+    pub fn run<M, W, ID, OP, R>(
+        reader: Box<dyn FastxReader>,
+        alphabet: Alphabet,
+        map: M,
+        reduce: (ID, OP),
+        mut writer: W,
+    ) -> anyhow::Result<R>
+    where
+        M: Fn(Record<'_, X>) -> R + Sync,
+        W: FnMut(Record<'_, X>) -> anyhow::Result<()>,
+        ID: Fn() -> R + Sync + Send,
+        OP: Fn(R, R) -> R + Sync + Send,
+        R: Send + Copy,
+    {
+        let (reduce_id, reduce_op) = (&reduce.0, &reduce.1);
+        Self::run_chunks(
+            from_fastx_reader(reader, alphabet)?,
+            |chunk| {
+                let mut result = reduce_id();
+                chunk.for_each(&mut |record| {
+                    result = reduce_op(result, map(record));
+                });
+                result
+            },
+            (reduce_id, reduce_op),
+            |chunks| try_for_each_reordered(chunks, &mut writer),
+        )
+    }
+
+    /// Process a series of paired [needletail] fastx records over a [rayon]-parallelised
+    /// map-reduce pipeline
+    ///
+    /// Same as [Self::run()], except that the `map` and `writer` closures are called on each pair
+    /// of records. The second mates are read from `mates` in lockstep with `reader` if provided,
+    /// else `reader` is an interleaved input.
+    pub fn run_pairs<M, W, ID, OP, R>(
+        reader: Box<dyn FastxReader>,
+        mates: Option<Box<dyn FastxReader>>,
+        alphabet: Alphabet,
+        map: M,
+        reduce: (ID, OP),
+        mut writer: W,
+    ) -> anyhow::Result<R>
+    where
+        M: Fn(Record<'_, X>, Record<'_, X>) -> R + Sync,
+        W: FnMut(Record<'_, X>, Record<'_, X>) -> anyhow::Result<()>,
+        ID: Fn() -> R + Sync + Send,
+        OP: Fn(R, R) -> R + Sync + Send,
+        R: Send + Copy,
+    {
+        let (reduce_id, reduce_op) = (&reduce.0, &reduce.1);
+        Self::run_chunks(
+            from_paired_fastx_readers(reader, mates, alphabet)?,
+            |chunk| {
+                let mut result = reduce_id();
+                chunk.for_each_pair(&mut |first, second| {
+                    result = reduce_op(result, map(first, second));
+                });
+                result
+            },
+            (reduce_id, reduce_op),
+            |chunks| try_for_each_pair_reordered(chunks, &mut writer),
+        )
+    }
+
+    /// Process a series of [needletail] fastx records over a [rayon]-parallelised map-reduce
+    /// pipeline, chunk by chunk
     ///
-    pub fn run<M, W,  ID, OP, R>(
-        reader: Box<dyn FastxReader>, map: M, reduce: (ID, OP), mut writer: W) -> anyhow::Result<R>
-
-    where M: Fn(Record<'_, X>) -> R + Sync,
-          W: FnMut(Record<'_, X>) -> anyhow::Result<()>,
-          ID: Fn() -> R + Sync + Send,
-          OP: Fn(R, R) -> R + Sync + Send,
-          R: Send + Copy,
-      {
-          let (input_tx, input_rx) = sync_channel::<Chunk<X, O>>(INPUT_CHANNEL_SIZE);
-          let (output_tx, output_rx) = sync_channel::<Chunk<X, O>>(OUTPUT_CHANNEL_SIZE);
-
-          let (reduce_id, reduce_op) = (&reduce.0, &reduce.1);
-
-          std::thread::scope(|s| -> anyhow::Result<R> {
-
-              let reader_thread = s.spawn(move || -> anyhow::Result<()> {
-                  for chunk in from_fastx_reader(reader)? {
-                      if input_tx.send(chunk?).is_err() {
-                          // abort on send error but do not report any error
-                          // (at this point the receiver thread would yield a more meaningful error)
-                          break;
-                      }
-                  }
-                  Ok(())
-              });
-
-              let map_reduce_thread = s.spawn(|| -> anyhow::Result<R> {
-                  let output_tx = output_tx;
-
-                  input_rx.into_iter().par_bridge().map(|mut chunk| {
-                      let mut result = reduce_id();
-                      chunk.for_each(&mut |record| {
-                          result = reduce_op(result, map(record));
-                      });
-                      output_tx.send(chunk).map_err(|_| SendError(()))?;
-                      Ok(result)
-                  })
-                  .try_reduce(reduce_id, |a, b| Ok(reduce_op(a, b)))
-              });
-
-              try_for_each_reordered(output_rx.into_iter(), &mut writer)?;
-
-              reader_thread.join().expect("panic in the reader thread")?;
-              map_reduce_thread.join().expect("panic in the map reduce thread")
-          })
-      }
+    /// Same as [Self::run()] without `writer`, except that the `map` closure is called on each
+    /// [Chunk] (eg. to group the updates of a shared structure), in no particular order
+    pub fn run_chunked<M, ID, OP, R>(
+        reader: Box<dyn FastxReader>,
+        alphabet: Alphabet,
+        map: M,
+        reduce: (ID, OP),
+    ) -> anyhow::Result<R>
+    where
+        M: Fn(&mut Chunk<X, O>) -> R + Sync,
+        ID: Fn() -> R + Sync + Send,
+        OP: Fn(R, R) -> R + Sync + Send,
+        R: Send + Copy,
+    {
+        Self::run_chunks(
+            from_fastx_reader(reader, alphabet)?,
+            map,
+            reduce,
+            |chunks| {
+                chunks.for_each(drop);
+                Ok(())
+            },
+        )
+    }
+
+    /// Run the pipeline threads, `map` being called on each chunk and `writer` on the sequence
+    /// of processed chunks (not ordered)
+    fn run_chunks<I, M, W, ID, OP, R>(
+        chunks: I,
+        map: M,
+        reduce: (ID, OP),
+        writer: W,
+    ) -> anyhow::Result<R>
+    where
+        I: Iterator<Item = anyhow::Result<Chunk<X, O>>> + Send,
+        M: Fn(&mut Chunk<X, O>) -> R + Sync,
+        W: FnOnce(std::sync::mpsc::IntoIter<Chunk<X, O>>) -> anyhow::Result<()>,
+        ID: Fn() -> R + Sync + Send,
+        OP: Fn(R, R) -> R + Sync + Send,
+        R: Send + Copy,
+    {
+        let (input_tx, input_rx) = sync_channel::<Chunk<X, O>>(INPUT_CHANNEL_SIZE);
+        let (output_tx, output_rx) = sync_channel::<Chunk<X, O>>(OUTPUT_CHANNEL_SIZE);
+
+        let (reduce_id, reduce_op) = (&reduce.0, &reduce.1);
+
+        std::thread::scope(|s| -> anyhow::Result<R> {
+            let reader_thread = s.spawn(move || -> anyhow::Result<()> {
+                for chunk in chunks {
+                    if input_tx.send(chunk?).is_err() {
+                        // abort on send error but do not report any error
+                        // (at this point the receiver thread would yield a more meaningful error)
+                        break;
+                    }
+                }
+                Ok(())
+            });
+
+            let map_reduce_thread = s.spawn(|| -> anyhow::Result<R> {
+                let output_tx = output_tx;
+
+                input_rx
+                    .into_iter()
+                    .par_bridge()
+                    .map(|mut chunk| {
+                        let result = map(&mut chunk);
+                        output_tx.send(chunk).map_err(|_| SendError(()))?;
+                        Ok(result)
+                    })
+                    .try_reduce(reduce_id, |a, b| Ok(reduce_op(a, b)))
+            });
+
+            writer(output_rx.into_iter())?;
+
+            reader_thread.join().expect("panic in the reader thread")?;
+            map_reduce_thread
+                .join()
+                .expect("panic in the map reduce thread")
+        })
+    }
 }
 
 #[cfg(test)]
@@ -386,8 +791,7 @@ mod tests {
     use super::*;
 
     #[test]
-    fn pipeline() -> anyhow::Result<()>
-    {
+    fn pipeline() -> anyhow::Result<()> {
         let seq_len = 150;
 
         let mut rng = biotest::rand();
@@ -420,6 +824,7 @@ mod tests {
                         let snd = snd;
                         Pipeline::<Option<usize>>::run(
                             make_reader(),
+                            Alphabet::Dna,
                             // map
                             |rec| {
                                 // make one thread much slower to verify that the chunks are
@@ -491,4 +896,221 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn pipeline_with_qual() -> anyhow::Result<()> {
+        let fastq = b"@r1 desc\nACGTN\n+\nII#I5\n@r2\nacgt\n+\n5555\n".to_vec();
+        let mut records = Vec::new();
+
+        Pipeline::<(), WithQual>::run(
+            needletail::parse_fastx_reader(Cursor::new(fastq.clone()))?,
+            Alphabet::Dna,
+            |rec| {
+                if let Some(qual) = rec.qual {
+                    qual.reverse()
+                }
+            },
+            NO_REDUCE,
+            |rec| {
+                records.push((
+                    rec.id.to_vec(),
+                    rec.seq.to_vec(),
+                    rec.qual.map(|q| q.to_vec()),
+                ));
+                Ok(())
+            },
+        )?;
+        assert_eq!(
+            records,
+            vec![
+                (
+                    b"r1 desc".to_vec(),
+                    b"ACGTN".to_vec(),
+                    Some(b"5I#II".to_vec())
+                ),
+                (b"r2".to_vec(), b"ACGT".to_vec(), Some(b"5555".to_vec())),
+            ]
+        );
+
+        // the original sequence is stored after the quality line
+        let mut raw_seqs = Vec::new();
+        Pipeline::<(), WithOriginal>::run(
+            needletail::parse_fastx_reader(Cursor::new(fastq.clone()))?,
+            Alphabet::Dna,
+            |rec| assert!(rec.qual.is_some()),
+            NO_REDUCE,
+            |rec| {
+                raw_seqs.push((rec.seq.to_vec(), rec.raw_seq.map(|s| s.to_vec())));
+                Ok(())
+            },
+        )?;
+        assert_eq!(
+            raw_seqs,
+            vec![
+                (b"ACGTN".to_vec(), Some(b"ACGTN".to_vec())),
+                (b"ACGT".to_vec(), Some(b"acgt".to_vec())),
+            ]
+        );
+
+        // the quality line is not stored without the WithQual marker
+        Pipeline::<()>::run(
+            needletail::parse_fastx_reader(Cursor::new(fastq))?,
+            Alphabet::Dna,
+            |rec| assert!(rec.qual.is_none()),
+            NO_REDUCE,
+            NO_WRITER,
+        )?;
+
+        // the amino acids are only converted to uppercase
+        let mut seqs = Vec::new();
+        Pipeline::<()>::run(
+            needletail::parse_fastx_reader(Cursor::new(b">p1\nmkVW*x\nEQ\n".to_vec()))?,
+            Alphabet::Protein,
+            |_| (),
+            NO_REDUCE,
+            |rec| {
+                seqs.push(rec.seq.to_vec());
+                Ok(())
+            },
+        )?;
+        assert_eq!(seqs, vec![b"MKVW*XEQ".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn mate_names() {
+        assert_eq!(mate_name(b"r1/1"), b"r1");
+        assert_eq!(mate_name(b"r1/2 1:N:0:ACGT"), b"r1");
+        assert_eq!(mate_name(b"SRR1.7 7 length=150"), b"SRR1.7");
+        assert_eq!(mate_name(b"r1/3"), b"r1/3");
+    }
+    #[test]
+    fn pipeline_pairs() -> anyhow::Result<()> {
+        let reader =
+            |data: &[u8]| needletail::parse_fastx_reader(Cursor::new(data.to_vec())).unwrap();
+        let to_str = |v: &[u8]| std::str::from_utf8(v).unwrap().to_owned();
+
+        // many pairs, so that they span several chunks
+        let mut rng = biotest::rand();
+        let generator = biotest::Fasta::builder().sequence_len(150).build()?;
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        generator.records(&mut first, &mut rng, 3000)?;
+        generator.records(&mut second, &mut rng, 3000)?;
+        // the mates have the same names
+        let names = first
+            .split(|c| *c == b'\n')
+            .filter(|line| line.starts_with(b">"));
+        let mut lines = second
+            .split(|c| *c == b'\n')
+            .filter(|line| !line.starts_with(b">"));
+        let second = names
+            .zip(&mut lines)
+            .flat_map(|(name, seq)| [name, b"\n", seq, b"\n"].concat())
+            .collect::<Vec<u8>>();
+
+        let expected = |data: &[u8]| -> Vec<String> {
+            let mut reader = reader(data);
+            let mut seqs = Vec::new();
+            while let Some(rec) = reader.next() {
+                seqs.push(to_str(&rec.unwrap().normalize(false)));
+            }
+            seqs
+        };
+
+        let mut pairs = Vec::new();
+        let count = Pipeline::<usize, WithId>::run_pairs(
+            reader(&first),
+            Some(reader(&second)),
+            Alphabet::Dna,
+            |r1, r2| {
+                assert_eq!(r1.read_id + 1, r2.read_id);
+                *r1.extra = r2.seq.len();
+                1
+            },
+            (|| 0, |a, b| a + b),
+            |r1, r2| {
+                assert_eq!(*r1.extra, r2.seq.len());
+                pairs.push((to_str(r1.seq), to_str(r2.seq)));
+                Ok(())
+            },
+        )?;
+        assert_eq!(count, 3000);
+        assert_eq!(
+            pairs,
+            expected(&first)
+                .into_iter()
+                .zip(expected(&second))
+                .collect::<Vec<_>>()
+        );
+
+        // interleaved input
+        let interleaved = b">r1/1\nACGT\n>r1/2\nTTGCA\n>r2/1\nGG\n>r2/2\nC\n";
+        let mut pairs = Vec::new();
+        Pipeline::<(), WithId>::run_pairs(
+            reader(interleaved),
+            None,
+            Alphabet::Dna,
+            |_, _| (),
+            NO_REDUCE,
+            |r1, r2| {
+                pairs.push((to_str(r1.id), to_str(r1.seq), to_str(r2.id), to_str(r2.seq)));
+                Ok(())
+            },
+        )?;
+        assert_eq!(
+            pairs,
+            vec![
+                ("r1/1".into(), "ACGT".into(), "r1/2".into(), "TTGCA".into()),
+                ("r2/1".into(), "GG".into(), "r2/2".into(), "C".into()),
+            ]
+        );
+
+        // inconsistent inputs
+        assert!(Pipeline::<()>::run_pairs(
+            reader(b">a\nA\n>a\nC\n>c\nG\n"),
+            None,
+            Alphabet::Dna,
+            |_, _| (),
+            NO_REDUCE,
+            |_, _| Ok(())
+        )
+        .is_err());
+        assert!(Pipeline::<()>::run_pairs(
+            reader(b">a\nA\n>b\nC\n"),
+            Some(reader(b">a\nA\n")),
+            Alphabet::Dna,
+            |_, _| (),
+            NO_REDUCE,
+            |_, _| Ok(())
+        )
+        .is_err());
+        assert!(Pipeline::<()>::run_pairs(
+            reader(b">a\nA\n"),
+            Some(reader(b">a\nA\n>b\nC\n")),
+            Alphabet::Dna,
+            |_, _| (),
+            NO_REDUCE,
+            |_, _| Ok(())
+        )
+        .is_err());
+        assert!(Pipeline::<()>::run_pairs(
+            reader(b">a/1\nA\n>b/2\nC\n"),
+            None,
+            Alphabet::Dna,
+            |_, _| (),
+            NO_REDUCE,
+            |_, _| Ok(())
+        )
+        .is_err());
+        assert!(Pipeline::<()>::run_pairs(
+            reader(b">a/1 x\nA\n>b/1\nC\n"),
+            Some(reader(b">a/2 y\nA\n>c/2\nC\n")),
+            Alphabet::Dna,
+            |_, _| (),
+            NO_REDUCE,
+            |_, _| Ok(())
+        )
+        .is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,176 @@
+//! Classification of the sequences to the reference sharing the most kmers with them
+//!
+//! Each sequence (or pair of sequences) is assigned to the target of the colored index (see
+//! [KmerColors]) it shares the most kmers with: a reference record if the origin of the kmers is
+//! tracked, else a label. The classification is written as a TSV file (see `--out-classification`)
+//! with one line per sequence, the unclassified sequences included:
+//!
+//! `read_id  reference  score  runner_up  runner_up_score  ambiguous`
+//!
+//! where the score is the number of kmers shared with the reference, and the sequence is ambiguous
+//! if the runner-up shares as many kmers as the reference (the reference is then the first of them
+//! in the order of the index).
+
+/* std use */
+use std::io::Write;
+
+/* crates use */
+
+/* project use */
+use crate::colors::KmerColors;
+use crate::matched_sequences::MatchedSequence;
+
+/// header of the classification file
+pub const HEADER: &str = "read_id\treference\tscore\trunner_up\trunner_up_score\tambiguous\n";
+
+/// Best reference of a sequence, see [classify()]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Classification {
+    /// reference sharing the most kmers with the sequence, if any, with the number of shared kmers
+    pub reference: Option<(u32, usize)>,
+    /// reference sharing the most kmers after it, if any, with the number of shared kmers
+    pub runner_up: Option<(u32, usize)>,
+}
+
+impl Classification {
+    /// true if the runner-up shares as many kmers as the reference
+    pub fn is_ambiguous(&self) -> bool {
+        matches!((self.reference, self.runner_up), (Some((_, best)), Some((_, second))) if best == second)
+    }
+
+    /// write the classification of a sequence as a line of the classification file
+    pub fn write(
+        &self,
+        output: &mut impl Write,
+        read_id: &[u8],
+        names: &[String],
+    ) -> std::io::Result<()> {
+        // the read is identified by the first word of its header
+        let read_id = read_id
+            .split(|c| c.is_ascii_whitespace())
+            .next()
+            .unwrap_or_default();
+        output.write_all(read_id)?;
+        match self.reference {
+            Some((reference, score)) => {
+                write!(output, "\t{}\t{}", names[reference as usize], score)?
+            }
+            None => write!(output, "\tunclassified\t0")?,
+        }
+        match self.runner_up {
+            Some((runner_up, score)) => {
+                write!(output, "\t{}\t{}", names[runner_up as usize], score)?
+            }
+            None => write!(output, "\t*\t0")?,
+        }
+        writeln!(output, "\t{}", u8::from(self.is_ambiguous()))
+    }
+}
+
+/// classify some sequences (eg. the two mates of a pair) as a whole, according to their numbers
+/// of kmers shared with each target of `colors`
+pub fn classify<D: MatchedSequence>(
+    colors: &KmerColors,
+    matched_sequences: &[&D],
+) -> Classification {
+    let mut counts = vec![0; target_names(colors).len()];
+    for matched_sequence in matched_sequences {
+        if colors.tracks_origins() {
+            for (record, count) in matched_sequence.record_counts() {
+                counts[*record as usize] += count;
+            }
+        } else {
+            for (label, count) in counts.iter_mut().enumerate() {
+                *count += matched_sequence.label_count(label);
+            }
+        }
+    }
+    best_targets(&counts)
+}
+
+/// names of the targets the sequences are classified to: the reference records if the origin of
+/// the kmers is tracked, else the labels
+pub fn target_names(colors: &KmerColors) -> &[String] {
+    if colors.tracks_origins() {
+        &colors.records
+    } else {
+        &colors.labels
+    }
+}
+
+/// the two targets with the most shared kmers (the first ones in case of tie), if they share any
+fn best_targets(counts: &[usize]) -> Classification {
+    let mut classification = Classification::default();
+    for (target, &count) in counts.iter().enumerate().filter(|(_, count)| **count > 0) {
+        let candidate = Some((target as u32, count));
+        match classification.reference {
+            Some((_, best)) if count <= best => {
+                if classification
+                    .runner_up
+                    .is_none_or(|(_, second)| count > second)
+                {
+                    classification.runner_up = candidate;
+                }
+            }
+            _ => {
+                classification.runner_up = classification.reference;
+                classification.reference = candidate;
+            }
+        }
+    }
+    classification
+}
+
+#[cfg(test)]
+mod tests {
+    /* project use */
+    use super::*;
+    use crate::matched_sequences::MachedCount;
+
+    #[test]
+    fn classification() -> std::io::Result<()> {
+        let mut colors = KmerColors::new(vec!["genes".to_string()]);
+        for gene in ["gene1", "gene2", "gene3"] {
+            colors.add_record(gene.to_string(), 0);
+        }
+        let names = target_names(&colors);
+        assert_eq!(names, ["gene1", "gene2", "gene3"]);
+
+        let mut first = MachedCount::new(10);
+        first.add_records(&[0, 2]);
+        first.add_records(&[2]);
+        let mut second = MachedCount::new(10);
+        second.add_records(&[0]);
+
+        let mut output = Vec::new();
+        let classification = classify(&colors, &[&first]);
+        assert_eq!(classification.reference, Some((2, 2)));
+        assert!(!classification.is_ambiguous());
+        classification.write(&mut output, b"read1 first mate", names)?;
+        // the mates of a pair are classified as a whole
+        let classification = classify(&colors, &[&first, &second]);
+        assert!(classification.is_ambiguous());
+        classification.write(&mut output, b"read2", names)?;
+        classify(&colors, &[&MachedCount::new(10)]).write(&mut output, b"read3", names)?;
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "read1\tgene3\t2\tgene1\t1\t0\nread2\tgene1\t2\tgene3\t2\t1\nread3\tunclassified\t0\t*\t0\t0\n"
+        );
+
+        assert_eq!(
+            best_targets(&[1, 3, 0, 2, 3]),
+            Classification {
+                reference: Some((1, 3)),
+                runner_up: Some((4, 3))
+            }
+        );
+        assert_eq!(
+            best_targets(&[0, 4]),
+            Classification {
+                reference: Some((1, 4)),
+                runner_up: None
+            }
+        );
+        Ok(())
+    }
+}
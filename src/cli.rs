@@ -6,6 +6,10 @@
 use clap::Parser;
 
 /* project use */
+use crate::alphabet::Alphabet;
+use crate::kmer::{Kmer as _, LongKmer};
+use crate::kmer_index::IndexBackend;
+use crate::low_complexity::ComplexityMetric;
 
 /// Extract sequences that contain some kmers
 #[derive(Parser, Debug)]
@@ -17,14 +21,64 @@ pub struct Args {
     ///     even if it occurs multiple times in the file.
     ///     If the stranded option is not used (default), a kmer
     ///     and its reverse complement are considered as the same kmer.
+    ///     Required, unless `--in-index` is used
+    ///     The file may also be a kmer dump, with one kmer per line optionally followed by its
+    ///     count (`kmer<TAB>count`, as output by `jellyfish dump -c`, `kmc_dump` or `kmtricks`):
+    ///     its kmers must have the size `--kmer-size`, and their counts are appended to the lines
+    ///     of `--out-kmers` (after the number of occurrences in the sequences)
+    ///     It may also be a KMC database (`NAME`, `NAME.kmc_pre` or `NAME.kmc_suf`), with kmers of
+    ///     size `--kmer-size`, counted on both strands unless `--stranded` is used
+    ///     Several labelled kmer sets may be queried at once by repeating this option,
+    ///     each value being `[LABEL=]FILE` (the default label is the file name without extensions,
+    ///     and a prefix containing a path separator is not a label: `runs/k=31/host.fa` is a file).
+    ///     The number and ratio of shared kmers of each label are then appended to the
+    ///     output sequence headers (as `LABEL:count:ratio`)
     #[arg(long, verbatim_doc_comment)]
-    pub in_kmers: String,
+    pub in_kmers: Vec<String>,
+
+    /// Input binary index file built by a previous run with `--out-index`.
+    ///     Replaces `--in-kmers`: the kmers are loaded without being parsed and indexed again.
+    ///     Note: `--kmer-size` and `--stranded` must be the same as when the index was built
+    #[arg(long, default_value_t = String::from(""), verbatim_doc_comment)]
+    pub in_index: String,
+
+    /// Output binary index file storing the indexed kmers,
+    /// so that they can be reused by other runs with `--in-index`.
+    /// If no other output is provided, stop once the index is written
+    #[arg(long, default_value_t = String::from(""), verbatim_doc_comment)]
+    pub out_index: String,
+
+    /// Output JSON file of the statistics of the indexing of the `--in-kmers` files: the number of
+    /// kmers indexed, skipped (non ACGT letters, low complexity, ...) and duplicated, and the number
+    /// of kmers indexed from each record. A summary is always printed
+    #[arg(long, default_value_t = String::from(""))]
+    pub index_stats: String,
+
+    /// Backend of the index of the kmers
+    ///     exact: hash map of the kmers, with the number of occurrences of each kmer
+    ///     bloom: Bloom filter of the kmers (about 10 bits per kmer for a false positive rate of
+    ///     1%), for kmer sets too large to fit in memory. The sequences sharing only a few kmers
+    ///     may be output wrongly (see `--false-positive-rate`), and the occurrences of the kmers
+    ///     are not counted: `--out-kmers` is unavailable. The `--in-kmers` fasta and fastq files
+    ///     are read twice: once to count their kmers and size the filter, once to index them.
+    ///     Not compatible with `--in-index`, `--out-index`, several `--in-kmers` sets,
+    ///     `--track-origins`, `--max-mismatches` and `--expand-iupac`
+    #[arg(long, value_enum, default_value_t = IndexBackend::Exact, verbatim_doc_comment)]
+    pub index_backend: IndexBackend,
+
+    /// False positive rate of the Bloom filter of `--index-backend bloom`: probability that a kmer
+    /// of the sequences is matched while it is not indexed [default: 0.01]
+    #[arg(long)]
+    pub false_positive_rate: Option<f64>,
 
     /// Input fasta or fastq [.gz|zst] file containing the original sequences (eg. reads).
     ///     The stdin is used if not provided
     ///     (and if `--in_filelist` is not provided neither)
-    #[arg(long, default_value_t = String::from(""), verbatim_doc_comment)]
-    pub in_sequences: String,
+    ///     Paired reads may be given as two files (`--in-sequences R1 R2`): each pair is then
+    ///     kept or dropped as a whole (see `--pair-mode`). The two mates of a pair must have the
+    ///     same name (the first word of their header, without a `/1` or `/2` suffix)
+    #[arg(long, num_args = 1..=2, verbatim_doc_comment)]
+    pub in_sequences: Vec<String>,
 
     /// Input txt file containing in each line a path to a fasta or fastq [.gz|zst] file
     /// containing the original sequences (eg. reads).
@@ -36,11 +90,46 @@ pub struct Args {
 
     /// Output file containing the filtered original sequences (eg. reads).
     /// It will be automatically in fasta or fastq format depending on the input file.
+    /// It is compressed according to its extension (.gz, .bgz or .zst).
     /// If not provided, only the in_kmers with their count is output
+    ///     With paired reads, two files may be given (`--out-sequences R1 R2`) to write the
+    ///     first and second mates separately, else the pairs are interleaved in a single file
+    #[arg(long, num_args = 1..=2, verbatim_doc_comment)]
+    pub out_sequences: Vec<String>,
+
+    /// Output file containing the sequences that are not in `--out-sequences` (ie. whose ratio
+    /// of indexed kmers is not in the thresholds), with the same metrics in their header.
+    /// It is filled during the same pass as `--out-sequences`.
+    ///     With paired reads, the rejected pairs are written to two files if two are given,
+    ///     else they are interleaved
+    #[arg(long, num_args = 1..=2, verbatim_doc_comment)]
+    pub out_unmatched: Vec<String>,
+
+    /// Output TSV file classifying each sequence (or pair) to the reference sharing the most
+    /// kmers with it: a record of the `--in-kmers` files with `--track-origins`, else a labelled
+    /// kmer set. It is filled during the same pass as `--out-sequences`.
+    ///     Columns: read id, reference (or `unclassified`), number of kmers shared with it,
+    ///     runner-up reference (or `*`), number of kmers shared with it, and 1 if the sequence is
+    ///     ambiguous (the runner-up shares as many kmers as the reference)
+    ///     With `--in-filelist`, txt file containing in each line the path to the classification
+    ///     file of the input file of the same line (filled during the pass of `--out-filelist`)
     #[arg(long, default_value_t = String::from(""), verbatim_doc_comment)]
-    pub out_sequences: String,
+    pub out_classification: String,
 
-    /// Output txt file containing in each line a path to a fasta or fastq [.gz] file
+    /// Output TSV file estimating the relative abundance of each record of the `--in-kmers` files
+    /// (requires `--track-origins`). Each sequence (or pair) is assigned to the records sharing the
+    /// most kmers with it, the ambiguous sequences are redistributed among their records by an
+    /// EM, and the numbers of sequences are normalized by the numbers of kmers of the records.
+    /// It is filled during the same pass as `--out-sequences` (or `--out-filelist`).
+    ///     Columns: record, number of kmers of the record, number of sequences assigned only to
+    ///     it, estimated number of sequences, and relative abundance.
+    ///     With `--in-filelist`, txt file containing in each line the path to the abundance
+    ///     table of the input file of the same line (the abundances of each input file are
+    ///     estimated independently)
+    #[arg(long, default_value_t = String::from(""), verbatim_doc_comment)]
+    pub out_abundances: String,
+
+    /// Output txt file containing in each line a path to a fasta or fastq [.gz|bgz|zst] file
     /// that will contain the related output file from the input files list
     #[arg(long, default_value_t = String::from(""), verbatim_doc_comment)]
     pub out_filelist: String,
@@ -52,6 +141,7 @@ pub struct Args {
     ///     * their occurrence positions if the --output_kmer_positions option is used
     ///          Note: if `--in_filelist` is used the output counted kmers are
     ///          those occurring the last input file of that list
+    /// It is compressed according to its extension (.gz, .bgz or .zst)
     #[arg(long, default_value_t = String::from(""), verbatim_doc_comment)]
     pub out_kmers: String,
 
@@ -74,8 +164,14 @@ pub struct Args {
     #[arg(long, default_value_t = false, verbatim_doc_comment)]
     pub output_mapping_positions: bool,
 
-    /// Size of the kmers to index and search
-    #[arg(short, long, default_value_t = 31)]
+    /// Size of the kmers to index and search, from 1 to 256 nucleotides (or 85 amino acids, see
+    /// `--alphabet`)
+    #[arg(
+        short,
+        long,
+        default_value_t = 31,
+        value_parser = clap::builder::RangedI64ValueParser::<usize>::new().range(1..=LongKmer::MAX_KMER_SIZE as i64)
+    )]
     pub kmer_size: usize,
 
     /// Output sequences are those whose ratio of indexed kmers is in ]min_threshold; max_threshold]
@@ -90,6 +186,41 @@ pub struct Args {
     #[arg(long, default_value_t = 100.0, verbatim_doc_comment)]
     pub max_threshold: f32,
 
+    /// Minimal number of indexed kmers that must be found in a sequence to keep it.
+    ///     Combined with the ratio thresholds, eg. for long reads of various lengths
+    #[arg(long, default_value_t = 0, verbatim_doc_comment)]
+    pub min_count: usize,
+
+    /// Maximal number of indexed kmers that may be found in a sequence to keep it
+    /// (no limit by default)
+    #[arg(long, verbatim_doc_comment)]
+    pub max_count: Option<usize>,
+
+    /// Minimal number of positions of a sequence covered by indexed kmers to keep it
+    #[arg(long, default_value_t = 0, verbatim_doc_comment)]
+    pub min_covered_bases: usize,
+
+    /// Additional threshold on the ratio of kmers of a label, given as `LABEL=MIN[:MAX]`
+    ///     Output sequences are also required to have a ratio (%) of kmers of this label in ]MIN; MAX]
+    ///     (MAX is 100 by default). May be repeated for several labels.
+    ///     Requires several labelled `--in-kmers` sets
+    #[arg(long, verbatim_doc_comment)]
+    pub label_threshold: Vec<String>,
+
+    /// Input fasta, fastq, kmer dump or KMC database of kmers to exclude (eg. host or
+    /// contaminant kmers), indexed as the `--in-kmers` files. May be repeated.
+    ///     Output sequences are also required to have a ratio (%) of excluded kmers of at most
+    ///     `--max-exclude-threshold`, the others are written in `--out-unmatched` if provided.
+    ///     The number and ratio of excluded kmers are appended to the output sequence headers
+    ///     (as `excluded:count:ratio`)
+    #[arg(long, verbatim_doc_comment)]
+    pub exclude_kmers: Vec<String>,
+
+    /// Maximal ratio (%) of excluded kmers (see `--exclude-kmers`) that may be found in a
+    /// sequence to keep it (default 0%: a sequence sharing any excluded kmer is not output)
+    #[arg(long, default_value_t = 0.0, verbatim_doc_comment)]
+    pub max_exclude_threshold: f32,
+
     /// Used original kmer strand (else canonical kmers are considered)
     #[arg(long, default_value_t = false)]
     pub stranded: bool,
@@ -98,14 +229,146 @@ pub struct Args {
     #[arg(long, default_value_t = false)]
     pub query_reverse: bool,
 
-    /// Do not index low complexity kmers (ie. with a Shannon entropy < 1.0)
+    /// Do not index low complexity kmers (by default with a Shannon entropy < 1.0, see
+    /// `--low-complexity-metric` and `--low-complexity-threshold`)
     #[arg(long, default_value_t = false)]
     pub no_low_complexity: bool,
 
+    /// Metric of the complexity of the kmers for `--no-low-complexity` and `--mask-queries`
+    ///     entropy: Shannon entropy of the letters, low if below the threshold (default 1.0)
+    ///     dust: DUST score of the trinucleotides, low if above the threshold (default 2.0).
+    ///     Nucleotide kmers only
+    ///     repeat: length of the longest homopolymer or dinucleotide run, low if at least the
+    ///     threshold (default 12)
+    #[arg(long, value_enum, default_value_t = ComplexityMetric::Entropy, verbatim_doc_comment)]
+    pub low_complexity_metric: ComplexityMetric,
+
+    /// Threshold of the low complexity metric (see `--low-complexity-metric`)
+    #[arg(long)]
+    pub low_complexity_threshold: Option<f32>,
+
+    /// Also skip the low complexity kmers of the queried sequences: they are not searched and do
+    /// not count in the ratios of shared kmers of the sequences
+    #[arg(long, default_value_t = false)]
+    pub mask_queries: bool,
+
+    /// Track the reference records (eg. genes or contigs) of the `--in-kmers` files each kmer
+    /// comes from.
+    ///     The records sharing the most kmers with an output sequence are appended to its header
+    ///     (as `origins:RECORD:count[,RECORD:count]`), and the records of each kmer are appended
+    ///     to the lines of the `--out-kmers` file (comma separated).
+    ///     When building an index with `--out-index`, the records are stored in the index
+    #[arg(long, default_value_t = false, verbatim_doc_comment)]
+    pub track_origins: bool,
+
+    /// Also match the kmers of the sequences with up to this number of substitutions (1 or 2)
+    /// with an indexed kmer (eg. for noisy long reads, or close strains).
+    ///     The approximate matches are appended to the output sequence headers (as
+    ///     `approximate:count:ratio`), after the exact ones. They are counted as shared kmers by
+    ///     the thresholds
+    #[arg(
+        long,
+        default_value_t = 0,
+        value_parser = clap::builder::RangedI64ValueParser::<usize>::new().range(0..=2),
+        verbatim_doc_comment
+    )]
+    pub max_mismatches: usize,
+
+    /// Spaced seed mask, of the size of the kmers (eg. `1101101101...`): only the nucleotides at the
+    /// positions of the `1` are indexed and searched, so that substitutions at the `0` positions do
+    /// not prevent a match. May be repeated, a kmer being then matched if it is matched with any mask.
+    ///     In the `--out-kmers` file, the positions ignored by the masks are written as `N`.
+    ///     Note: the same masks must be used when building and when loading an index
+    #[arg(long, verbatim_doc_comment)]
+    pub spaced_seed: Vec<String>,
+
+    /// Only index and search the minimizers of the kmers: the smallest kmer (in a hash order) of
+    /// each window of this number of consecutive kmers. Reduces the size of the index by about
+    /// (window + 1) / 2, while any stretch of window + kmer size - 1 shared nucleotides still
+    /// shares a kmer.
+    ///     The ratios of shared kmers are then relative to the number of sampled kmers of the sequences.
+    ///     Note: the same sampling must be used when building and when loading an index
+    #[arg(long, default_value_t = 0, verbatim_doc_comment)]
+    pub minimizers: usize,
+
+    /// Only index and search the open syncmers of the kmers: the kmers whose smallest s-mer of
+    /// this size is at the `--syncmer-offset` position (or at the symmetric position, unless
+    /// `--stranded`). Reduces the size of the index by about kmer size - s-mer size + 1.
+    ///     The ratios of shared kmers are then relative to the number of sampled kmers of the sequences.
+    ///     Note: the same sampling must be used when building and when loading an index
+    #[arg(long, default_value_t = 0, verbatim_doc_comment)]
+    pub syncmers: usize,
+
+    /// Position of the smallest s-mer in the open syncmers (see `--syncmers`)
+    #[arg(long, default_value_t = 0, verbatim_doc_comment)]
+    pub syncmer_offset: usize,
+
+    /// Alphabet of the kmers and of the sequences
+    ///     dna: nucleotides (ACGT)
+    ///     protein: amino acids, `--kmer-size` is then a number of amino acids (at most 85).
+    ///     Amino acid kmers have no strand: `--stranded` is implied
+    #[arg(long, value_enum, default_value_t = Alphabet::Dna, verbatim_doc_comment)]
+    pub alphabet: Alphabet,
+
+    /// Translate the input DNA sequences in their 6 frames and search the amino acid kmers in
+    /// them: the kmers and positions of a sequence are those of its frame sharing the most kmers.
+    ///     The output sequences are the original DNA sequences.
+    ///     Requires `--alphabet protein`
+    #[arg(long, default_value_t = false, verbatim_doc_comment)]
+    pub translate: bool,
+
+    /// Expand the kmers of `--in-kmers` containing IUPAC degenerate nucleotides (R, Y, N...)
+    /// into the ACGT kmers they stand for, if there are at most this number of them.
+    ///     By default (0) such kmers are not indexed.
+    ///     `--out-kmers` reports the degenerate kmers with the counts of the kmers they stand for
+    #[arg(long, default_value_t = 0, verbatim_doc_comment)]
+    pub expand_iupac: usize,
+
+    /// Skip the kmers of the counted kmer dumps and KMC databases of `--in-kmers` whose count is
+    /// smaller than this value
+    #[arg(long, default_value_t = 0, verbatim_doc_comment)]
+    pub min_abundance: u64,
+
+    /// Output the original sequences, as they are in the input file (lowercase letters,
+    /// IUPAC codes and line breaks are kept, and they are not reverse complemented by
+    /// `--query-reverse`).
+    ///     By default the output sequences are normalized (uppercase, non ACGT letters replaced by N).
+    ///     Note: the kmers are always searched in the normalized sequences
+    #[arg(long, default_value_t = false, verbatim_doc_comment)]
+    pub keep_original: bool,
+
+    /// The input sequences are paired reads, interleaved in a single file (or in each file of
+    /// `--in-filelist`): each pair is kept or dropped as a whole (see `--pair-mode`), its two mates
+    /// must have the same name
+    #[arg(long, default_value_t = false, verbatim_doc_comment)]
+    pub interleaved: bool,
+
+    /// How the thresholds are applied to paired reads
+    ///     combined: to the kmers of the two mates, considered as a single sequence
+    ///     both: to each mate, a pair is kept if both mates support the thresholds
+    ///     either: to each mate, a pair is kept if at least one mate supports the thresholds
+    #[arg(long, value_enum, default_value_t = PairMode::Combined, verbatim_doc_comment)]
+    pub pair_mode: PairMode,
+
     /// Number of threads
     ///    Note: if not provided, the number of threads is set to the number of logical cores
     #[arg(short, long, default_value_t = 0, verbatim_doc_comment)]
     pub threads: usize,
+
+    /// Report the progress of the indexing of the kmers, and the peak memory, on stderr
+    #[arg(long, default_value_t = false)]
+    pub progress: bool,
+}
+
+/// How the thresholds are applied to paired reads
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum PairMode {
+    /// thresholds on the ratio of kmers of the two mates
+    Combined,
+    /// each mate must support the thresholds
+    Both,
+    /// at least one mate must support the thresholds
+    Either,
 }
 
 /// check that a file name corresponds to a non empty file:
@@ -122,6 +385,46 @@ pub fn validate_non_empty_file(in_file: String) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// parse a `[LABEL=]FILE` input kmer set, the default label is the file name without extensions
+///
+/// The prefix before the first `=` is a label only if it contains no path separator, so that a
+/// path such as `runs/k=31/host.fa` is a file name
+pub fn parse_labelled_file(spec: &str) -> (String, String) {
+    match spec
+        .split_once('=')
+        .filter(|(label, _)| !label.contains(std::path::is_separator))
+    {
+        Some((label, file)) => (label.to_string(), file.to_string()),
+        None => {
+            let name = std::path::Path::new(spec)
+                .file_name()
+                .map_or(spec.to_string(), |name| name.to_string_lossy().to_string());
+            let label = name.split('.').next().unwrap_or_default().to_string();
+            (label, spec.to_string())
+        }
+    }
+}
+
+/// parse a `LABEL=MIN[:MAX]` label threshold
+pub fn parse_label_threshold(spec: &str) -> anyhow::Result<(String, f32, f32)> {
+    let Some((label, range)) = spec.split_once('=') else {
+        anyhow::bail!(
+            "invalid label threshold {:?}, expected LABEL=MIN[:MAX]",
+            spec
+        )
+    };
+    let (min, max) = range.split_once(':').unwrap_or((range, "100"));
+    let parse = |value: &str| -> anyhow::Result<f32> {
+        value.parse().map_err(|_| {
+            anyhow::anyhow!(
+                "invalid label threshold {:?}, expected LABEL=MIN[:MAX]",
+                spec
+            )
+        })
+    };
+    Ok((label.to_string(), parse(min)?, parse(max)?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -151,4 +454,36 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn labelled_file() {
+        assert_eq!(
+            parse_labelled_file("host=data/GRCh38.fa"),
+            ("host".to_string(), "data/GRCh38.fa".to_string())
+        );
+        assert_eq!(
+            parse_labelled_file("data/pathogens.fa.gz"),
+            ("pathogens".to_string(), "data/pathogens.fa.gz".to_string())
+        );
+        assert_eq!(
+            parse_labelled_file("runs/k=31/host.fa"),
+            ("host".to_string(), "runs/k=31/host.fa".to_string())
+        );
+    }
+
+    #[test]
+    fn label_threshold() -> anyhow::Result<()> {
+        assert_eq!(
+            parse_label_threshold("host=1.5")?,
+            ("host".to_string(), 1.5, 100.0)
+        );
+        assert_eq!(
+            parse_label_threshold("host=-1:0")?,
+            ("host".to_string(), -1.0, 0.0)
+        );
+        assert!(parse_label_threshold("host").is_err());
+        assert!(parse_label_threshold("host=a:2").is_err());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,284 @@
+//! Colored index: labels of the kmer sets (and reference records) each indexed kmer belongs to
+//!
+//! When several labelled kmer files are indexed, or when the origin of the kmers is tracked, each
+//! kmer is associated to a "color", the identifier of the set of targets it belongs to. A target
+//! is either a label (ie. a kmer file), or a reference record if the origin of the kmers is
+//! tracked. Colors are shared between kmers, so that each distinct set of targets is stored
+//! only once. The color of a kmer is stored with its counter in the kmer set (see [Colored]), so
+//! that the kmers are stored and looked up only once.
+
+/* std use */
+
+/* crates use */
+use ahash::AHashMap as HashMap;
+
+/* project use */
+use crate::kmer_counter::{KmerCounter, KmerMatch};
+
+/// identifier of a set of targets
+pub type ColorId = u32;
+
+/// Table of the distinct sets of targets
+#[derive(Default, Debug)]
+pub struct ColorTable {
+    /// sorted targets of each color
+    colors: Vec<Vec<u32>>,
+    /// color of each set of targets
+    ids: HashMap<Vec<u32>, ColorId>,
+    /// memoization of [ColorTable::add()]
+    transitions: HashMap<(ColorId, u32), ColorId>,
+}
+
+impl ColorTable {
+    /// get the color of a sorted set of targets, create it if needed
+    pub fn get_or_insert(&mut self, targets: &[u32]) -> ColorId {
+        if let Some(color) = self.ids.get(targets) {
+            return *color;
+        }
+        let color = self.colors.len() as ColorId;
+        self.colors.push(targets.to_vec());
+        self.ids.insert(targets.to_vec(), color);
+        color
+    }
+
+    /// get the color made of the targets of `color` plus `target`
+    pub fn add(&mut self, color: ColorId, target: u32) -> ColorId {
+        if let Some(new_color) = self.transitions.get(&(color, target)) {
+            return *new_color;
+        }
+        let mut targets = self.colors[color as usize].clone();
+        if let Err(pos) = targets.binary_search(&target) {
+            targets.insert(pos, target);
+        }
+        let new_color = self.get_or_insert(&targets);
+        self.transitions.insert((color, target), new_color);
+        new_color
+    }
+
+    /// sorted targets of a color
+    pub fn targets(&self, color: ColorId) -> &[u32] {
+        &self.colors[color as usize]
+    }
+
+    /// number of colors
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// true if there is no color
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+}
+
+/// Counter of the matches of a kmer of a colored index, with the color of the kmer
+#[derive(Default, Debug)]
+pub struct Colored<T> {
+    counter: T,
+    color: ColorId,
+}
+
+impl<T: KmerCounter> KmerCounter for Colored<T> {
+    fn add_match(&self, m: KmerMatch) {
+        self.counter.add_match(m);
+    }
+
+    fn to_string(&self) -> String {
+        self.counter.to_string()
+    }
+
+    fn get_count(&self) -> usize {
+        self.counter.get_count()
+    }
+
+    fn merged_to_string(counters: &[&Self]) -> String {
+        T::merged_to_string(
+            &counters
+                .iter()
+                .map(|colored| &colored.counter)
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn color(&self) -> Option<ColorId> {
+        Some(self.color)
+    }
+
+    fn set_color(&mut self, color: ColorId) {
+        self.color = color;
+    }
+}
+
+/// Labels (and reference records) of the indexed kmers
+///
+/// The color of each kmer is stored with its counter (see [Colored]).
+#[derive(Default, Debug)]
+pub struct KmerColors {
+    /// name of each label
+    pub labels: Vec<String>,
+    /// name of each reference record (empty if the origin of the kmers is not tracked)
+    pub records: Vec<String>,
+    /// label of each reference record
+    pub record_labels: Vec<u32>,
+    /// targets of each color
+    pub table: ColorTable,
+}
+
+impl KmerColors {
+    /// Create an empty set of colors for the given labels
+    pub fn new(labels: Vec<String>) -> Self {
+        Self {
+            labels,
+            records: Vec::new(),
+            record_labels: Vec::new(),
+            table: ColorTable::default(),
+        }
+    }
+
+    /// true if the targets are reference records, false if they are labels
+    pub fn tracks_origins(&self) -> bool {
+        !self.records.is_empty()
+    }
+
+    /// declare a new reference record, returns its target id
+    pub fn add_record(&mut self, name: String, label: u32) -> u32 {
+        self.records.push(name);
+        self.record_labels.push(label);
+        (self.records.len() - 1) as u32
+    }
+
+    /// add a target (label or reference record) to the color of a kmer (None for a kmer without
+    /// target yet), returns the new color of the kmer
+    pub fn add(&mut self, color: Option<ColorId>, target: u32) -> ColorId {
+        match color {
+            Some(color) => self.table.add(color, target),
+            None => self.table.get_or_insert(&[target]),
+        }
+    }
+
+    /// sorted targets of the kmer counted by `counter` (empty if the counter has no color)
+    pub fn get<C: KmerCounter>(&self, counter: &C) -> &[u32] {
+        counter
+            .color()
+            .map_or(&[], |color| self.table.targets(color))
+    }
+
+    /// sorted labels of a set of targets (without duplicates)
+    pub fn labels_of<'a>(&'a self, targets: &'a [u32]) -> impl Iterator<Item = u32> + 'a {
+        let mut previous = None;
+        targets
+            .iter()
+            .map(|target| match self.tracks_origins() {
+                true => self.record_labels[*target as usize],
+                false => *target,
+            })
+            .filter(move |label| previous.replace(*label) != Some(*label))
+    }
+
+    /// find a label by its name
+    pub fn label_id(&self, name: &str) -> Option<usize> {
+        self.labels.iter().position(|label| label == name)
+    }
+}
+
+/// Requirement on the ratio of kmers of a given label in the output sequences
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelThreshold {
+    /// label id
+    pub label: usize,
+    /// the ratio (%) must be > min
+    pub min: f32,
+    /// the ratio (%) must be <= max
+    pub max: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    /* crate use */
+    use atomic_counter::RelaxedCounter;
+
+    /* project use */
+    use super::*;
+
+    #[test]
+    fn color_table() {
+        let mut table = ColorTable::default();
+
+        let a = table.get_or_insert(&[0]);
+        let b = table.get_or_insert(&[2]);
+        let ab = table.add(a, 2);
+
+        assert_eq!(table.targets(ab), &[0, 2]);
+        assert_eq!(table.add(b, 0), ab);
+        assert_eq!(table.add(ab, 2), ab);
+        assert_eq!(table.get_or_insert(&[0, 2]), ab);
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn kmer_colors() {
+        let mut colors = KmerColors::new(vec!["host".to_string(), "pathogen".to_string()]);
+
+        let mut kmers = [None; 2];
+        for (kmer, target) in [(0, 0), (1, 1), (0, 1), (0, 1)] {
+            kmers[kmer] = Some(colors.add(kmers[kmer], target));
+        }
+        let mut counters = [Colored::<RelaxedCounter>::default(), Colored::default()];
+        for (counter, color) in counters.iter_mut().zip(kmers) {
+            counter.set_color(color.unwrap());
+        }
+
+        assert_eq!(colors.get(&counters[0]), &[0, 1]);
+        assert_eq!(colors.get(&counters[1]), &[1]);
+        assert_eq!(colors.get(&RelaxedCounter::default()), &[] as &[u32]);
+        assert_eq!(colors.label_id("pathogen"), Some(1));
+        assert_eq!(colors.label_id("other"), None);
+        assert!(!colors.tracks_origins());
+        assert_eq!(
+            colors
+                .labels_of(colors.get(&counters[0]))
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+
+        // the matches are counted by the wrapped counter
+        counters[0].add_match(KmerMatch {
+            id_read: 0,
+            position: 2,
+            forward: true,
+        });
+        assert_eq!(counters[0].get_count(), 1);
+        assert_eq!(
+            Colored::merged_to_string(&[&counters[0], &counters[1]]),
+            "1"
+        );
+    }
+
+    #[test]
+    fn kmer_origins() {
+        let mut colors = KmerColors::new(vec!["host".to_string(), "virus".to_string()]);
+        let chr1 = colors.add_record("chr1".to_string(), 0);
+        let chr2 = colors.add_record("chr2".to_string(), 0);
+        let gene = colors.add_record("gene".to_string(), 1);
+
+        let first = colors.add(None, chr1);
+        let first = colors.add(Some(first), chr2);
+        let second = colors.add(None, chr2);
+        let second = colors.add(Some(second), gene);
+
+        assert!(colors.tracks_origins());
+        assert_eq!(colors.table.targets(first), &[chr1, chr2]);
+        assert_eq!(
+            colors
+                .labels_of(colors.table.targets(first))
+                .collect::<Vec<_>>(),
+            vec![0]
+        );
+        assert_eq!(
+            colors
+                .labels_of(colors.table.targets(second))
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+    }
+}
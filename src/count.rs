@@ -1,18 +1,29 @@
 //! Count declarations
 
 /* std use */
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::Write;
 
 /* crates use */
 use ahash::AHashMap as HashMap;
 use anyhow::Context as _;
 
 /* project use */
-use crate::chunks::{NO_WRITER, Pipeline, WithoutId};
+use crate::abundance::ReadAssignments;
+use crate::alphabet::{self, Alphabet};
+use crate::chunks::{OptionalId, Pipeline, Record, WithOriginal, WithQual, WithoutId, NO_WRITER};
+use crate::classification;
+use crate::cli::PairMode;
+use crate::colors::{KmerColors, LabelThreshold};
+use crate::kmer::{normalize, Kmer};
 use crate::kmer_counter::KmerCounter;
+use crate::kmer_hash::MismatchIndex;
+use crate::kmer_index::KmerIndex;
+use crate::low_complexity::LowComplexity;
 use crate::matched_sequences::MatchedSequence;
-use crate::sequence_normalizer::SequenceNormalizer;
+use crate::options::Options;
+use crate::output::OutputFile;
+use crate::sampling::{sampled_kmers, Sampling};
+use crate::spaced_seeds::SpacedSeeds;
 
 
 /// Reverse complement a sequence in place.
@@ -30,108 +41,585 @@ pub fn rev_comp(seq: &mut [u8]) {
     });
 }
 
+/// files of the sequences read and written by [kmers_in_fasta_file_par()], the empty ones are not
+/// used
+#[derive(Debug, Default, Clone)]
+pub struct SequenceFiles {
+    /// input sequences, the standard input if empty
+    pub input: String,
+    /// second mates of the paired reads, if they are not interleaved in `input`
+    pub input_mates: String,
+    /// output sequences
+    pub output: String,
+    /// output second mates, if they are not interleaved in `output`
+    pub output_mates: String,
+    /// sequences (or pairs) that are not output
+    pub unmatched: String,
+    /// second mates that are not output, if they are not interleaved in `unmatched`
+    pub unmatched_mates: String,
+    /// classification of the sequences (see [classification])
+    pub classification: String,
+}
+
+/// indexes and parameters of the search of the kmers in the sequences, see [shared_kmers_par()]
+pub struct KmerSearch<'a, K, C, I: ?Sized> {
+    /// indexed kmers, exact or approximate (see [KmerIndex])
+    pub kmer_set: &'a I,
+    /// labels and reference records of the indexed kmers, if any
+    pub colors: Option<&'a KmerColors>,
+    /// excluded kmers, if any
+    pub exclude_set: Option<&'a HashMap<K, C>>,
+    /// index of the kmers to search with a few substitutions, if any
+    pub mismatch_index: Option<&'a MismatchIndex<K>>,
+    /// spaced seeds of the indexed kmers, if any
+    pub seeds: Option<&'a SpacedSeeds>,
+    /// sampling of the indexed kmers, if any
+    pub sampling: Option<&'a Sampling>,
+    /// filter of the low complexity kmers of the sequences, if any
+    pub low_complexity: Option<&'a LowComplexity>,
+    /// size of the kmers
+    pub kmer_size: usize,
+    /// the kmers are indexed as they are, else their canonical form
+    pub stranded: bool,
+    /// search both the kmers and their reverse complement
+    pub map_both_strands: bool,
+    /// alphabet of the kmers
+    pub alphabet: Alphabet,
+    /// the sequences are DNA sequences translated in their 6 frames
+    pub translate: bool,
+}
+
+impl<'a, K, C, I: ?Sized> KmerSearch<'a, K, C, I> {
+    /// search of the nucleotide kmers of size `kmer_size` of `kmer_set`, as they are indexed
+    pub fn new(kmer_set: &'a I, kmer_size: usize, stranded: bool) -> Self {
+        Self {
+            kmer_set,
+            colors: None,
+            exclude_set: None,
+            mismatch_index: None,
+            seeds: None,
+            sampling: None,
+            low_complexity: None,
+            kmer_size,
+            stranded,
+            map_both_strands: false,
+            alphabet: Alphabet::Dna,
+            translate: false,
+        }
+    }
+
+    /// the same search in another index of the kmers
+    pub fn in_index<'b, J: ?Sized>(&self, kmer_set: &'b J) -> KmerSearch<'b, K, C, J>
+    where
+        'a: 'b,
+    {
+        KmerSearch {
+            kmer_set,
+            colors: self.colors,
+            exclude_set: self.exclude_set,
+            mismatch_index: self.mismatch_index,
+            seeds: self.seeds,
+            sampling: self.sampling,
+            low_complexity: self.low_complexity,
+            kmer_size: self.kmer_size,
+            stranded: self.stranded,
+            map_both_strands: self.map_both_strands,
+            alphabet: self.alphabet,
+            translate: self.translate,
+        }
+    }
+}
+
+// the search only holds references, whatever its kmers and counters
+impl<K, C, I: ?Sized> Clone for KmerSearch<'_, K, C, I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, C, I: ?Sized> Copy for KmerSearch<'_, K, C, I> {}
+
 /// for each sequence of a given fasta file, count the number of indexed kmers it contains
-/// and output the sequence if its ratio of indexed kmers is in ]min_threshold, max_threshold]
-#[allow(clippy::too_many_arguments)]
-pub fn kmers_in_fasta_file_par<T, D>(
-    file_name: String,
-    kmer_set: &HashMap<Vec<u8>, T>,
-    kmer_size: usize,
-    out_fasta: String,
-    min_threshold: f32,
-    max_threshold: f32,
-    stranded: bool,
-    query_reverse: bool,
-    map_both_strands: bool,
-) -> anyhow::Result<(usize, usize, usize)>
+/// and output the sequence if its ratio of indexed kmers is in ]min_threshold, max_threshold],
+/// its number of indexed kmers is in [min_count, max_count] and its number of bases covered by
+/// indexed kmers is at least min_covered_bases (see [Options])
+///
+/// The kmers are searched as described by `search` (see [shared_kmers_par()]): if it has a
+/// `mismatch_index`, the kmers with a few substitutions are also searched, and reported separately
+/// as approximate matches (they are counted as matches by the thresholds)
+///
+/// the output records are in fastq format (with their quality line) if the input is fastq,
+/// else in fasta format. If `keep_original` is set, the original sequences are output (not
+/// normalized, not reverse complemented and with their line breaks).
+/// The output file is compressed according to its extension (see [OutputFile])
+///
+/// If the input is made of paired reads (`interleaved` is set or `files` has an `input_mates`
+/// file), the two mates of a pair are kept or dropped together according to `pair_mode`, and
+/// written to the `output` and `output_mates` files (or interleaved in `output` if `output_mates`
+/// is empty)
+///
+/// If `files` has an `unmatched` file, the sequences (or pairs) that are not output are written in
+/// it (and `unmatched_mates`) during the same pass, with their metrics
+///
+/// If `search` has an `exclude_set`, the sequences whose ratio of excluded kmers is above
+/// `max_exclude_threshold` are not output, and the number and ratio of excluded kmers are appended
+/// to the metrics. The sequences must also support the `label_thresholds`
+///
+/// If `files` has a `classification` file, each sequence (or pair) is classified to the target of
+/// the `colors` of `search` it shares the most kmers with, in this TSV file (see [classification])
+///
+/// If `read_assignments` is provided, each sequence (or pair) is assigned to the reference records
+/// sharing the most kmers with it, to estimate their abundances (see [crate::abundance])
+///
+/// Returns the number of nucleotides, of kmers and of matched kmers of the sequences, and the
+/// number of sequences shorter than the kmers (see [is_shorter_than_kmers()])
+pub fn kmers_in_fasta_file_par<K, T, D, I>(
+    files: &SequenceFiles,
+    search: &KmerSearch<'_, K, T, I>,
+    label_thresholds: &[LabelThreshold],
+    options: &Options,
+    read_assignments: Option<&mut ReadAssignments>,
+) -> anyhow::Result<(usize, usize, usize, usize)>
 where
+    K: Kmer,
     T: KmerCounter,
     D: MatchedSequence + Send + 'static,
+    I: KmerIndex<K, T> + ?Sized,
 {
-    let reader = match file_name.as_str() {
+    let reader = match files.input.as_str() {
         "" => needletail::parse_fastx_stdin()?,
         f  => needletail::parse_fastx_file(f)?,
     };
+    let mut outputs = Outputs::create(files, search.colors.is_some(), read_assignments)?;
+    let query = Query {
+        search,
+        label_thresholds,
+        options,
+    };
 
-    let mut output_file = BufWriter::new(
-        File::create(out_fasta).context("Error: failed to open the sequence file for writing")?,
-    );
+    // the pairs are kept or dropped as a whole
+    let pair_mode =
+        (options.interleaved || !files.input_mates.is_empty()).then_some(options.pair_mode);
+    let result = if let Some(pair_mode) = pair_mode {
+        let mates = match files.input_mates.as_str() {
+            "" => None,
+            f => Some(needletail::parse_fastx_file(f)?),
+        };
+        if options.keep_original {
+            filter_pairs::<K, T, D, I, WithOriginal>(reader, mates, &mut outputs, &query, pair_mode)
+        } else {
+            filter_pairs::<K, T, D, I, WithQual>(reader, mates, &mut outputs, &query, pair_mode)
+        }?
+    } else if options.keep_original {
+        // the original sequences are only stored in the chunks if they are needed
+        filter_records::<K, T, D, I, WithOriginal>(reader, &mut outputs, &query)?
+    } else {
+        filter_records::<K, T, D, I, WithQual>(reader, &mut outputs, &query)?
+    };
+    outputs.finish()?;
+    Ok(result)
+}
 
-    Pipeline::<Option<D>>::run(
-        reader,
-        // map
-        |record| {
-            if query_reverse {
-                // we need to reverse complement the sequence first
-                rev_comp(record.seq);
+/// output file of the sequences, and of the second mates of paired reads if they are not
+/// interleaved
+struct PairedOutput {
+    file: OutputFile,
+    mates_file: Option<OutputFile>,
+}
+
+impl PairedOutput {
+    /// open the output files (`mates_file_name` may be empty)
+    fn create(file_name: &str, mates_file_name: &str) -> anyhow::Result<Self> {
+        let create = |f| {
+            OutputFile::create(f, rayon::current_num_threads())
+                .context("Error: failed to open the sequence file for writing")
+        };
+        Ok(Self {
+            file: create(file_name)?,
+            mates_file: match mates_file_name {
+                "" => None,
+                f => Some(create(f)?),
+            },
+        })
+    }
+
+    /// output file of the second mates
+    fn mates(&mut self) -> &mut OutputFile {
+        self.mates_file.as_mut().unwrap_or(&mut self.file)
+    }
+
+    /// flush and close the output files
+    fn finish(self) -> anyhow::Result<()> {
+        for file in std::iter::once(self.file).chain(self.mates_file) {
+            file.finish()
+                .context("Error: failed to write the sequence file")?;
+        }
+        Ok(())
+    }
+}
+
+/// outputs of the sequences filled by [kmers_in_fasta_file_par()], see [SequenceFiles]
+struct Outputs<'a> {
+    sequences: PairedOutput,
+    unmatched: Option<PairedOutput>,
+    classification: Option<OutputFile>,
+    read_assignments: Option<&'a mut ReadAssignments>,
+}
+
+impl<'a> Outputs<'a> {
+    /// open the output files, the sequences can only be classified if the index is `colored`
+    fn create(
+        files: &SequenceFiles,
+        colored: bool,
+        read_assignments: Option<&'a mut ReadAssignments>,
+    ) -> anyhow::Result<Self> {
+        let classification = match (files.classification.as_str(), colored) {
+            ("", _) => None,
+            (f, true) => {
+                let mut file = OutputFile::create(f, rayon::current_num_threads())
+                    .context("Error: failed to open the classification file for writing")?;
+                file.write_all(classification::HEADER.as_bytes())?;
+                Some(file)
             }
-            let total_nucleotides = record.seq.len();
-            let total_kmer = record.seq.len() - kmer_size + 1;
+            (_, false) => {
+                anyhow::bail!("the sequences can only be classified with a colored index")
+            }
+        };
+        Ok(Self {
+            sequences: PairedOutput::create(&files.output, &files.output_mates)?,
+            unmatched: match files.unmatched.as_str() {
+                "" => None,
+                f => Some(PairedOutput::create(f, &files.unmatched_mates)?),
+            },
+            classification,
+            read_assignments,
+        })
+    }
 
-            let proxy_shared_kmers = shared_kmers_par::<_, D>(
-                kmer_set,
-                record.seq,
-                record.read_id,
-                kmer_size,
-                stranded,
-                map_both_strands,
-                );
+    /// classify some records (a sequence or a pair) as a whole, and assign them to the reference
+    /// records, if requested
+    fn classify<K, T, I, D>(
+        &mut self,
+        query: &Query<'_, K, T, I>,
+        records: &[&Record<'_, Option<D>>],
+    ) -> std::io::Result<()>
+    where
+        K: Kmer,
+        T: KmerCounter,
+        I: KmerIndex<K, T> + ?Sized,
+        D: MatchedSequence,
+    {
+        if let Some(classification) = self.classification.as_mut() {
+            query.write_classification(classification, records)?;
+        }
+        if let Some(read_assignments) = self.read_assignments.as_deref_mut() {
+            let matched_sequences = records
+                .iter()
+                .map(|record| record.extra.as_ref().unwrap())
+                .collect::<Vec<_>>();
+            read_assignments.add(&matched_sequences);
+        }
+        Ok(())
+    }
 
-            let match_kmer = proxy_shared_kmers.match_count();
+    /// flush and close the output files
+    fn finish(self) -> anyhow::Result<()> {
+        self.sequences.finish()?;
+        if let Some(unmatched) = self.unmatched {
+            unmatched.finish()?;
+        }
+        if let Some(classification) = self.classification {
+            classification
+                .finish()
+                .context("Error: failed to write the classification file")?;
+        }
+        Ok(())
+    }
+}
 
-            *record.extra = Some(proxy_shared_kmers);
+/// parameters of the search of the indexed kmers in the sequences, see [kmers_in_fasta_file_par()]
+struct Query<'a, K, T, I: ?Sized> {
+    search: &'a KmerSearch<'a, K, T, I>,
+    label_thresholds: &'a [LabelThreshold],
+    options: &'a Options,
+}
 
-            (total_nucleotides, total_kmer, match_kmer)
-        },
+impl<K, T, I> Query<'_, K, T, I>
+where
+    K: Kmer,
+    T: KmerCounter,
+    I: KmerIndex<K, T> + ?Sized,
+{
+    /// search the indexed kmers in a record, store the result in its extra field
+    ///
+    /// returns the number of nucleotides, of kmers and of matched kmers of the record, and 1 if it
+    /// is shorter than the kmers (see [is_shorter_than_kmers()])
+    fn match_record<D: MatchedSequence>(
+        &self,
+        record: Record<'_, Option<D>>,
+    ) -> (usize, usize, usize, usize) {
+        if self.options.query_reverse {
+            // we need to reverse complement the sequence first
+            rev_comp(record.seq);
+            if let (Some(qual), None) = (record.qual, record.raw_seq) {
+                // the quality line is kept in the same order as the output sequence
+                qual.reverse();
+            }
+        }
+        let total_nucleotides = record.seq.len();
+        let short_sequence = usize::from(is_shorter_than_kmers(
+            record.seq,
+            self.search.kmer_size,
+            self.search.translate,
+        ));
+
+        let proxy_shared_kmers =
+            shared_kmers_par::<_, _, D, _>(self.search, record.seq, record.read_id);
+
+        // only the sampled kmers are counted if the kmers are sampled
+        let total_kmer = proxy_shared_kmers.mapped_position_size();
+        let match_kmer =
+            proxy_shared_kmers.match_count() + proxy_shared_kmers.approximate_match_count();
+
+        *record.extra = Some(proxy_shared_kmers);
+
+        (total_nucleotides, total_kmer, match_kmer, short_sequence)
+    }
+
+    /// alphabet of the read sequences: they are DNA sequences if they are translated
+    fn sequence_alphabet(&self) -> Alphabet {
+        if self.search.translate {
+            Alphabet::Dna
+        } else {
+            self.search.alphabet
+        }
+    }
+
+    /// true if the numbers and ratios of indexed kmers of some sequences (considered as a whole)
+    /// are in the user defined thresholds
+    fn supports_thresholds<D: MatchedSequence>(&self, matched_sequences: &[&D]) -> bool {
+        let options = self.options;
+        let sum =
+            |count: &dyn Fn(&D) -> usize| matched_sequences.iter().map(|m| count(m)).sum::<usize>();
+        let positions = sum(&|m| m.mapped_position_size()) as f32;
+        let ratio = |count: &dyn Fn(&D) -> usize| 100.0 * sum(count) as f32 / positions;
+
+        // the approximate matches are also considered as shared kmers
+        let shared_kmers = sum(&|m| m.match_count() + m.approximate_match_count());
+        let percent_shared_kmers = ratio(&|m| m.match_count() + m.approximate_match_count());
+        percent_shared_kmers > options.min_threshold
+            && percent_shared_kmers <= options.max_threshold
+            && shared_kmers >= options.min_count
+            && shared_kmers <= options.max_count
+            && sum(&|m| m.covered_bases()) >= options.min_covered_bases
+            && self.label_thresholds.iter().all(|t| {
+                let percent_label_kmers = ratio(&|m| m.label_count(t.label));
+                percent_label_kmers > t.min && percent_label_kmers <= t.max
+            })
+            && ratio(&|m| m.excluded_count()) <= options.max_exclude_threshold
+    }
+
+    /// write the classification of some records (a sequence or a pair) in the classification file,
+    /// under the id of the first one
+    fn write_classification<D: MatchedSequence>(
+        &self,
+        output_file: &mut OutputFile,
+        records: &[&Record<'_, Option<D>>],
+    ) -> std::io::Result<()> {
+        // the classification file is only created with a colored index
+        let colors = self.search.colors.unwrap();
+        let matched_sequences = records
+            .iter()
+            .map(|record| record.extra.as_ref().unwrap())
+            .collect::<Vec<_>>();
+        classification::classify(colors, &matched_sequences).write(
+            output_file,
+            records[0].id,
+            classification::target_names(colors),
+        )
+    }
+
+    /// write a record with its metrics
+    fn write_record<D: MatchedSequence>(
+        &self,
+        output_file: &mut OutputFile,
+        record: &Record<'_, Option<D>>,
+    ) -> std::io::Result<()> {
+        let matched_sequence = record.extra.as_ref().unwrap();
+        let iter = record.raw_seq.unwrap_or(record.seq).split(|&x| x == b'\n');
+
+        let header = std::str::from_utf8(record.id).unwrap();
+        let marker = if record.qual.is_some() { '@' } else { '>' };
+        write!(output_file, "{}{}{}", marker, header, matched_sequence)?; // append metrics
+        if self.search.mismatch_index.is_some() {
+            // append metrics of the approximate matches
+            output_file.write_all(matched_sequence.approximate_to_string().as_bytes())?;
+        }
+        if self.search.exclude_set.is_some() {
+            // append metrics of the excluded kmers
+            output_file.write_all(matched_sequence.excluded_to_string().as_bytes())?;
+        }
+        if let Some(colors) = self.search.colors {
+            if colors.labels.len() > 1 {
+                // append metrics of each label
+                output_file
+                    .write_all(matched_sequence.labels_to_string(&colors.labels).as_bytes())?;
+            }
+            if colors.tracks_origins() {
+                // append the reference records sharing the most kmers
+                output_file.write_all(
+                    matched_sequence
+                        .origins_to_string(&colors.records)
+                        .as_bytes(),
+                )?;
+            }
+        }
+        writeln!(output_file)?;
+        for line in iter {
+            output_file.write_all(line)?;
+            output_file.write_all(b"\n")?;
+        }
+        if let Some(qual) = &record.qual {
+            output_file.write_all(b"+\n")?;
+            output_file.write_all(qual)?;
+            output_file.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// see [kmers_in_fasta_file_par()]
+fn filter_records<K, T, D, I, O>(
+    reader: Box<dyn needletail::FastxReader>,
+    outputs: &mut Outputs<'_>,
+    query: &Query<'_, K, T, I>,
+) -> anyhow::Result<(usize, usize, usize, usize)>
+where
+    K: Kmer,
+    T: KmerCounter,
+    D: MatchedSequence + Send + 'static,
+    I: KmerIndex<K, T> + ?Sized,
+    O: OptionalId + Send,
+{
+    Pipeline::<Option<D>, O>::run(
+        reader,
+        query.sequence_alphabet(),
+        // map
+        |record| query.match_record(record),
         // reduce
         (
-            || (0, 0, 0),
-            |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2),
+            || (0, 0, 0, 0),
+            |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2, a.3 + b.3),
         ),
         // writer
         |record| {
-            let matched_sequence = record.extra.as_ref().unwrap();
-            let percent_shared_kmers = matched_sequence.percent_shared_kmers();
-
-            if percent_shared_kmers > min_threshold && percent_shared_kmers <= max_threshold {
-                // supports the user defined thresholds
-
-                // let record_as_string = seq.1.to_owned();
-                let iter = record.seq.split(|&x| x == b'\n');
-
+            if query.supports_thresholds(&[record.extra.as_ref().unwrap()]) {
+                query.write_record(&mut outputs.sequences.file, &record)?;
+            } else if let Some(unmatched) = outputs.unmatched.as_mut() {
+                query.write_record(&mut unmatched.file, &record)?;
+            }
+            outputs.classify(query, &[&record])?;
+            Ok(())
+        },
+    )
+}
 
-                // output_file.write_all(iter.next().unwrap())?; // write the original header of the record
-                let header = std::str::from_utf8(record.id).unwrap();
-                writeln!(output_file, ">{}{}", header, matched_sequence)?; // append metrics
-                for line in iter {
-                    output_file.write_all(line)?;
-                    output_file.write_all(b"\n")?;
+/// see [kmers_in_fasta_file_par()]
+fn filter_pairs<K, T, D, I, O>(
+    reader: Box<dyn needletail::FastxReader>,
+    mates: Option<Box<dyn needletail::FastxReader>>,
+    outputs: &mut Outputs<'_>,
+    query: &Query<'_, K, T, I>,
+    pair_mode: PairMode,
+) -> anyhow::Result<(usize, usize, usize, usize)>
+where
+    K: Kmer,
+    T: KmerCounter,
+    D: MatchedSequence + Send + 'static,
+    I: KmerIndex<K, T> + ?Sized,
+    O: OptionalId + Send,
+{
+    Pipeline::<Option<D>, O>::run_pairs(
+        reader,
+        mates,
+        query.sequence_alphabet(),
+        // map
+        |first, second| {
+            let (a, b) = (query.match_record(first), query.match_record(second));
+            (a.0 + b.0, a.1 + b.1, a.2 + b.2, a.3 + b.3)
+        },
+        // reduce
+        (
+            || (0, 0, 0, 0),
+            |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2, a.3 + b.3),
+        ),
+        // writer
+        |first, second| {
+            let (first_match, second_match) = (
+                first.extra.as_ref().unwrap(),
+                second.extra.as_ref().unwrap(),
+            );
+            let keep = match pair_mode {
+                PairMode::Combined => query.supports_thresholds(&[first_match, second_match]),
+                PairMode::Both => {
+                    query.supports_thresholds(&[first_match])
+                        && query.supports_thresholds(&[second_match])
                 }
-            } // end read contains at least one indexed kmer
+                PairMode::Either => {
+                    query.supports_thresholds(&[first_match])
+                        || query.supports_thresholds(&[second_match])
+                }
+            };
+            // the pair is kept or dropped as a whole
+            let output = match keep {
+                true => Some(&mut outputs.sequences),
+                false => outputs.unmatched.as_mut(),
+            };
+            if let Some(output) = output {
+                query.write_record(&mut output.file, &first)?;
+                query.write_record(output.mates(), &second)?;
+            }
+            // the pair is classified as a whole, with the id of its first mate
+            outputs.classify(query, &[&first, &second])?;
             Ok(())
-        })
+        },
+    )
 }
 
-/// for each sequence of a given fasta file, count the number of indexed kmers it contains
-pub fn only_kmers_in_fasta_file_par<T, D>(
+/// for each sequence of a given fasta file, count the number of indexed kmers it contains, searched
+/// as described by `search` (its colors and excluded kmers are not used)
+///
+/// Returns the same counts as [kmers_in_fasta_file_par()]
+pub fn only_kmers_in_fasta_file_par<K, T, D, I>(
     file_name: String,
-    kmer_set: &HashMap<Vec<u8>, T>,
-    kmer_size: usize,
-    stranded: bool,
+    search: &KmerSearch<'_, K, T, I>,
     query_reverse: bool,
-) -> anyhow::Result<(usize, usize, usize)>
+) -> anyhow::Result<(usize, usize, usize, usize)>
 where
+    K: Kmer,
     T: KmerCounter,
     D: MatchedSequence + Send + 'static,
+    I: KmerIndex<K, T> + ?Sized,
 {
-
     let reader = match file_name.as_str() {
         "" => needletail::parse_fastx_stdin()?,
         f  => needletail::parse_fastx_file(f)?,
     };
+    // in this case we map only the kmer or its reverse complement not both
+    let search = KmerSearch {
+        colors: None,
+        exclude_set: None,
+        map_both_strands: false,
+        ..*search
+    };
 
     Pipeline::<Option<D>, WithoutId>::run(
         reader,
+        if search.translate {
+            Alphabet::Dna
+        } else {
+            search.alphabet
+        },
         // map
         |record| {
             if query_reverse {
@@ -139,133 +627,253 @@ where
                 rev_comp(record.seq);
             }
             let total_nucleotides = record.seq.len();
-            let total_kmer = record.seq.len() - kmer_size + 1;
-
-            let proxy_shared_kmers = shared_kmers_par::<_, D>(
-                kmer_set,
+            let short_sequence = usize::from(is_shorter_than_kmers(
                 record.seq,
-                record.read_id,
-                kmer_size,
-                stranded,
-                false, // in this case we map only the kmer or its reverse complement not both
-            );
-            let match_kmer = proxy_shared_kmers.match_count();
+                search.kmer_size,
+                search.translate,
+            ));
+
+            let proxy_shared_kmers =
+                shared_kmers_par::<_, _, D, _>(&search, record.seq, record.read_id);
+            let total_kmer = proxy_shared_kmers.mapped_position_size();
+            let match_kmer =
+                proxy_shared_kmers.match_count() + proxy_shared_kmers.approximate_match_count();
 
             *record.extra = Some(proxy_shared_kmers);
 
-            (total_nucleotides, total_kmer, match_kmer)
+            (total_nucleotides, total_kmer, match_kmer, short_sequence)
         },
         // reduce
         (
-            || (0, 0, 0),
-            |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+            || (0, 0, 0, 0),
+            |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2, a.3 + b.3),
         ),
         // writer
         NO_WRITER,
-        )
+    )
+}
+
+/// true if a sequence is shorter than the kmers: no kmer is searched in it
+///
+/// a translated DNA sequence is shorter than the kmers if its longest frame is
+pub fn is_shorter_than_kmers(sequence: &[u8], kmer_size: usize, translate: bool) -> bool {
+    let letters = if translate {
+        sequence.len() / 3
+    } else {
+        sequence.len()
+    };
+    letters < kmer_size
+}
+
+/// count the number of indexed kmers in a given read, searched as described by `search`
+///
+/// the kmers are looked up in `kmer_set`, an exact or an approximate index (see [KmerIndex]): the
+/// counters of the matched kmers are only incremented if it is exact.
+/// if `mismatch_index` is provided, the kmers that are not indexed are searched with a few
+/// substitutions, and counted as approximate matches.
+/// If `seeds` are provided, the keys of the kmers for each spaced seed are searched instead of the
+/// kmers.
+/// If `sampling` is provided, only the sampled kmers are searched, and the ratios are relative to
+/// the number of sampled kmers.
+/// If `exclude_set` is provided, the kmers of the read belonging to it are counted as excluded
+/// kmers (see [MatchedSequence::excluded_count()]).
+/// If `low_complexity` is provided, the low complexity kmers of the read are skipped: they are not
+/// counted in the number of positions of the read, so that they do not lower its ratios.
+/// If `translate` is set, the read is a DNA sequence translated in its 6 frames (see
+/// [alphabet::six_frames()]) and searched for amino acid kmers: the result of the frame sharing the
+/// most kmers (the first one in case of tie) is returned, its positions are in amino acids. The
+/// frames are searched without counting their kmers, then only the returned frame is searched again
+/// to increment the counters of its matches
+pub fn shared_kmers_par<K, C, D, I>(
+    search: &KmerSearch<'_, K, C, I>,
+    read: &[u8],
+    read_id: usize,
+) -> D
+where
+    K: Kmer,
+    C: KmerCounter,
+    D: MatchedSequence + Sized,
+    I: KmerIndex<K, C> + ?Sized,
+{
+    if !search.translate {
+        return search_read_kmers(search, read, read_id, true);
+    }
+    let search = KmerSearch {
+        translate: false,
+        ..*search
+    };
+    let matches = |result: &D| result.match_count() + result.approximate_match_count();
+    let frames = alphabet::six_frames(read).collect::<Vec<_>>();
+    let best = frames
+        .iter()
+        .map(|frame| {
+            matches(&search_read_kmers::<K, C, D, I>(
+                &search, frame, read_id, false,
+            ))
+        })
+        .enumerate()
+        // the first frame in case of tie
+        .max_by(|(i, best), (j, other)| best.cmp(other).then(j.cmp(i)))
+        .map(|(i, _)| i);
+    match best {
+        Some(best) => search_read_kmers(&search, &frames[best], read_id, true),
+        None => D::new(0),
+    }
 }
 
-/// count the number of indexed kmers in a given read
-pub fn shared_kmers_par<C, D>(
-    kmer_set: &HashMap<Vec<u8>, C>,
+/// the kmers of `read` shared with the indexed kmers (see [shared_kmers_par()]), the counters of
+/// the matched kmers being incremented only if `count` is set
+fn search_read_kmers<K, C, D, I>(
+    search: &KmerSearch<'_, K, C, I>,
     read: &[u8],
     read_id: usize,
-    kmer_size: usize,
-    stranded: bool,
-    map_both_strands: bool,
+    count: bool,
 ) -> D
 where
+    K: Kmer,
     C: KmerCounter,
     D: MatchedSequence + Sized,
+    I: KmerIndex<K, C> + ?Sized,
 {
+    let KmerSearch {
+        kmer_set,
+        colors,
+        exclude_set,
+        mismatch_index,
+        seeds,
+        sampling,
+        low_complexity,
+        kmer_size,
+        stranded,
+        map_both_strands,
+        alphabet,
+        ..
+    } = *search;
     // prints the read in human readable format
     //println!("Read {}: {}", read_id, String::from_utf8_lossy(read));
     if read.len() < kmer_size {
         return D::new(0);
     }
-    let mut result = D::new(read.len() - kmer_size + 1);
-    let reverse_complement = if stranded { Some(false) } else { None };
-
-    let mut buf = [0].repeat(kmer_size);
-
-    if !map_both_strands {
-        // if we do not map both strands, we only map the kmer or its reverse complement
-        let canonical_kmer = buf.as_mut_slice();
-        // For computing the numbe of positions covered by at least a kmer, we need to keep track of the first uncovered position
-        let mut first_uncovered_position = 0;
-
-        for i in 0..(read.len() - kmer_size + 1) {
-            let kmer = &read[i..(i + kmer_size)];
-            let sequence_normalizer = SequenceNormalizer::new(kmer, reverse_complement);
-            sequence_normalizer.copy_to_slice(canonical_kmer);
-            if let Some(kmer_counter) = kmer_set.get(canonical_kmer) {
-                result.add_match(i, sequence_normalizer.is_raw());
-                if first_uncovered_position < i {
-                    result.add_covered_base(kmer_size);
-                }
-                else {
-                    result.add_covered_base(kmer_size + i - first_uncovered_position);
-                }
-                first_uncovered_position = i + kmer_size;
+    // the low complexity kmers of the read are neither searched nor counted
+    let is_masked = |i: usize| {
+        low_complexity.is_some_and(|filter| filter.is_low_complexity(&read[i..i + kmer_size]))
+    };
+    // kmers containing a non ACGT letter are never indexed, they are skipped by the iterator
+    let kmers = || sampled_kmers::<K>(alphabet, read, kmer_size, stranded, sampling.copied());
+    // the sampled or unmasked kmers are buffered, so that the read is only sampled once
+    let buffered = (sampling.is_some() || low_complexity.is_some()).then(|| {
+        let mut nb_kmers = 0;
+        let kmers = kmers()
+            .inspect(|_| nb_kmers += 1)
+            .filter(|(i, _, _)| !is_masked(*i))
+            .collect::<Vec<_>>();
+        (kmers, nb_kmers)
+    });
+    let mapped_position_size = match (&buffered, sampling) {
+        (Some((kmers, _)), Some(_)) => kmers.len(),
+        // every position of the read, but the masked kmers
+        (Some((kmers, nb_kmers)), None) => (read.len() + 1 - kmer_size) - (nb_kmers - kmers.len()),
+        (None, _) => read.len() + 1 - kmer_size,
+    };
+    let mut result = D::new(mapped_position_size);
 
+    // For computing the numbe of positions covered by at least a kmer, we need to keep track of the first uncovered position
+    let mut first_uncovered_position = 0;
 
-                kmer_counter.add_match(crate::kmer_counter::KmerMatch {
-                    id_read: (read_id),
-                    position: (i),
-                    forward: (sequence_normalizer.is_raw()),
-                });
-            } 
+    let mut search_kmer = |(i, forward, reverse): (usize, K, K)| {
+        if let Some(exclude_set) = exclude_set {
+            // the excluded kmers are searched as they are, even with spaced seeds
+            let (kmer, is_raw) = normalize(forward, reverse, stranded);
+            let other = if is_raw { reverse } else { forward };
+            if exclude_set.contains_key(&kmer)
+                || (map_both_strands && exclude_set.contains_key(&other))
+            {
+                result.add_excluded_match();
+            }
         }
-        result
-    } else {
-        // if we map both strands, we map the kmer and its reverse complement
-        // Note that if --stranded is not set, the mapping is always detected in forward strand
-        let normalizer_kmer = buf.as_mut_slice();
-        // For computing the numbe of positions covered by at least a kmer, we need to keep track of the first uncovered position
-        let mut first_uncovered_position = 0;
+        // with spaced seeds, the kmer is matched if it is matched with any of them
+        let matched = (0..seeds.map_or(1, |seeds| seeds.len())).find_map(|mask| {
+            let (forward, reverse) = match seeds {
+                Some(seeds) => seeds.keys(mask, forward, reverse),
+                None => (forward, reverse),
+            };
+            let (kmer, is_raw) = normalize(forward, reverse, stranded);
+
+            let exact_match = if !map_both_strands {
+                // if we do not map both strands, we only map the kmer or its reverse complement
+                kmer_set.find(&kmer).map(|counter| (counter, is_raw))
+            } else {
+                // if we map both strands, we map the kmer and its reverse complement
+                // Note that if --stranded is not set, the mapping is always detected in forward strand
+                kmer_set
+                    .find(&kmer)
+                    .map(|counter| (counter, true))
+                    .or_else(|| {
+                        // forward did not match, we try the reverse one
+                        let other = if is_raw { reverse } else { forward };
+                        kmer_set.find(&other).map(|counter| (counter, false))
+                    })
+            };
 
-        for i in 0..(read.len() - kmer_size + 1) {
-            let kmer = &read[i..(i + kmer_size)];
-            let sequence_normalizer = SequenceNormalizer::new(kmer, reverse_complement);
-            sequence_normalizer.copy_to_slice(normalizer_kmer);
+            exact_match.map(|m| (m, true)).or_else(|| {
+                // search the closest indexed kmer with a few substitutions
+                let index = mismatch_index?;
+                let other = if is_raw { reverse } else { forward };
+                let (kmer_forward, other_forward) = if map_both_strands {
+                    (true, false)
+                } else {
+                    (is_raw, !is_raw)
+                };
+                // the canonical orientation of the indexed kmer may differ from the one of the read kmer
+                let found = index
+                    .find(&kmer)
+                    .map(|(found, _)| (found, kmer_forward))
+                    .or_else(|| {
+                        (!stranded || map_both_strands)
+                            .then(|| index.find(&other).map(|(found, _)| (found, other_forward)))
+                            .flatten()
+                    });
+                let (found, forward) = found?;
+                Some(((kmer_set.find(&found)?, forward), false))
+            })
+        });
 
+        if let Some(((kmer_counter, forward), exact)) = matched {
+            if exact {
+                result.add_match(i, forward);
+            } else {
+                result.add_approximate_match(i, forward);
+            }
+            if let Some(colors) = colors {
+                // the color of a kmer is stored with its counter
+                let targets = kmer_counter.map_or(&[][..], |counter| colors.get(counter));
+                result.add_labels(colors.labels_of(targets));
+                if colors.tracks_origins() {
+                    result.add_records(targets);
+                }
+            }
+            if first_uncovered_position < i {
+                result.add_covered_base(kmer_size);
+            } else {
+                result.add_covered_base(kmer_size + i - first_uncovered_position);
+            }
+            first_uncovered_position = i + kmer_size;
 
-            if let Some(kmer_counter) = kmer_set.get(normalizer_kmer) {
-                result.add_match(i, true);
+            // the matches are not counted by an approximate index
+            if let Some(kmer_counter) = kmer_counter.filter(|_| count) {
                 kmer_counter.add_match(crate::kmer_counter::KmerMatch {
                     id_read: (read_id),
                     position: (i),
-                    forward: true,
+                    forward,
                 });
-                if first_uncovered_position < i {
-                    result.add_covered_base(kmer_size);
-                }
-                else {
-                    result.add_covered_base(kmer_size + i - first_uncovered_position);
-                }
-                first_uncovered_position = i + kmer_size;
-            } else {
-                // forward did not match, we try the reverse one
-                rev_comp(normalizer_kmer);
-                if let Some(kmer_counter) = kmer_set.get(normalizer_kmer) {
-                    result.add_match(i, false);
-                    kmer_counter.add_match(crate::kmer_counter::KmerMatch {
-                        id_read: (read_id),
-                        position: (i),
-                        forward: false,
-                    });
-                    if first_uncovered_position < i {
-                        result.add_covered_base(kmer_size);
-                    }
-                    else {
-                        result.add_covered_base(kmer_size + i - first_uncovered_position);
-                    }
-                    first_uncovered_position = i + kmer_size;
-                }   
             }
         }
-        result
+    };
+    match buffered {
+        Some((kmers, _)) => kmers.into_iter().for_each(&mut search_kmer),
+        None => kmers().for_each(&mut search_kmer),
     }
+    result
 }
 
 #[cfg(test)]
@@ -307,72 +915,101 @@ mod tests {
 
         std::fs::File::create(&kmers_in_path)?.write_all(&data)?;
 
-        let (kmer_set_cano, _) = crate::kmer_hash::index_kmers::<atomic_counter::RelaxedCounter>(
-            kmers_in_path.display().to_string(),
-            kmer_size,
-            false,
-            false,
+        let (kmer_set_cano, _) = crate::kmer_hash::index_kmers::<
+            u64,
+            atomic_counter::RelaxedCounter,
+        >(
+            kmers_in_path.display().to_string(), kmer_size, false, None
         )?;
 
         assert_eq!(
-            shared_kmers_par::<_, matched_sequences::MachedCount>(
-                &kmer_set_cano,
+            shared_kmers_par::<_, _, matched_sequences::MachedCount, _>(
+                &KmerSearch::new(&kmer_set_cano, kmer_size, false),
                 &sequence,
                 42,
-                kmer_size,
-                false,
-                false
             )
             .count,
             136
         );
 
+        // the kmers of the excluded set are counted apart from the matches
+        let excluded = shared_kmers_par::<_, _, matched_sequences::MachedCount, _>(
+            &KmerSearch {
+                exclude_set: Some(&kmer_set_cano),
+                ..KmerSearch::new(&kmer_set_cano, kmer_size, false)
+            },
+            &sequence,
+            42,
+        );
+        assert_eq!((excluded.count, excluded.excluded_count), (136, 136));
+
+        // the masked low complexity kmers of the read are not counted in its positions
+        let mut low_complexity_read = b"AAAAAAAAAAAAAAAAAAAAC".to_vec();
+        low_complexity_read.extend(&sequence);
+        let repeats = LowComplexity::new(
+            crate::low_complexity::ComplexityMetric::Repeat,
+            Some(15.0),
+            Alphabet::Dna,
+        )?;
+        let masked = shared_kmers_par::<_, _, matched_sequences::MachedCount, _>(
+            &KmerSearch {
+                low_complexity: Some(&repeats),
+                ..KmerSearch::new(&kmer_set_cano, kmer_size, false)
+            },
+            &low_complexity_read,
+            42,
+        );
+        assert_eq!((masked.count, masked.mapped_position_size), (136, 157 - 6));
+
         let mut random_sequence = vec![];
         s_generator.record(&mut random_sequence, &mut rng)?;
 
         assert_eq!(
-            shared_kmers_par::<_, matched_sequences::MachedCount>(
-                &kmer_set_cano,
+            shared_kmers_par::<_, _, matched_sequences::MachedCount, _>(
+                &KmerSearch::new(&kmer_set_cano, kmer_size, false),
                 &random_sequence,
                 42,
-                kmer_size,
-                false,
-                false
             )
             .count,
             0
         );
 
         let to_small_sequence = random_sequence[10..20].to_vec();
+        assert!(is_shorter_than_kmers(&to_small_sequence, kmer_size, false));
+        assert!(!is_shorter_than_kmers(&random_sequence, kmer_size, false));
+        // the frames of a translated sequence are 3 times shorter
+        assert!(is_shorter_than_kmers(
+            &random_sequence[..44],
+            kmer_size,
+            true
+        ));
 
         assert_eq!(
-            shared_kmers_par::<_, matched_sequences::MachedCount>(
-                &kmer_set_cano,
+            shared_kmers_par::<_, _, matched_sequences::MachedCount, _>(
+                &KmerSearch::new(&kmer_set_cano, kmer_size, false),
                 &to_small_sequence,
                 42,
-                kmer_size,
-                false,
-                false
             )
             .count,
             0
         );
 
-        let (kmer_set_both, _) = crate::kmer_hash::index_kmers::<atomic_counter::RelaxedCounter>(
-            kmers_in_path.display().to_string(),
-            kmer_size,
-            false,
-            true,
-        )?;
+        let (kmer_set_both, _) =
+            crate::kmer_hash::index_kmers::<u64, atomic_counter::RelaxedCounter>(
+                kmers_in_path.display().to_string(),
+                kmer_size,
+                false,
+                Some(&LowComplexity::default()),
+            )?;
 
         assert_eq!(
-            shared_kmers_par::<_, matched_sequences::MachedCount>(
-                &kmer_set_both,
+            shared_kmers_par::<_, _, matched_sequences::MachedCount, _>(
+                &KmerSearch {
+                    map_both_strands: true,
+                    ..KmerSearch::new(&kmer_set_both, kmer_size, false)
+                },
                 &sequence,
                 42,
-                kmer_size,
-                false,
-                true
             )
             .count,
             136
@@ -381,13 +1018,13 @@ mod tests {
         rev_comp(&mut sequence);
 
         assert_eq!(
-            shared_kmers_par::<_, matched_sequences::MachedCount>(
-                &kmer_set_both,
+            shared_kmers_par::<_, _, matched_sequences::MachedCount, _>(
+                &KmerSearch {
+                    map_both_strands: true,
+                    ..KmerSearch::new(&kmer_set_both, kmer_size, true)
+                },
                 &sequence,
                 42,
-                kmer_size,
-                true,
-                true
             )
             .count,
             136
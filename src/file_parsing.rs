@@ -2,7 +2,7 @@
 
 /* std use */
 use std::fs::File;
-use std::io::{BufRead, BufReader, Error};
+use std::io::{BufRead, BufReader, Error, Read as _};
 use std::path::Path;
 
 /// Parses a file and returns a vector of Strings
@@ -22,6 +22,55 @@ pub fn read_file_lines(file_path: &str) -> Result<Vec<String>, Error> {
     Ok(lines)
 }
 
+/// Opens a text file for reading, decompressing it if it is compressed (gzip or zstd, detected
+/// from its first bytes)
+pub fn open_text_file(file_path: &str) -> Result<Box<dyn BufRead>, Error> {
+    let mut magic = [0; 4];
+    let len = File::open(file_path)?.take(4).read(&mut magic)?;
+    let file = File::open(file_path)?;
+    Ok(match &magic[..len] {
+        [0x1f, 0x8b, ..] => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))),
+        [0x28, 0xb5, 0x2f, 0xfd] => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+        _ => Box::new(BufReader::new(file)),
+    })
+}
+
+/// Returns true if a file is a kmer dump (one kmer per line, optionally followed by its count)
+/// rather than a fasta or fastq file, according to its first non empty line
+pub fn is_kmer_dump(file_path: &str) -> Result<bool, Error> {
+    for line in open_text_file(file_path)?.lines() {
+        match line?.trim_start().bytes().next() {
+            None => continue,
+            Some(first) => return Ok(first != b'>' && first != b'@'),
+        }
+    }
+    Ok(false)
+}
+
+/// Parses a line of a kmer dump: a kmer, optionally followed by its count (separated by spaces
+/// or a tab, as in the outputs of `jellyfish dump -c`, `kmc_dump` or `kmtricks`)
+///
+/// Returns None for an empty line
+pub fn parse_kmer_dump_line(line: &str) -> anyhow::Result<Option<(&[u8], Option<u64>)>> {
+    let mut fields = line.split_ascii_whitespace();
+    let Some(kmer) = fields.next() else {
+        return Ok(None);
+    };
+    let count = fields
+        .next()
+        .map(|count| count.parse())
+        .transpose()
+        .ok()
+        .filter(|_| fields.next().is_none())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "invalid line {:?}, expected a kmer and an optional count",
+                line
+            )
+        })?;
+    Ok(Some((kmer.as_bytes(), count)))
+}
+
 #[cfg(test)]
 mod tests {
     /* project use */
@@ -55,4 +104,38 @@ input_3.fasta
 
         Ok(())
     }
+
+    #[test]
+    fn kmer_dumps() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let temp_path = temp_dir.path();
+        let dump_path = format!("{}", temp_path.join("kmers.txt.gz").display());
+        let fasta_path = format!("{}", temp_path.join("kmers.fasta").display());
+
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&dump_path)?,
+            flate2::Compression::default(),
+        );
+        encoder.write_all(b"\nACGTA\t12\nCGTAC 3\n")?;
+        encoder.finish()?;
+        std::fs::write(&fasta_path, b"\n>kmer\nACGTA\n")?;
+
+        assert!(is_kmer_dump(&dump_path)?);
+        assert!(!is_kmer_dump(&fasta_path)?);
+        let lines = open_text_file(&dump_path)?
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(lines, ["", "ACGTA\t12", "CGTAC 3"]);
+
+        assert_eq!(parse_kmer_dump_line("")?, None);
+        assert_eq!(
+            parse_kmer_dump_line("ACGTA\t12")?,
+            Some((&b"ACGTA"[..], Some(12)))
+        );
+        assert_eq!(parse_kmer_dump_line("ACGTA")?, Some((&b"ACGTA"[..], None)));
+        assert!(parse_kmer_dump_line("ACGTA twelve").is_err());
+        assert!(parse_kmer_dump_line("ACGTA 12 3").is_err());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,768 @@
+//! Persistent kmer index: build once, query many times
+//!
+//! The binary file is made of a fixed-size header followed by the labels, the reference records,
+//! the spaced seeds, the packed kmers and, for a colored index (several labelled kmer sets or
+//! tracked origins), the color of each kmer:
+//!
+//! | field            | type          | content                                     |
+//! |------------------|---------------|---------------------------------------------|
+//! | magic            | 8 bytes       | `B2SINDEX`                                  |
+//! | version          | u32           | [INDEX_VERSION]                             |
+//! | kmer_size        | u32           | size of the indexed kmers                   |
+//! | flags            | u32           | bit 0: stranded, bit 2: amino acids         |
+//! | bytes_per_kmer   | u32           | see [Kmer::BYTES]                           |
+//! | nb_kmers         | u64           | number of kmers                             |
+//! | nb_labels        | u32           | number of labels (1 if not colored)         |
+//! | nb_colors        | u32           | number of colors (0 if not colored)         |
+//! | nb_records       | u32           | number of records (0 if not tracked)        |
+//! | nb_seeds         | u32           | number of spaced seeds (0 if none)          |
+//! | sampling         | 3 * u32       | kind and parameters of the kmer sampling, see [Sampling::to_header()] |
+//! | low complexity   | 2 * u32       | metric and threshold of the low complexity filter, see [LowComplexity::to_header()] |
+//! | labels           | nb_labels * (u32 length + utf-8 string)   | name of each label  |
+//! | records          | nb_records * (u32 label + u32 length + utf-8 string) | label and name of each record |
+//! | seeds            | nb_seeds * (u32 length + ascii string)  | mask of each spaced seed |
+//! | kmers            | nb_kmers * bytes_per_kmer | sorted, see [Kmer::write_le_bytes()] |
+//! | kmer colors      | nb_kmers * u32 | color of each kmer (colored index only)    |
+//! | colors           | nb_colors * (u32 length + length * u32) | targets of each color |
+//!
+//! The targets of a color are record ids if the origins are tracked, label ids otherwise.
+//! With spaced seeds, the kmers are the keys of the kmers for each seed (see [crate::spaced_seeds::SpacedSeeds]).
+//! With a sampling, only the sampled kmers are stored (see [Sampling]).
+//! With a low complexity filter, the low complexity kmers are not stored (see [LowComplexity]).
+//!
+//! All integers are little-endian. The kmers are sorted, so that the same kmers always give the
+//! same file.
+//!
+//! The file is memory mapped, and its kmers are looked up in the mapped file by a binary search
+//! (see [MappedKmerSet]): only the counters of the kmers are allocated when it is loaded.
+
+/* std use */
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::marker::PhantomData;
+
+/* crates use */
+use anyhow::Context as _;
+
+/* project use */
+use crate::alphabet::Alphabet;
+use crate::colors::KmerColors;
+use crate::kmer::Kmer;
+use crate::kmer_counter::KmerCounter;
+use crate::kmer_hash::IndexParameters;
+use crate::kmer_index::KmerIndex;
+use crate::low_complexity::LowComplexity;
+use crate::sampling::Sampling;
+
+/// magic number at the start of an index file
+pub const INDEX_MAGIC: &[u8; 8] = b"B2SINDEX";
+
+/// version of the index file format
+pub const INDEX_VERSION: u32 = 8;
+
+const HEADER_SIZE: usize = 68;
+const FLAG_STRANDED: u32 = 1;
+const FLAG_PROTEIN: u32 = 4;
+
+/// Parameters used for building an index
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexHeader {
+    /// size of the indexed kmers
+    pub kmer_size: usize,
+    /// original kmer strand (true) or canonical kmers (false)
+    pub stranded: bool,
+    /// filter of the low complexity kmers, that were not indexed (None if all kmers are indexed)
+    pub low_complexity: Option<LowComplexity>,
+    /// alphabet of the kmers
+    pub alphabet: Alphabet,
+    /// number of indexed kmers
+    pub nb_kmers: usize,
+    /// number of labels (1 if the index is not colored)
+    pub nb_labels: usize,
+    /// number of colors (0 if the index is not colored)
+    pub nb_colors: usize,
+    /// number of reference records (0 if the origin of the kmers is not tracked)
+    pub nb_records: usize,
+    /// number of spaced seeds (0 if the kmers are contiguous)
+    pub nb_seeds: usize,
+    /// sampling of the indexed kmers (None if all kmers are indexed)
+    pub sampling: Option<Sampling>,
+}
+
+impl IndexHeader {
+    /// true if the kmers have a color: several labels, or tracked origins (see [KmerColors])
+    pub fn is_colored(&self) -> bool {
+        self.nb_labels > 1 || self.nb_records > 0
+    }
+
+    fn to_bytes<K: Kmer>(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.stranded {
+            flags |= FLAG_STRANDED;
+        }
+        if self.alphabet == Alphabet::Protein {
+            flags |= FLAG_PROTEIN;
+        }
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        bytes.extend_from_slice(INDEX_MAGIC);
+        bytes.extend_from_slice(&INDEX_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.kmer_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&flags.to_le_bytes());
+        bytes.extend_from_slice(&(K::BYTES as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.nb_kmers as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.nb_labels as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.nb_colors as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.nb_records as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.nb_seeds as u32).to_le_bytes());
+        let (kind, first, second) = Sampling::to_header(self.sampling.as_ref());
+        [kind, first, second]
+            .iter()
+            .for_each(|value| bytes.extend_from_slice(&value.to_le_bytes()));
+        let (metric, threshold) = LowComplexity::to_header(self.low_complexity.as_ref());
+        [metric, threshold]
+            .iter()
+            .for_each(|value| bytes.extend_from_slice(&value.to_le_bytes()));
+        bytes
+    }
+
+    fn from_bytes<K: Kmer>(input: &mut Input) -> anyhow::Result<Self> {
+        if input.data.len() < HEADER_SIZE || input.bytes(8)? != INDEX_MAGIC {
+            anyhow::bail!("not a back_to_sequences index file");
+        }
+        let version = input.u32()?;
+        if version != INDEX_VERSION {
+            anyhow::bail!(
+                "unsupported index version {} (expected {})",
+                version,
+                INDEX_VERSION
+            );
+        }
+        let kmer_size = input.u32()? as usize;
+        let flags = input.u32()?;
+        if input.u32()? as usize != K::BYTES {
+            anyhow::bail!("corrupted index file (invalid kmer encoding)");
+        }
+        Ok(Self {
+            kmer_size,
+            stranded: flags & FLAG_STRANDED != 0,
+            alphabet: if flags & FLAG_PROTEIN != 0 {
+                Alphabet::Protein
+            } else {
+                Alphabet::Dna
+            },
+            nb_kmers: u64::from_le_bytes(input.bytes(8)?.try_into().unwrap()) as usize,
+            nb_labels: input.u32()? as usize,
+            nb_colors: input.u32()? as usize,
+            nb_records: input.u32()? as usize,
+            nb_seeds: input.u32()? as usize,
+            sampling: Sampling::from_header(input.u32()?, input.u32()?, input.u32()?)?,
+            low_complexity: LowComplexity::from_header(input.u32()?, input.u32()?)?,
+        })
+    }
+}
+
+/// Sequential reader on the (memory mapped) content of an index file
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let Some(bytes) = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.pos..end))
+        else {
+            anyhow::bail!("truncated or corrupted index file");
+        };
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// check that `count` items of at least `size` bytes may remain in the file (the counts of the
+    /// header are not trusted), returns `count`
+    fn count(&self, count: usize, size: usize) -> anyhow::Result<usize> {
+        if count
+            .checked_mul(size)
+            .is_none_or(|len| len > self.data.len() - self.pos)
+        {
+            anyhow::bail!("truncated or corrupted index file");
+        }
+        Ok(count)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
+
+/// write the kmers of an index, with their counters (and their labels and origins if the index is
+/// colored) into a binary file, with the `parameters` they were indexed with
+pub fn write_index<'a, K: Kmer, T: KmerCounter + 'a>(
+    file_name: &str,
+    kmers: impl IntoIterator<Item = (K, &'a T)>,
+    colors: Option<&KmerColors>,
+    parameters: &IndexParameters<'_>,
+) -> anyhow::Result<()> {
+    let IndexParameters {
+        kmer_size,
+        stranded,
+        low_complexity,
+        seeds,
+        sampling,
+        alphabet,
+        ..
+    } = *parameters;
+    let mut kmers = kmers.into_iter().collect::<Vec<_>>();
+    kmers.sort_unstable_by_key(|(kmer, _)| *kmer);
+    let header = IndexHeader {
+        kmer_size,
+        stranded,
+        alphabet,
+        nb_kmers: kmers.len(),
+        nb_labels: colors.map_or(1, |colors| colors.labels.len()),
+        nb_colors: colors.map_or(0, |colors| colors.table.len()),
+        nb_records: colors.map_or(0, |colors| colors.records.len()),
+        nb_seeds: seeds.map_or(0, |seeds| seeds.len()),
+        sampling: sampling.copied(),
+        low_complexity: low_complexity.copied(),
+    };
+
+    (|| -> std::io::Result<_> {
+        let mut output = BufWriter::new(File::create(file_name)?);
+        output.write_all(&header.to_bytes::<K>())?;
+
+        let no_label = [String::new()];
+        let labels = colors.map_or(&no_label[..], |colors| &colors.labels);
+        for label in labels {
+            output.write_all(&(label.len() as u32).to_le_bytes())?;
+            output.write_all(label.as_bytes())?;
+        }
+        if let Some(colors) = colors {
+            for (record, label) in colors.records.iter().zip(&colors.record_labels) {
+                output.write_all(&label.to_le_bytes())?;
+                output.write_all(&(record.len() as u32).to_le_bytes())?;
+                output.write_all(record.as_bytes())?;
+            }
+        }
+        for mask in seeds.map_or(&[][..], |seeds| seeds.masks()) {
+            output.write_all(&(mask.len() as u32).to_le_bytes())?;
+            output.write_all(mask.as_bytes())?;
+        }
+
+        let mut buf = Vec::with_capacity(K::BYTES);
+        for (kmer, _) in &kmers {
+            buf.clear();
+            kmer.write_le_bytes(&mut buf);
+            output.write_all(&buf)?;
+        }
+
+        if let Some(colors) = colors {
+            // same order as the kmers, the color of a kmer is stored with its counter
+            for (_, counter) in &kmers {
+                let color = counter
+                    .color()
+                    .expect("the counters of labelled kmers store their color");
+                output.write_all(&color.to_le_bytes())?;
+            }
+            for color in 0..colors.table.len() {
+                let targets = colors.table.targets(color as u32);
+                output.write_all(&(targets.len() as u32).to_le_bytes())?;
+                for target in targets {
+                    output.write_all(&target.to_le_bytes())?;
+                }
+            }
+        }
+        output.flush()
+    })()
+    .with_context(|| format!("Error writing the index file {}", file_name))
+}
+
+/// read the header of a binary index file, with the parameters the index was built with
+pub fn read_header<K: Kmer>(file_name: &str) -> anyhow::Result<IndexHeader> {
+    let mut data = Vec::with_capacity(HEADER_SIZE);
+    File::open(file_name)
+        .with_context(|| format!("Error opening the index file {}", file_name))?
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut data)
+        .with_context(|| format!("Error reading the index file {}", file_name))?;
+    IndexHeader::from_bytes::<K>(&mut Input {
+        data: &data,
+        pos: 0,
+    })
+    .with_context(|| format!("Error reading the index file {}", file_name))
+}
+
+/// Kmers of an index file, looked up in the memory mapped file, with their counters
+///
+/// The kmers of the file are sorted (see [write_index()]): they are found by a binary search, and
+/// only their counters (that may store their color, see [crate::colors::Colored]) are in memory.
+pub struct MappedKmerSet<K, T> {
+    data: memmap2::Mmap,
+    /// position of the kmers in `data`
+    start: usize,
+    /// counter of each kmer, in the order of the kmers
+    counters: Vec<T>,
+    kmer: PhantomData<K>,
+}
+
+impl<K: Kmer, T> MappedKmerSet<K, T> {
+    /// kmer at a position
+    #[inline]
+    fn kmer(&self, i: usize) -> K {
+        let start = self.start + i * K::BYTES;
+        K::from_le_bytes(&self.data[start..start + K::BYTES])
+    }
+
+    /// counter of a kmer, if it is indexed
+    #[inline]
+    pub fn get(&self, kmer: &K) -> Option<&T> {
+        let (mut low, mut high) = (0, self.counters.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.kmer(mid).cmp(kmer) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(&self.counters[mid]),
+            }
+        }
+        None
+    }
+
+    /// the kmers, sorted, with their counters
+    pub fn iter(&self) -> impl Iterator<Item = (K, &T)> + '_ {
+        self.counters
+            .iter()
+            .enumerate()
+            .map(|(i, counter)| (self.kmer(i), counter))
+    }
+
+    /// number of kmers
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// true if there is no kmer
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+}
+
+impl<K: Kmer, T: KmerCounter> KmerIndex<K, T> for MappedKmerSet<K, T> {
+    #[inline]
+    fn find(&self, kmer: &K) -> Option<Option<&T>> {
+        self.get(kmer).map(Some)
+    }
+
+    fn nb_kmers(&self) -> usize {
+        self.len()
+    }
+}
+
+/// load the kmers of a binary index file
+///
+/// fails if the index was not built with the same `kmer_size`, `stranded`, `seeds`, `sampling`
+/// and `alphabet` parameters (the other `parameters` are not checked)
+/// returns the kmers, looked up in the mapped file, with their counters initialized to 0, and the
+/// labels (and origins) of each kmer if the index is colored
+#[allow(clippy::type_complexity)]
+pub fn load_index<K: Kmer, T: KmerCounter>(
+    file_name: &str,
+    parameters: &IndexParameters<'_>,
+) -> anyhow::Result<(MappedKmerSet<K, T>, Option<KmerColors>, IndexHeader)> {
+    let IndexParameters {
+        kmer_size,
+        stranded,
+        seeds,
+        sampling,
+        alphabet,
+        ..
+    } = *parameters;
+    let file = File::open(file_name)
+        .with_context(|| format!("Error opening the index file {}", file_name))?;
+    // SAFETY: the file is only read, it must not be modified by another process while it is used
+    let data = unsafe { memmap2::Mmap::map(&file) }
+        .with_context(|| format!("Error mapping the index file {}", file_name))?;
+    let mut input = Input {
+        data: &data,
+        pos: 0,
+    };
+
+    let header = IndexHeader::from_bytes::<K>(&mut input)
+        .with_context(|| format!("Error reading the index file {}", file_name))?;
+    if header.kmer_size != kmer_size {
+        anyhow::bail!(
+            "the index file {} was built with kmers of size {}, not {} (see --kmer-size)",
+            file_name,
+            header.kmer_size,
+            kmer_size
+        );
+    }
+    if header.stranded != stranded {
+        anyhow::bail!(
+            "the index file {} was built {} the --stranded option",
+            file_name,
+            if header.stranded { "with" } else { "without" }
+        );
+    }
+    if header.alphabet != alphabet {
+        anyhow::bail!(
+            "the index file {} was built with {} kmers, not {} kmers (see --alphabet)",
+            file_name,
+            header.alphabet,
+            alphabet
+        );
+    }
+    if header.sampling.as_ref() != sampling {
+        let describe = |sampling: Option<&Sampling>| {
+            sampling.map_or("all kmers".to_string(), |s| s.to_string())
+        };
+        anyhow::bail!(
+            "the index file {} was built with {}, not {} (see --minimizers and --syncmers)",
+            file_name,
+            describe(header.sampling.as_ref()),
+            describe(sampling)
+        );
+    }
+
+    (|| -> anyhow::Result<_> {
+        // each label, record and seed is at least made of its length
+        let mut labels = Vec::with_capacity(input.count(header.nb_labels, 4)?);
+        for _ in 0..header.nb_labels {
+            let len = input.u32()? as usize;
+            labels.push(String::from_utf8(input.bytes(len)?.to_vec())?);
+        }
+        let mut records = Vec::with_capacity(input.count(header.nb_records, 8)?);
+        for _ in 0..header.nb_records {
+            let label = input.u32()?;
+            if label as usize >= header.nb_labels {
+                anyhow::bail!("corrupted index file (invalid label of a record)");
+            }
+            let len = input.u32()? as usize;
+            records.push((String::from_utf8(input.bytes(len)?.to_vec())?, label));
+        }
+        let mut masks = Vec::with_capacity(input.count(header.nb_seeds, 4)?);
+        for _ in 0..header.nb_seeds {
+            let len = input.u32()? as usize;
+            masks.push(String::from_utf8(input.bytes(len)?.to_vec())?);
+        }
+        if masks != seeds.map_or(&[][..], |seeds| seeds.masks()) {
+            anyhow::bail!(
+                "the index was built with the spaced seeds [{}], not [{}] (see --spaced-seed)",
+                masks.join(", "),
+                seeds.map_or(String::new(), |seeds| seeds.masks().join(", "))
+            );
+        }
+
+        let start = input.pos;
+        let kmers = input.bytes(input.count(header.nb_kmers, K::BYTES)? * K::BYTES)?;
+        // the kmers are searched by a binary search
+        let mut previous = None;
+        for kmer in kmers.chunks_exact(K::BYTES) {
+            let kmer = K::from_le_bytes(kmer);
+            if previous
+                .replace(kmer)
+                .is_some_and(|previous| previous >= kmer)
+            {
+                anyhow::bail!("corrupted index file (unsorted kmers)");
+            }
+        }
+        let mut counters = Vec::with_capacity(header.nb_kmers);
+        let colors = if header.is_colored() {
+            assert!(
+                T::default().color().is_some(),
+                "the counters of labelled kmers store their color"
+            );
+            let mut colors = KmerColors::new(labels);
+            for (record, label) in records {
+                colors.add_record(record, label);
+            }
+            let kmer_colors = input.bytes(input.count(header.nb_kmers, 4)? * 4)?;
+            for color in kmer_colors.chunks_exact(4) {
+                let color = u32::from_le_bytes(color.try_into().unwrap());
+                if color as usize >= header.nb_colors {
+                    anyhow::bail!("corrupted index file (invalid color of a kmer)");
+                }
+                // the color of a kmer is stored with its counter
+                let mut counter = T::default();
+                counter.set_color(color);
+                counters.push(counter);
+            }
+            // the targets are records if the origins are tracked, labels otherwise
+            let nb_targets = match header.nb_records {
+                0 => header.nb_labels,
+                nb_records => nb_records,
+            };
+            for color in 0..input.count(header.nb_colors, 4)? {
+                let len = input.u32()? as usize;
+                let targets = (0..input.count(len, 4)?)
+                    .map(|_| input.u32())
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                if targets.iter().any(|target| *target as usize >= nb_targets)
+                    || colors.table.get_or_insert(&targets) as usize != color
+                {
+                    anyhow::bail!("corrupted index file (invalid targets of a color)");
+                }
+            }
+            Some(colors)
+        } else {
+            counters.resize_with(header.nb_kmers, T::default);
+            None
+        };
+
+        if input.pos != input.data.len() {
+            anyhow::bail!("corrupted index file (unexpected trailing data)");
+        }
+        Ok((start, counters, colors))
+    })()
+    .with_context(|| format!("Error reading the index file {}", file_name))
+    .map(|(start, counters, colors)| {
+        eprintln!(
+            "Loaded {} kmers, each of size {}, from index {}",
+            counters.len(),
+            kmer_size,
+            file_name
+        );
+        let kmer_set = MappedKmerSet {
+            data,
+            start,
+            counters,
+            kmer: PhantomData,
+        };
+        (kmer_set, colors, header)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    /* crate use */
+    use biotest::Format as _;
+
+    /* project use */
+    use super::*;
+    use crate::colors::Colored;
+    use crate::spaced_seeds::SpacedSeeds;
+
+    #[test]
+    fn write_and_load() -> anyhow::Result<()> {
+        let mut rng = biotest::rand();
+        let k_generate = biotest::Fasta::builder().sequence_len(40).build()?;
+
+        let temp_dir = tempfile::tempdir()?;
+        let kmers_in_path = temp_dir.path().join("kmers_in.fasta");
+        let index_path = temp_dir.path().join("kmers.idx");
+        let index_path = index_path.to_str().unwrap();
+
+        k_generate.create(&kmers_in_path, &mut rng, 50)?;
+
+        let (kmer_set, _) = crate::kmer_hash::index_kmers::<u128, atomic_counter::RelaxedCounter>(
+            kmers_in_path.display().to_string(),
+            35,
+            false,
+            Some(&LowComplexity::default()),
+        )?;
+
+        let low_complexity = LowComplexity::default();
+        let parameters = IndexParameters {
+            low_complexity: Some(&low_complexity),
+            ..IndexParameters::new(35, false)
+        };
+        write_index(
+            index_path,
+            kmer_set.iter().map(|(kmer, counter)| (*kmer, counter)),
+            None,
+            &parameters,
+        )?;
+
+        let load = |parameters: &IndexParameters<'_>| {
+            load_index::<u128, atomic_counter::RelaxedCounter>(index_path, parameters)
+        };
+        let (loaded, colors, header) = load(&IndexParameters::new(35, false))?;
+        assert_eq!(
+            header,
+            IndexHeader {
+                kmer_size: 35,
+                stranded: false,
+                low_complexity: Some(LowComplexity::default()),
+                alphabet: Alphabet::Dna,
+                nb_kmers: kmer_set.len(),
+                nb_labels: 1,
+                nb_colors: 0,
+                nb_records: 0,
+                nb_seeds: 0,
+                sampling: None,
+            }
+        );
+        assert!(colors.is_none());
+        let mut expected = kmer_set.keys().copied().collect::<Vec<_>>();
+        expected.sort_unstable();
+        assert_eq!(
+            loaded.iter().map(|(kmer, _)| kmer).collect::<Vec<_>>(),
+            expected
+        );
+        // the kmers are looked up in the mapped file
+        assert!(expected.iter().all(|kmer| loaded.get(kmer).is_some()));
+        assert!(loaded.get(&u128::MAX).is_none());
+
+        // mismatched parameters
+        assert!(load(&IndexParameters::new(33, false)).is_err());
+        assert!(load(&IndexParameters::new(35, true)).is_err());
+        let seeds = SpacedSeeds::new(&["1".repeat(34) + "0"], 35)?;
+        assert!(load(&IndexParameters {
+            seeds: Some(&seeds),
+            ..IndexParameters::new(35, false)
+        })
+        .is_err());
+        let sampling = Sampling::minimizers(10)?;
+        assert!(load(&IndexParameters {
+            sampling: Some(&sampling),
+            ..IndexParameters::new(35, false)
+        })
+        .is_err());
+        assert!(load(&IndexParameters {
+            alphabet: Alphabet::Protein,
+            ..IndexParameters::new(35, false)
+        })
+        .is_err());
+
+        // not an index
+        assert!(load_index::<u128, atomic_counter::RelaxedCounter>(
+            kmers_in_path.to_str().unwrap(),
+            &IndexParameters::new(35, false),
+        )
+        .is_err());
+
+        // the counts of a corrupted header are not trusted: neither the number of kmers, nor the
+        // number of labels
+        let data = std::fs::read(index_path)?;
+        for (range, count) in [
+            (24..32, u64::MAX),
+            (24..32, u64::MAX / 2),
+            (32..36, u32::MAX as u64),
+        ] {
+            let mut corrupted = data.clone();
+            corrupted[range.clone()].copy_from_slice(&count.to_le_bytes()[..range.len()]);
+            std::fs::write(index_path, corrupted)?;
+            let error = load(&IndexParameters::new(35, false))
+                .err()
+                .map(|error| format!("{:#}", error));
+            assert!(error.is_some_and(|error| error.ends_with("truncated or corrupted index file")));
+        }
+
+        // the kmers end the file, they are searched by a binary search
+        let mut corrupted = data.clone();
+        let start = data.len() - expected.len() * u128::BYTES;
+        corrupted[start..start + 2 * u128::BYTES].rotate_left(u128::BYTES);
+        std::fs::write(index_path, corrupted)?;
+        let error = load(&IndexParameters::new(35, false))
+            .err()
+            .map(|error| format!("{:#}", error));
+        assert!(error.is_some_and(|error| error.ends_with("corrupted index file (unsorted kmers)")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_and_load_colored() -> anyhow::Result<()> {
+        let mut rng = biotest::rand();
+        let k_generate = biotest::Fasta::builder().sequence_len(20).build()?;
+
+        let temp_dir = tempfile::tempdir()?;
+        let host_path = temp_dir.path().join("host.fasta");
+        let virus_path = temp_dir.path().join("virus.fasta");
+        let index_path = temp_dir.path().join("kmers.idx");
+        let index_path = index_path.to_str().unwrap();
+
+        // the virus set shares all the kmers of the host set
+        let mut data = vec![];
+        k_generate.records(&mut data, &mut rng, 20)?;
+        std::fs::write(&host_path, &data)?;
+        k_generate.records(&mut data, &mut rng, 20)?;
+        std::fs::write(&virus_path, &data)?;
+
+        let parameters = IndexParameters {
+            track_origins: true,
+            ..IndexParameters::new(15, true)
+        };
+        let ((kmer_set, colors), _) =
+            crate::kmer_hash::index_labelled_kmers::<u64, Colored<atomic_counter::RelaxedCounter>>(
+                &[
+                    ("host".to_string(), host_path.display().to_string()),
+                    ("virus".to_string(), virus_path.display().to_string()),
+                ],
+                &parameters,
+                None,
+                None,
+                None,
+            )?;
+        let colors = colors.unwrap();
+
+        write_index(
+            index_path,
+            kmer_set.iter().map(|(kmer, counter)| (*kmer, counter)),
+            Some(&colors),
+            &parameters,
+        )?;
+        // the index does not depend on the order of the hash map
+        let other_path = temp_dir.path().join("other.idx");
+        let mut reordered = ahash::AHashMap::with_capacity(4 * kmer_set.len());
+        reordered.extend(kmer_set.iter().map(|(kmer, counter)| {
+            let mut other = Colored::<atomic_counter::RelaxedCounter>::default();
+            other.set_color(counter.color().unwrap());
+            (*kmer, other)
+        }));
+        write_index(
+            other_path.to_str().unwrap(),
+            reordered.iter().map(|(kmer, counter)| (*kmer, counter)),
+            Some(&colors),
+            &parameters,
+        )?;
+        assert_eq!(std::fs::read(index_path)?, std::fs::read(&other_path)?);
+
+        let (loaded, loaded_colors, header) =
+            load_index::<u64, Colored<atomic_counter::RelaxedCounter>>(index_path, &parameters)?;
+        let loaded_colors = loaded_colors.unwrap();
+
+        assert_eq!(header.nb_labels, 2);
+        assert_eq!(header.nb_colors, colors.table.len());
+        assert_eq!(header.nb_records, 60);
+        assert_eq!(loaded_colors.labels, colors.labels);
+        assert_eq!(loaded_colors.records, colors.records);
+        assert_eq!(loaded_colors.record_labels, colors.record_labels);
+        assert_eq!(loaded.len(), kmer_set.len());
+        for (kmer, counter) in kmer_set.iter() {
+            let loaded_counter = loaded.get(kmer).unwrap();
+            assert_eq!(loaded_colors.get(loaded_counter), colors.get(counter));
+        }
+
+        // the labels of the records, the colors of the kmers and the targets of the colors are
+        // checked: the label of the first record follows the header and the labels ("host" and
+        // "virus"), the table of the colors ends the file, after the colors of the kmers
+        let data = std::fs::read(index_path)?;
+        let colors_start = data.len()
+            - (0..colors.table.len())
+                .map(|color| 4 + 4 * colors.table.targets(color as u32).len())
+                .sum::<usize>();
+        for (pos, value, error) in [
+            (HEADER_SIZE + 17, 2, "invalid label of a record"),
+            (
+                colors_start - 4 * kmer_set.len(),
+                colors.table.len() as u32,
+                "invalid color of a kmer",
+            ),
+            (colors_start + 4, 60, "invalid targets of a color"),
+        ] {
+            let mut corrupted = data.clone();
+            corrupted[pos..pos + 4].copy_from_slice(&u32::to_le_bytes(value));
+            std::fs::write(index_path, corrupted)?;
+            let error_message =
+                load_index::<u64, Colored<atomic_counter::RelaxedCounter>>(index_path, &parameters)
+                    .err()
+                    .map(|error| format!("{:#}", error));
+            assert!(error_message.is_some_and(|message| message.contains(error)));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Statistics of the indexing of the kmers
+//!
+//! Counts what happened to the kmers of the `--in-kmers` files: how many were indexed, and how
+//! many were skipped (non ACGT letters, low complexity, ...) or merged with an already indexed
+//! kmer. They are printed as a summary, and may be written in a JSON file (see `--index-stats`),
+//! with the number of kmers indexed from each record of the files.
+
+/* std use */
+use std::io::Write as _;
+
+/* crates use */
+use anyhow::Context as _;
+
+/* project use */
+
+/// Number of kmers indexed from a record of an `--in-kmers` file (or from a whole kmer dump)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordStats {
+    /// file of the record
+    pub file: String,
+    /// name of the record (first word of its id), or label of a kmer dump
+    pub name: String,
+    /// number of kmers (or spaced seed keys) of the record inserted in the index
+    pub kmers: usize,
+}
+
+/// Statistics of the indexing of the kmers, see [crate::kmer_hash::index_labelled_kmers()]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    /// number of sequences read (the kmers of the dumps being sequences of their own)
+    pub sequences: usize,
+    /// number of sequences shorter than the kmer size
+    pub short_sequences: usize,
+    /// number of kmers of the sequences (ie. positions of a kmer)
+    pub kmers: usize,
+    /// number of kmers skipped because they contain a non ACGT letter (or a non standard amino acid)
+    pub non_acgt: usize,
+    /// number of kmers skipped because they are not sampled (see `--minimizers` and `--syncmers`)
+    pub unsampled: usize,
+    /// number of kmers skipped because they are of low complexity (see `--no-low-complexity`)
+    pub low_complexity: usize,
+    /// number of kmers of the dumps skipped because they are less abundant than `--min-abundance`
+    pub low_abundance: usize,
+    /// number of kmers (or spaced seed keys, or expanded degenerate kmers) inserted in the index
+    pub inserted: usize,
+    /// number of inserted kmers replaced by their reverse complement (canonical kmers), which may
+    /// collide with the kmers of the other strand
+    pub reverse_complements: usize,
+    /// number of distinct kmers of the index
+    pub distinct: usize,
+    /// kmers indexed from each record, in the order of the files (if they are tracked)
+    pub records: Option<Vec<RecordStats>>,
+}
+
+impl IndexStats {
+    /// empty statistics, tracking the kmers indexed from each record if `track_records` is set
+    pub fn new(track_records: bool) -> Self {
+        Self {
+            records: track_records.then(Vec::new),
+            ..Default::default()
+        }
+    }
+
+    /// number of inserted kmers that were already indexed
+    pub fn duplicates(&self) -> usize {
+        self.inserted.saturating_sub(self.distinct)
+    }
+
+    /// add the counts of other statistics (but not their records)
+    pub fn add(&mut self, other: &Self) {
+        self.sequences += other.sequences;
+        self.short_sequences += other.short_sequences;
+        self.kmers += other.kmers;
+        self.non_acgt += other.non_acgt;
+        self.unsampled += other.unsampled;
+        self.low_complexity += other.low_complexity;
+        self.low_abundance += other.low_abundance;
+        self.inserted += other.inserted;
+        self.reverse_complements += other.reverse_complements;
+        self.distinct += other.distinct;
+    }
+
+    /// count the kmers indexed from a record, if the records are tracked
+    pub fn add_record(&mut self, file: &str, name: &str, kmers: usize) {
+        if let Some(records) = self.records.as_mut() {
+            records.push(RecordStats {
+                file: file.to_string(),
+                name: name.to_string(),
+                kmers,
+            });
+        }
+    }
+
+    /// print a summary of the statistics on stdout
+    pub fn print_summary(&self) {
+        println!(
+            "Number of indexed sequences {}, {} shorter than the kmer size",
+            self.sequences, self.short_sequences
+        );
+        println!(
+            "Number of kmers read {}, skipped: {} with a non ACGT letter, {} not sampled, {} of low complexity, {} of low abundance",
+            self.kmers, self.non_acgt, self.unsampled, self.low_complexity, self.low_abundance
+        );
+        println!(
+            "Number of kmers inserted {} ({} as their reverse complement), {} duplicates, {} distinct kmers",
+            self.inserted,
+            self.reverse_complements,
+            self.duplicates(),
+            self.distinct
+        );
+    }
+
+    /// statistics in JSON format
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\n  \"sequences\": {},\n  \"short_sequences\": {},\n  \"kmers\": {},\n  \"non_acgt_kmers\": {},\n  \
+             \"unsampled_kmers\": {},\n  \"low_complexity_kmers\": {},\n  \"low_abundance_kmers\": {},\n  \
+             \"inserted_kmers\": {},\n  \"reverse_complement_kmers\": {},\n  \"duplicate_kmers\": {},\n  \
+             \"distinct_kmers\": {}",
+            self.sequences,
+            self.short_sequences,
+            self.kmers,
+            self.non_acgt,
+            self.unsampled,
+            self.low_complexity,
+            self.low_abundance,
+            self.inserted,
+            self.reverse_complements,
+            self.duplicates(),
+            self.distinct
+        );
+        if let Some(records) = &self.records {
+            json.push_str(",\n  \"records\": [");
+            for (i, record) in records.iter().enumerate() {
+                json.push_str(if i == 0 { "\n" } else { ",\n" });
+                json.push_str(&format!(
+                    "    {{\"file\": {}, \"name\": {}, \"kmers\": {}}}",
+                    json_string(&record.file),
+                    json_string(&record.name),
+                    record.kmers
+                ));
+            }
+            json.push_str(if records.is_empty() { "]" } else { "\n  ]" });
+        }
+        json.push_str("\n}\n");
+        json
+    }
+
+    /// write the statistics in a JSON file
+    pub fn write_json(&self, file_name: &str) -> anyhow::Result<()> {
+        std::fs::File::create(file_name)
+            .and_then(|mut file| file.write_all(self.to_json().as_bytes()))
+            .with_context(|| format!("Error writing the indexing statistics in {}", file_name))
+    }
+}
+
+/// quoted JSON string
+fn json_string(string: &str) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    /* project use */
+    use super::*;
+
+    #[test]
+    fn index_stats_json() {
+        let mut stats = IndexStats::new(true);
+        stats.add(&IndexStats {
+            sequences: 2,
+            kmers: 10,
+            inserted: 8,
+            distinct: 6,
+            ..Default::default()
+        });
+        stats.add_record("genes.fa", "gene\"1", 8);
+        assert_eq!(stats.duplicates(), 2);
+        assert_eq!(
+            stats.to_json(),
+            "{
+  \"sequences\": 2,
+  \"short_sequences\": 0,
+  \"kmers\": 10,
+  \"non_acgt_kmers\": 0,
+  \"unsampled_kmers\": 0,
+  \"low_complexity_kmers\": 0,
+  \"low_abundance_kmers\": 0,
+  \"inserted_kmers\": 8,
+  \"reverse_complement_kmers\": 0,
+  \"duplicate_kmers\": 2,
+  \"distinct_kmers\": 6,
+  \"records\": [
+    {\"file\": \"genes.fa\", \"name\": \"gene\\\"1\", \"kmers\": 8}
+  ]
+}
+"
+        );
+
+        // the records are not tracked
+        stats.records = None;
+        stats.add_record("genes.fa", "gene2", 3);
+        assert!(stats.to_json().ends_with("\"distinct_kmers\": 6\n}\n"));
+    }
+}
@@ -0,0 +1,241 @@
+//! IUPAC degenerate kmers of the reference sequences
+//!
+//! A kmer containing degenerate nucleotides (eg. `R` for A or G, `N` for any nucleotide) stands
+//! for all the ACGT kmers obtained by replacing each degenerate nucleotide by one of its
+//! nucleotides. Such kmers are skipped by the kmer iterators, unless they are expanded into the
+//! ACGT kmers they stand for (up to a limit on their number, see [DegenerateKmers]). The expanded
+//! kmers keep a link to their degenerate kmer, so that their counts can be reported per
+//! degenerate kmer.
+
+/* std use */
+
+/* crates use */
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+
+/* project use */
+use crate::kmer::Kmer;
+
+/// nucleotides each IUPAC code (upper or lower case) stands for, bit i being set for the
+/// nucleotide of 2-bit code i (A=0, C=1, G=2, T=3), 0 for the other letters
+pub static IUPAC_MAP: [u8; 256] = {
+    const CODES: [(u8, u8); 16] = [
+        (b'A', 0b0001),
+        (b'C', 0b0010),
+        (b'G', 0b0100),
+        (b'T', 0b1000),
+        (b'U', 0b1000),
+        (b'R', 0b0101),
+        (b'Y', 0b1010),
+        (b'S', 0b0110),
+        (b'W', 0b1001),
+        (b'K', 0b1100),
+        (b'M', 0b0011),
+        (b'B', 0b1110),
+        (b'D', 0b1101),
+        (b'H', 0b1011),
+        (b'V', 0b0111),
+        (b'N', 0b1111),
+    ];
+    let mut map = [0; 256];
+    let mut i = 0;
+    while i < CODES.len() {
+        map[CODES[i].0 as usize] = CODES[i].1;
+        map[CODES[i].0.to_ascii_lowercase() as usize] = CODES[i].1;
+        i += 1;
+    }
+    map
+};
+
+/// Degenerate kmers of the reference sequences, with the kmers they were expanded into
+pub struct DegenerateKmers<K> {
+    /// largest number of kmers a degenerate kmer is expanded into
+    limit: usize,
+    /// each degenerate kmer (uppercase, in the orientation of its reference sequence) with its
+    /// expanded kmers (normalized as the indexed kmers)
+    pub probes: Vec<(Vec<u8>, Vec<K>)>,
+    /// id of each degenerate kmer
+    probe_ids: HashMap<Vec<u8>, usize>,
+    /// indexed kmers that are only expansions of degenerate kmers (not also ACGT kmers of the
+    /// reference sequences)
+    expanded: HashSet<K>,
+    /// number of degenerate kmers that were not expanded, standing for more than `limit` kmers
+    pub skipped: usize,
+}
+
+impl<K: Kmer> DegenerateKmers<K> {
+    /// Create an empty set of degenerate kmers, expanded into at most `limit` kmers each
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            probes: Vec::new(),
+            probe_ids: HashMap::new(),
+            expanded: HashSet::new(),
+            skipped: 0,
+        }
+    }
+
+    /// largest number of kmers a degenerate kmer is expanded into
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// expand the degenerate kmers of a (not normalized) sequence
+    ///
+    /// returns `(position, probe, forward, reverse)` for each ACGT kmer a degenerate kmer of the
+    /// sequence stands for, `probe` being the id of the degenerate kmer in [DegenerateKmers::probes].
+    /// The kmers are then registered with [DegenerateKmers::insert()] if they are indexed
+    pub fn expand(&mut self, seq: &[u8], kmer_size: usize) -> Vec<(usize, usize, K, K)> {
+        let mut result = Vec::new();
+        // number of letters of the current window standing for 1, 2, 3 or 4 nucleotides
+        let mut counts = [0u32; 5];
+        // start of the current run of IUPAC letters
+        let mut run_start = 0;
+        for end in 0..seq.len() {
+            let nucleotides = IUPAC_MAP[seq[end] as usize].count_ones() as usize;
+            if nucleotides == 0 {
+                counts = [0; 5];
+                run_start = end + 1;
+                continue;
+            }
+            counts[nucleotides] += 1;
+            if end + 1 < run_start + kmer_size {
+                continue;
+            }
+            let start = end + 1 - kmer_size;
+            if start > run_start {
+                counts[IUPAC_MAP[seq[start - 1] as usize].count_ones() as usize] -= 1;
+            }
+            if counts[2..].iter().all(|count| *count == 0) {
+                // ACGT kmer, not degenerate
+                continue;
+            }
+            let nb_kmers = (2..=4usize).try_fold(1usize, |acc, nucleotides| {
+                acc.checked_mul(nucleotides.checked_pow(counts[nucleotides])?)
+            });
+            if nb_kmers.is_none_or(|nb_kmers| nb_kmers > self.limit) {
+                self.skipped += 1;
+                continue;
+            }
+
+            let window = seq[start..=end].to_ascii_uppercase();
+            let probe = match self.probe_ids.get(&window) {
+                Some(probe) => *probe,
+                None => {
+                    self.probe_ids.insert(window.clone(), self.probes.len());
+                    self.probes.push((window.clone(), Vec::new()));
+                    self.probes.len() - 1
+                }
+            };
+            let kmers = window
+                .iter()
+                .fold(vec![(K::default(), K::default())], |kmers, letter| {
+                    let mask = IUPAC_MAP[*letter as usize];
+                    kmers
+                        .iter()
+                        .flat_map(|(forward, reverse)| {
+                            (0..4u8)
+                                .filter(move |code| mask & (1 << code) != 0)
+                                .map(move |code| {
+                                    (
+                                        forward.push(code, kmer_size),
+                                        reverse.push_rc(code, kmer_size),
+                                    )
+                                })
+                        })
+                        .collect()
+                });
+            result.extend(
+                kmers
+                    .into_iter()
+                    .map(|(forward, reverse)| (start, probe, forward, reverse)),
+            );
+        }
+        result
+    }
+
+    /// register an indexed kmer expanded from the degenerate kmer `probe`, `indexed` being true
+    /// if the kmer was already indexed
+    pub fn insert(&mut self, probe: usize, kmer: K, indexed: bool) {
+        let expansions = &mut self.probes[probe].1;
+        if !expansions.contains(&kmer) {
+            expansions.push(kmer);
+        }
+        if !indexed {
+            self.expanded.insert(kmer);
+        }
+    }
+
+    /// register an indexed ACGT kmer of the reference sequences, that is thus not only an
+    /// expansion of degenerate kmers
+    pub fn insert_acgt(&mut self, kmer: &K) {
+        self.expanded.remove(kmer);
+    }
+
+    /// true if an indexed kmer is only an expansion of degenerate kmers
+    pub fn is_expanded(&self, kmer: &K) -> bool {
+        self.expanded.contains(kmer)
+    }
+
+    /// total number of kmers the degenerate kmers were expanded into
+    pub fn nb_expansions(&self) -> usize {
+        self.probes.iter().map(|(_, kmers)| kmers.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /* project use */
+    use super::*;
+
+    #[test]
+    fn expand_degenerate_kmers() {
+        let mut degenerate = DegenerateKmers::<u64>::new(4);
+        let expanded = degenerate.expand(b"ACGTRTANNNCX", 4);
+
+        // the kmers with an R stand for 2 kmers, those with Ns for at least 8 kmers
+        assert_eq!(degenerate.skipped, 4);
+        let probes = degenerate
+            .probes
+            .iter()
+            .map(|(probe, _)| probe.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            probes,
+            [b"CGTR".to_vec(), b"GTRT".to_vec(), b"TRTA".to_vec()]
+        );
+        let kmers = expanded
+            .iter()
+            .map(|(position, probe, forward, reverse)| {
+                assert_eq!(reverse.rev_comp(4), *forward);
+                (*position, *probe, forward.to_bytes(4))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kmers,
+            [
+                (1, 0, b"CGTA".to_vec()),
+                (1, 0, b"CGTG".to_vec()),
+                (2, 1, b"GTAT".to_vec()),
+                (2, 1, b"GTGT".to_vec()),
+                (3, 2, b"TATA".to_vec()),
+                (3, 2, b"TGTA".to_vec()),
+            ]
+        );
+
+        // the expansions that are also ACGT kmers of the references are not only expansions
+        for (_, probe, forward, _) in &expanded {
+            degenerate.insert(*probe, *forward, false);
+        }
+        degenerate.insert_acgt(&u64::from_bytes(b"TATA").unwrap());
+        assert!(degenerate.is_expanded(&u64::from_bytes(b"GTAT").unwrap()));
+        assert!(!degenerate.is_expanded(&u64::from_bytes(b"TATA").unwrap()));
+        assert_eq!(degenerate.nb_expansions(), 6);
+
+        // the same degenerate kmer is reported once
+        let mut degenerate = DegenerateKmers::<u64>::new(16);
+        degenerate.expand(b"ANNA", 4);
+        degenerate.expand(b"anna", 4);
+        assert_eq!(degenerate.probes.len(), 1);
+        assert_eq!(degenerate.skipped, 0);
+    }
+}
@@ -0,0 +1,429 @@
+//! Packed kmer representation
+//!
+//! Kmers are stored as integers, each nucleotide being encoded on 2 bits (A=0, C=1, G=2, T=3),
+//! the first nucleotide of the kmer in the most significant bits. With this encoding, the
+//! natural order of the integers is the lexicographic order of the kmers, thus the canonical
+//! kmer is simply the smallest of the forward and reverse complement values.
+//!
+//! - [u64] is used for kmers of size up to 32
+//! - [u128] is used for kmers of size up to 64
+//! - [LongKmer] is used for larger kmers
+
+/* std use */
+
+/* crates use */
+
+/* project use */
+use crate::consts::{DECODE_MAP, ENCODE_MAP};
+
+/// Integer type able to store a packed kmer
+pub trait Kmer:
+    Copy + Default + Eq + Ord + std::hash::Hash + std::fmt::Debug + Send + Sync + 'static
+{
+    /// largest kmer size that can be stored in this type
+    const MAX_KMER_SIZE: usize;
+
+    /// size of the binary representation (see [Kmer::write_le_bytes()])
+    const BYTES: usize;
+
+    /// append a nucleotide (2-bit code) at the end of a kmer of size `kmer_size`
+    ///
+    /// the first nucleotide of the kmer is dropped
+    fn push(self, code: u8, kmer_size: usize) -> Self;
+
+    /// prepend the complement of a nucleotide (2-bit code) at the start of a kmer of size `kmer_size`
+    ///
+    /// the last nucleotide of the kmer is dropped. This is the rolling update of the reverse
+    /// complement when [Kmer::push()] is applied on the forward kmer.
+    fn push_rc(self, code: u8, kmer_size: usize) -> Self;
+
+    /// 2-bit code of the nucleotide at `position` in a kmer of size `kmer_size`
+    fn code(&self, position: usize, kmer_size: usize) -> u8;
+
+    /// append the little-endian binary representation of the kmer ([Kmer::BYTES] bytes) to `out`
+    fn write_le_bytes(&self, out: &mut Vec<u8>);
+
+    /// decode a kmer from its little-endian binary representation
+    ///
+    /// panics if `bytes` is not [Kmer::BYTES] long
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+
+    /// encode an ascii kmer, returns None if it contains a non ACGT letter
+    fn from_bytes(kmer: &[u8]) -> Option<Self> {
+        kmer.iter()
+            .try_fold(Self::default(), |acc, c| match ENCODE_MAP[*c as usize] {
+                4 => None,
+                code => Some(acc.push(code, kmer.len())),
+            })
+    }
+
+    /// append the ascii (uppercase) representation of a kmer of size `kmer_size` to `out`
+    fn write_bytes(&self, kmer_size: usize, out: &mut Vec<u8>) {
+        out.extend((0..kmer_size).map(|i| DECODE_MAP[self.code(i, kmer_size) as usize]));
+    }
+
+    /// ascii (uppercase) representation of a kmer of size `kmer_size`
+    fn to_bytes(&self, kmer_size: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(kmer_size);
+        self.write_bytes(kmer_size, &mut out);
+        out
+    }
+
+    /// reverse complement of a kmer of size `kmer_size`
+    fn rev_comp(&self, kmer_size: usize) -> Self {
+        (0..kmer_size).fold(Self::default(), |acc, i| {
+            acc.push_rc(self.code(i, kmer_size), kmer_size)
+        })
+    }
+
+    /// number of positions where two kmers of size `kmer_size` have different nucleotides
+    fn hamming_distance(&self, other: &Self, kmer_size: usize) -> usize {
+        (0..kmer_size)
+            .filter(|i| self.code(*i, kmer_size) != other.code(*i, kmer_size))
+            .count()
+    }
+
+    /// hash of the packed kmer, identical across runs and platforms (used to sample the kmers)
+    fn hash64(&self) -> u64;
+
+    /// sub-kmer made of the nucleotides at `positions` of a kmer of size `kmer_size`
+    fn segment(&self, positions: std::ops::Range<usize>, kmer_size: usize) -> Self {
+        let segment_size = positions.len();
+        positions.fold(Self::default(), |acc, i| {
+            acc.push(self.code(i, kmer_size), segment_size)
+        })
+    }
+}
+
+macro_rules! impl_kmer_for_uint {
+    ($t:ty) => {
+        impl Kmer for $t {
+            const MAX_KMER_SIZE: usize = <$t>::BITS as usize / 2;
+            const BYTES: usize = std::mem::size_of::<$t>();
+
+            #[inline]
+            fn push(self, code: u8, kmer_size: usize) -> Self {
+                let mask = <$t>::MAX
+                    .checked_shr(<$t>::BITS - 2 * kmer_size as u32)
+                    .unwrap_or(0);
+                ((self << 2) | code as $t) & mask
+            }
+
+            #[inline]
+            fn push_rc(self, code: u8, kmer_size: usize) -> Self {
+                (self >> 2) | (((3 - code) as $t) << (2 * (kmer_size - 1)))
+            }
+
+            #[inline]
+            fn code(&self, position: usize, kmer_size: usize) -> u8 {
+                ((self >> (2 * (kmer_size - 1 - position))) & 3) as u8
+            }
+
+            #[inline]
+            fn hamming_distance(&self, other: &Self, _kmer_size: usize) -> usize {
+                // one bit set (the lowest of the 2 bits) for each different nucleotide
+                let diff = self ^ other;
+                ((diff | (diff >> 1)) & (<$t>::MAX / 3)).count_ones() as usize
+            }
+
+            #[inline]
+            fn segment(&self, positions: std::ops::Range<usize>, kmer_size: usize) -> Self {
+                let mask = <$t>::MAX
+                    .checked_shr(<$t>::BITS - 2 * positions.len() as u32)
+                    .unwrap_or(0);
+                (self >> (2 * (kmer_size - positions.end))) & mask
+            }
+
+            #[inline]
+            fn hash64(&self) -> u64 {
+                // fold the 64-bit words of the kmer, starting with the least significant one
+                self.to_le_bytes().chunks_exact(8).fold(0, |acc, word| {
+                    mix64(acc ^ u64::from_le_bytes(word.try_into().unwrap()))
+                })
+            }
+
+            fn write_le_bytes(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn from_le_bytes(bytes: &[u8]) -> Self {
+                <$t>::from_le_bytes(bytes.try_into().expect("invalid kmer length"))
+            }
+        }
+    };
+}
+
+impl_kmer_for_uint!(u64);
+impl_kmer_for_uint!(u128);
+
+/// Packed kmer stored on several 64-bit words (the first word holds the most significant bits)
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct WideKmer<const N: usize>([u64; N]);
+
+/// Kmer type used for kmer sizes above 64
+pub type LongKmer = WideKmer<8>;
+
+impl<const N: usize> Default for WideKmer<N> {
+    fn default() -> Self {
+        Self([0; N])
+    }
+}
+
+impl<const N: usize> WideKmer<N> {
+    /// clear all bits above the 2*`kmer_size` least significant ones
+    #[inline]
+    fn mask(&mut self, kmer_size: usize) {
+        let bits = 2 * kmer_size;
+        for (w, word) in self.0.iter_mut().enumerate() {
+            let low = 64 * (N - 1 - w);
+            if bits <= low {
+                *word = 0;
+            } else if bits - low < 64 {
+                *word &= (1u64 << (bits - low)) - 1;
+            }
+        }
+    }
+}
+
+impl<const N: usize> Kmer for WideKmer<N> {
+    const MAX_KMER_SIZE: usize = 32 * N;
+    const BYTES: usize = 8 * N;
+
+    #[inline]
+    fn push(mut self, code: u8, kmer_size: usize) -> Self {
+        for w in 0..N {
+            let carry = if w + 1 < N { self.0[w + 1] >> 62 } else { 0 };
+            self.0[w] = (self.0[w] << 2) | carry;
+        }
+        self.0[N - 1] |= code as u64;
+        self.mask(kmer_size);
+        self
+    }
+
+    #[inline]
+    fn push_rc(mut self, code: u8, kmer_size: usize) -> Self {
+        for w in (0..N).rev() {
+            let carry = if w > 0 { self.0[w - 1] << 62 } else { 0 };
+            self.0[w] = (self.0[w] >> 2) | carry;
+        }
+        let bit = 2 * (kmer_size - 1);
+        self.0[N - 1 - bit / 64] |= ((3 - code) as u64) << (bit % 64);
+        self
+    }
+
+    #[inline]
+    fn code(&self, position: usize, kmer_size: usize) -> u8 {
+        let bit = 2 * (kmer_size - 1 - position);
+        ((self.0[N - 1 - bit / 64] >> (bit % 64)) & 3) as u8
+    }
+
+    #[inline]
+    fn hamming_distance(&self, other: &Self, _kmer_size: usize) -> usize {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(a, b)| a.hamming_distance(b, 32))
+            .sum()
+    }
+
+    #[inline]
+    fn hash64(&self) -> u64 {
+        self.0.iter().rev().fold(0, |acc, word| mix64(acc ^ word))
+    }
+
+    fn write_le_bytes(&self, out: &mut Vec<u8>) {
+        self.0
+            .iter()
+            .for_each(|word| out.extend_from_slice(&word.to_le_bytes()));
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        assert_eq!(bytes.len(), Self::BYTES, "invalid kmer length");
+        let mut kmer = Self::default();
+        for (word, chunk) in kmer.0.iter_mut().zip(bytes.chunks_exact(8)) {
+            *word = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+        kmer
+    }
+}
+
+/// Mix the bits of a 64-bit word (splitmix64)
+#[inline]
+pub(crate) fn mix64(word: u64) -> u64 {
+    let mut z = word.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Select the kmer used as a key in the index
+///
+/// Returns the forward kmer if `stranded` is set, else the canonical kmer (the smallest of the
+/// forward kmer and its reverse complement). The boolean is true if the forward kmer was selected.
+#[inline]
+pub fn normalize<K: Kmer>(forward: K, reverse: K, stranded: bool) -> (K, bool) {
+    if stranded || forward <= reverse {
+        (forward, true)
+    } else {
+        (reverse, false)
+    }
+}
+
+/// Rolling iterator on the packed kmers of a sequence
+///
+/// Yields `(position, forward kmer, reverse complement kmer)` for each kmer of the sequence,
+/// kmers containing a non ACGT letter are skipped.
+pub struct KmerIter<'a, K> {
+    seq: &'a [u8],
+    kmer_size: usize,
+    position: usize,
+    valid: usize,
+    forward: K,
+    reverse: K,
+}
+
+impl<'a, K: Kmer> KmerIter<'a, K> {
+    /// Iterate on the kmers of size `kmer_size` of `seq`
+    pub fn new(seq: &'a [u8], kmer_size: usize) -> Self {
+        Self {
+            seq,
+            kmer_size,
+            position: 0,
+            valid: 0,
+            forward: K::default(),
+            reverse: K::default(),
+        }
+    }
+}
+
+impl<K: Kmer> Iterator for KmerIter<'_, K> {
+    type Item = (usize, K, K);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position < self.seq.len() {
+            let code = ENCODE_MAP[self.seq[self.position] as usize];
+            self.position += 1;
+            if code == 4 {
+                // non ACGT letter, the next kmer starts after it
+                self.valid = 0;
+                continue;
+            }
+            self.forward = self.forward.push(code, self.kmer_size);
+            self.reverse = self.reverse.push_rc(code, self.kmer_size);
+            self.valid += 1;
+            if self.valid >= self.kmer_size {
+                return Some((self.position - self.kmer_size, self.forward, self.reverse));
+            }
+        }
+        None
+    }
+}
+
+/// Run an expression with a type alias set to the [Kmer] type suited for a kmer size
+///
+/// `with_kmer_type!(kmer_size, K => expr)`
+macro_rules! with_kmer_type {
+    ($kmer_size:expr, $kmer:ident => $body:expr) => {
+        match $kmer_size {
+            0..=32 => {
+                type $kmer = u64;
+                $body
+            }
+            33..=64 => {
+                type $kmer = u128;
+                $body
+            }
+            _ => {
+                type $kmer = $crate::kmer::LongKmer;
+                $body
+            }
+        }
+    };
+}
+pub(crate) use with_kmer_type;
+
+#[cfg(test)]
+mod tests {
+    /* crate use */
+    use biotest::values::Generate as _;
+
+    /* project use */
+    use super::*;
+    use crate::count::rev_comp;
+
+    fn check_kmer_type<K: Kmer>(kmer_size: usize) -> anyhow::Result<()> {
+        let mut rng = biotest::rand();
+        let mut sequence = biotest::values::Nucleotides::Dna.generate(&mut rng, 600)?;
+        sequence[300] = b'N';
+        let upper = sequence.to_ascii_uppercase();
+
+        let mut expected = vec![];
+        for i in 0..(sequence.len() - kmer_size + 1) {
+            if let Some(forward) = K::from_bytes(&sequence[i..i + kmer_size]) {
+                let mut rc = upper[i..i + kmer_size].to_vec();
+                rev_comp(&mut rc);
+                assert_eq!(forward.to_bytes(kmer_size), &upper[i..i + kmer_size]);
+                assert_eq!(forward.rev_comp(kmer_size).to_bytes(kmer_size), rc);
+                assert_eq!(
+                    normalize(forward, forward.rev_comp(kmer_size), false)
+                        .0
+                        .to_bytes(kmer_size),
+                    std::cmp::min(&upper[i..i + kmer_size], rc.as_slice()),
+                );
+                expected.push((i, forward, forward.rev_comp(kmer_size)));
+            }
+        }
+        assert_eq!(sequence.len() - kmer_size + 1 - kmer_size, expected.len());
+        assert_eq!(
+            KmerIter::<K>::new(&sequence, kmer_size).collect::<Vec<_>>(),
+            expected
+        );
+
+        // substitutions and segments
+        for window in expected.windows(2) {
+            let (kmer, other) = (window[0].1, window[1].1);
+            let (ascii, other_ascii) = (kmer.to_bytes(kmer_size), other.to_bytes(kmer_size));
+            assert_eq!(
+                kmer.hamming_distance(&other, kmer_size),
+                ascii
+                    .iter()
+                    .zip(&other_ascii)
+                    .filter(|(a, b)| a != b)
+                    .count()
+            );
+            let positions = kmer_size / 3..kmer_size - kmer_size / 4;
+            assert_eq!(
+                kmer.segment(positions.clone(), kmer_size)
+                    .to_bytes(positions.len()),
+                &ascii[positions]
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn packed_kmers() -> anyhow::Result<()> {
+        check_kmer_type::<u64>(1)?;
+        check_kmer_type::<u64>(15)?;
+        check_kmer_type::<u64>(32)?;
+        check_kmer_type::<u128>(33)?;
+        check_kmer_type::<u128>(64)?;
+        check_kmer_type::<LongKmer>(31)?;
+        check_kmer_type::<LongKmer>(65)?;
+        check_kmer_type::<LongKmer>(127)?;
+        check_kmer_type::<LongKmer>(LongKmer::MAX_KMER_SIZE)?;
+        Ok(())
+    }
+
+    #[test]
+    fn lexicographic_order() {
+        let kmers: [&[u8]; 4] = [b"AACGT", b"ACGTA", b"CATTA", b"TTTTT"];
+        for pair in kmers.windows(2) {
+            assert!(u64::from_bytes(pair[0]) < u64::from_bytes(pair[1]));
+            assert!(LongKmer::from_bytes(pair[0]) < LongKmer::from_bytes(pair[1]));
+        }
+        assert_eq!(u64::from_bytes(b"ACGNT"), None);
+    }
+}
@@ -5,6 +5,8 @@ use integer_encoding::*;
 
 use std::sync::Mutex;
 
+use crate::colors::ColorId;
+
 /// Information needed to represent a match between a kmer and a read.
 pub struct KmerMatch {
     /// The id of the read.
@@ -25,6 +27,22 @@ pub trait KmerCounter: Default + Sync + Send {
 
     /// Returns the count of the counter.
     fn get_count(&self) -> usize;
+
+    /// Returns a string representation of several counters considered as a single one (eg. the
+    /// counters of the expansions of a degenerate kmer).
+    fn merged_to_string(counters: &[&Self]) -> String;
+
+    /// Returns the color of the counted kmer, if the counter stores it (see [crate::colors::Colored]).
+    fn color(&self) -> Option<ColorId> {
+        None
+    }
+
+    /// Sets the color of the counted kmer.
+    ///
+    /// Panics if the counter does not store the color of its kmer (see [crate::colors::Colored]).
+    fn set_color(&mut self, _color: ColorId) {
+        panic!("the counter does not store the color of its kmer");
+    }
 }
 
 impl KmerCounter for atomic_counter::RelaxedCounter {
@@ -40,6 +58,14 @@ impl KmerCounter for atomic_counter::RelaxedCounter {
     fn get_count(&self) -> usize {
         self.get()
     }
+
+    fn merged_to_string(counters: &[&Self]) -> String {
+        counters
+            .iter()
+            .map(|counter| counter.get())
+            .sum::<usize>()
+            .to_string()
+    }
 }
 
 #[derive(Default)]
@@ -97,6 +123,10 @@ impl KmerCounter for Mutex<KmerCounterWithLog> {
     fn get_count(&self) -> usize {
         self.lock().unwrap().count
     }
+
+    fn merged_to_string(counters: &[&Self]) -> String {
+        counters.iter().map(|counter| counter.to_string()).collect()
+    }
 }
 
 // TODO Pierre: impl Display
@@ -126,6 +156,10 @@ mod tests {
 
         assert_eq!(counter.get_count(), 10);
         assert_eq!(counter.to_string(), "10".to_string());
+        assert_eq!(
+            atomic_counter::RelaxedCounter::merged_to_string(&[&counter, &counter]),
+            "20".to_string()
+        );
     }
 
     #[test]
@@ -1,94 +1,931 @@
 //! Kmer hash declarations
 
 /* std use */
+use std::collections::hash_map::Entry;
+use std::io::BufRead as _;
+use std::ops::Range;
+use std::sync::Mutex;
 
 /* crates use */
 use ahash::AHashMap as HashMap;
-use entropy::shannon_entropy;
-use needletail::{Sequence, parse_fastx_file};
+use anyhow::Context as _;
+use needletail::parse_fastx_file;
 
 /* project use */
-use crate::{kmer_counter::KmerCounter, sequence_normalizer::SequenceNormalizer};
-
-/// given a kmer as a &[u8] return a tuple boolean, position
-/// if the kmer contains an non ACGT letter, return false and the position of the first non ACGT letter
-/// As we used needletail to normalize the sequences, we only check for 'N'
-/// else return true and 0 as position
-pub fn first_non_acgt(kmer: &[u8]) -> (bool, usize) {
-    for (i, &byte) in kmer.iter().enumerate() {
-        // if byte == b'N' {
-        //     return (false, i);
-        // }
-        if byte != b'A' && byte != b'C' && byte != b'G' && byte != b'T' {
-            return (false, i);
+use crate::alphabet::Alphabet;
+use crate::chunks::{Pipeline, WithId};
+use crate::colors::KmerColors;
+use crate::consts::DECODE_MAP;
+use crate::file_parsing;
+use crate::index_stats::IndexStats;
+use crate::iupac::DegenerateKmers;
+use crate::kmer::{normalize, Kmer};
+use crate::kmer_counter::KmerCounter;
+use crate::kmer_index::BloomFilter;
+use crate::low_complexity::LowComplexity;
+use crate::progress::Progress;
+use crate::sampling::{sampled_kmers, Sampling};
+use crate::spaced_seeds::SpacedSeeds;
+
+/// indexed kmers with their counters, and their labels (and origins) if there are several
+/// labelled kmer sets or if the origin of the kmers is tracked (the color of each kmer being then
+/// stored with its counter, see [crate::colors::Colored])
+pub type LabelledKmerSet<K, T> = (HashMap<K, T>, Option<KmerColors>);
+
+/// abundance of the kmers in the counted kmer dumps they were read from (summed over the dumps)
+pub type KmerAbundances<K> = HashMap<K, u64>;
+
+/// parameters of the indexing of the kmers, see [index_labelled_kmers()]
+#[derive(Debug, Clone, Copy)]
+pub struct IndexParameters<'a> {
+    /// size of the kmers
+    pub kmer_size: usize,
+    /// index the kmers as they are, else their canonical form
+    pub stranded: bool,
+    /// skip the low complexity kmers, if provided
+    pub low_complexity: Option<&'a LowComplexity>,
+    /// store the reference records each kmer was read from
+    pub track_origins: bool,
+    /// index the keys of the kmers for each spaced seed, if provided
+    pub seeds: Option<&'a SpacedSeeds>,
+    /// only index the sampled kmers, if provided
+    pub sampling: Option<&'a Sampling>,
+    /// alphabet of the kmers
+    pub alphabet: Alphabet,
+    /// skip the kmers of the counted dumps whose count is smaller than this value
+    pub min_abundance: u64,
+    /// report the progress of the indexing on stderr
+    pub progress: bool,
+    /// count the kmers indexed from each record in the statistics
+    pub record_stats: bool,
+}
+
+impl IndexParameters<'_> {
+    /// parameters of the indexing of all the nucleotide kmers of size `kmer_size`
+    pub fn new(kmer_size: usize, stranded: bool) -> Self {
+        Self {
+            kmer_size,
+            stranded,
+            low_complexity: None,
+            track_origins: false,
+            seeds: None,
+            sampling: None,
+            alphabet: Alphabet::Dna,
+            min_abundance: 0,
+            progress: false,
+            record_stats: false,
         }
     }
-    (true, 0)
 }
 
 /// index all kmers of size kmer_size in the fasta file
-/// returns a hashmap with the kmers as keys and their count as values, initialized to 0
-pub fn index_kmers<T: KmerCounter>(
+/// returns a hashmap with the packed kmers as keys and their count as values, initialized to 0,
+/// and the statistics of the indexing
+pub fn index_kmers<K: Kmer, T: KmerCounter>(
     file_name: String,
     kmer_size: usize,
     stranded: bool,
-    no_low_complexity: bool,
-) -> anyhow::Result<(HashMap<Vec<u8>, T>, usize)> {
-    // ) -> anyhow::Result<(Box<dyn HashMap<Vec<u8>, T>>, usize)> {
-    let mut kmer_set = HashMap::new();
-    let reverse_complement = if stranded { Some(false) } else { None };
-
-    let mut reader = parse_fastx_file(&file_name).unwrap();
-    while let Some(record) = reader.next() {
-        let seqrec = record.expect("invalid record");
-        let norm_seq = seqrec.normalize(false);
-        // transform norm_seq into a string on the ACGTN alphabet
-        let acgt_sequence = norm_seq.iter().cloned().collect::<Vec<u8>>(); // TODO optimize
-
-    // loop {
-    //     let Some(mut record) = reader.next()? else {
-    //         break;
-    //     };
-    //     record.upper();
-    //     let acgt_sequence = record.seq();
-
-        // for each kmer of the sequence, insert it in the kmer_set
-        if acgt_sequence.len() < kmer_size {
+    low_complexity: Option<&LowComplexity>,
+) -> anyhow::Result<(HashMap<K, T>, IndexStats)> {
+    let parameters = IndexParameters {
+        low_complexity,
+        ..IndexParameters::new(kmer_size, stranded)
+    };
+    let ((kmer_set, _), stats) =
+        index_labelled_kmers::<K, T>(&[(String::new(), file_name)], &parameters, None, None, None)?;
+    Ok((kmer_set, stats))
+}
+
+/// index all kmers of size kmer_size in several fasta files, each file being given with a label
+/// (see [IndexParameters] for the parameters of the indexing)
+/// returns a hashmap with the packed kmers as keys and their count as values, initialized to 0,
+/// and the labels of each kmer if there are several files (their counters must then store their
+/// color, see [crate::colors::Colored]).
+/// The files may also be kmer dumps, with one kmer per line optionally followed by its count
+/// (see [file_parsing::is_kmer_dump()]), or KMC databases (see [KmcDatabase]): the counts are then
+/// added to `abundances` if provided, and the kmers whose count is smaller than `min_abundance`
+/// are skipped.
+/// If `track_origins` is set, the records (first word of their id) each kmer was read from are
+/// also stored (the record of the kmers of a dump is its label).
+/// If `seeds` are provided, the keys of each kmer for each spaced seed are indexed instead of the
+/// kmers (see [SpacedSeeds]).
+/// If `sampling` is provided, only the sampled kmers are indexed (see [Sampling]).
+/// The kmers are made of nucleotides or of amino acids according to `alphabet`.
+/// If `degenerate` is provided, the kmers containing IUPAC degenerate nucleotides are expanded
+/// into the ACGT kmers they stand for and indexed as well (see [DegenerateKmers]).
+/// The fasta and fastq files are parsed and indexed in parallel (see [ShardedKmerSet]), unless
+/// the kmers are labelled or expanded: the labels and the degenerate kmers are then numbered in the
+/// order of the files.
+/// If `progress` is set, the progress of the indexing and the peak memory are reported on stderr.
+/// Also returns the statistics of the indexing (see [IndexStats]), with the number of kmers indexed
+/// from each record if `record_stats` is set.
+/// If `bloom` is provided, the kmers are inserted in this approximate index instead of the returned
+/// hashmap, which is then empty (see [BloomFilter]): they are neither labelled nor expanded.
+pub fn index_labelled_kmers<K: Kmer, T: KmerCounter>(
+    files: &[(String, String)],
+    parameters: &IndexParameters<'_>,
+    degenerate: Option<&mut DegenerateKmers<K>>,
+    abundances: Option<&mut KmerAbundances<K>>,
+    bloom: Option<&mut BloomFilter>,
+) -> anyhow::Result<(LabelledKmerSet<K, T>, IndexStats)> {
+    let IndexParameters {
+        kmer_size,
+        stranded,
+        low_complexity,
+        track_origins,
+        seeds,
+        sampling,
+        alphabet,
+        min_abundance,
+        progress,
+        record_stats,
+    } = *parameters;
+    if kmer_size == 0 || kmer_size > alphabet.max_kmer_size::<K>() {
+        anyhow::bail!(
+            "kmer size must be between 1 and {} (got {})",
+            alphabet.max_kmer_size::<K>(),
+            kmer_size
+        );
+    }
+    if bloom.is_some() && (files.len() > 1 || track_origins || degenerate.is_some()) {
+        anyhow::bail!("the kmers of a Bloom filter can neither be labelled nor expanded");
+    }
+    let colored = files.len() > 1 || track_origins;
+    assert!(
+        !colored || T::default().color().is_some(),
+        "the counters of labelled kmers store their color"
+    );
+    let mut builder = KmerSetBuilder {
+        kmer_size,
+        stranded,
+        low_complexity,
+        seeds,
+        sampling,
+        alphabet,
+        kmer_set: HashMap::new(),
+        bloom,
+        colors: colored
+            .then(|| KmerColors::new(files.iter().map(|(label, _)| label.clone()).collect())),
+        degenerate,
+        abundances,
+        min_abundance,
+        progress: Progress::new("Indexing", progress),
+        stats: IndexStats::new(record_stats),
+    };
+
+    for (label, (label_name, file_name)) in files.iter().enumerate() {
+        // the kmers of a dump or of a KMC database are attached to its label, or to a record named
+        // after it
+        let dump_target = |colors: Option<&mut KmerColors>| match colors {
+            Some(colors) if track_origins => colors.add_record(label_name.clone(), label as u32),
+            _ => label as u32,
+        };
+        if let Some(name) = KmcDatabase::database_name(file_name) {
+            let database = KmcDatabase::open(name)?;
+            if database.kmer_size != kmer_size {
+                anyhow::bail!(
+                    "the KMC database {} contains kmers of size {}, not {} (see --kmer-size)",
+                    name,
+                    database.kmer_size,
+                    kmer_size
+                );
+            }
+            if database.canonical && stranded {
+                anyhow::bail!("the KMC database {} contains canonical kmers, it can not be used with --stranded", name);
+            }
+            if alphabet != Alphabet::Dna {
+                anyhow::bail!(
+                    "the KMC database {} contains nucleotide kmers (see --alphabet)",
+                    name
+                );
+            }
+            let target = dump_target(builder.colors.as_mut());
+            let mut kmers = 0;
+            for (kmer, count) in database.kmers() {
+                kmers += builder.insert_sequence(&kmer, None, target, count);
+            }
+            builder
+                .stats
+                .add_record(file_name, dump_name(label_name, file_name), kmers);
             continue;
         }
-        let mut i = 0;
-        while i < acgt_sequence.len() - kmer_size + 1 {
-            // for mut i in 0..(acgt_sequence.len() - kmer_size + 1) {
-            let kmer = &acgt_sequence[i..(i + kmer_size)];
-            let first_non_acgt = first_non_acgt(kmer);
-            if !first_non_acgt.0 {
-                // If the kmer contains a non acgt letter, we jump to the next possible kmer
-                i = i + first_non_acgt.1 + 1;
-                continue;
-            } else {
-                // If the entropy is too low, the kmer is not inserted
-                if no_low_complexity && shannon_entropy(kmer) < 1.0 {
-                    i += 1;
+        if file_parsing::is_kmer_dump(file_name)? {
+            let target = dump_target(builder.colors.as_mut());
+            let mut kmers = 0;
+            let mut lines = file_parsing::open_text_file(file_name)?.lines();
+            while let Some(line) = lines.next().transpose()? {
+                let Some((kmer, count)) = file_parsing::parse_kmer_dump_line(&line)
+                    .with_context(|| format!("Error reading the kmers of {}", file_name))?
+                else {
+                    continue;
+                };
+                if kmer.len() != kmer_size {
+                    anyhow::bail!(
+                        "the kmers of {} have size {}, not {} (see --kmer-size)",
+                        file_name,
+                        kmer.len(),
+                        kmer_size
+                    );
+                }
+                let kmer = kmer.to_ascii_uppercase();
+                kmers += builder.insert_sequence(&kmer, Some(&kmer), target, count);
+            }
+            builder
+                .stats
+                .add_record(file_name, dump_name(label_name, file_name), kmers);
+            continue;
+        }
+
+        let mut reader = parse_fastx_file(file_name)?;
+        let (sequences, short_sequences) = (builder.stats.sequences, builder.stats.short_sequences);
+        if builder.colors.is_none() && builder.degenerate.is_none() {
+            builder.insert_fastx_par(reader, file_name)?;
+        } else {
+            while let Some(record) = reader.next() {
+                let seqrec = record?;
+                let sequence = alphabet.normalize(&seqrec);
+                let name = record_name(seqrec.id());
+                // the kmers of this record are either attached to its label or to the record itself
+                let target = match builder.colors.as_mut() {
+                    Some(colors) if track_origins => colors.add_record(name.clone(), label as u32),
+                    _ => label as u32,
+                };
+                // the degenerate kmers are read in the original sequence
+                let raw_sequence = builder.degenerate.is_some().then(|| seqrec.seq());
+                let kmers =
+                    builder.insert_sequence(&sequence, raw_sequence.as_deref(), target, None);
+                builder.stats.add_record(file_name, &name, kmers);
+            }
+        }
+        // the sequences shorter than the kmers are not indexed
+        let nb_sequences = builder.stats.sequences - sequences;
+        if nb_sequences > 0 && builder.stats.short_sequences - short_sequences == nb_sequences {
+            anyhow::bail!(
+                "the sequences of {} are all shorter than the kmer size ({})",
+                file_name,
+                kmer_size
+            );
+        }
+    }
+    let KmerSetBuilder {
+        kmer_set,
+        bloom,
+        colors,
+        degenerate,
+        progress,
+        mut stats,
+        ..
+    } = builder;
+    if let Some(bloom) = &bloom {
+        stats.distinct = bloom.estimated_len();
+        eprintln!(
+            "Indexed about {} kmers, each of size {}, in a Bloom filter of {} bits with {} hashes",
+            stats.distinct,
+            kmer_size,
+            bloom.nb_bits(),
+            bloom.nb_hashes()
+        );
+    } else {
+        stats.distinct = kmer_set.len();
+        eprintln!(
+            "Indexed {} kmers, each of size {}",
+            kmer_set.len(),
+            kmer_size
+        );
+    }
+    progress.finish();
+    if stats.short_sequences > 0 {
+        eprintln!(
+            "Warning: {} sequences shorter than the kmer size ({}) were not indexed",
+            stats.short_sequences, kmer_size
+        );
+    }
+    if let Some(degenerate) = &degenerate {
+        eprintln!(
+            "Expanded {} degenerate kmers into {} kmers",
+            degenerate.probes.len(),
+            degenerate.nb_expansions()
+        );
+        if degenerate.skipped > 0 {
+            eprintln!(
+                "Warning: {} degenerate kmers were not indexed, they stand for more than {} kmers (see --expand-iupac)",
+                degenerate.skipped,
+                degenerate.limit()
+            );
+        }
+    }
+    if let Some(colors) = &colors {
+        if !colors.tracks_origins() {
+            eprintln!(
+                "Indexed {} labelled kmer sets, with {} distinct combinations of labels",
+                colors.labels.len(),
+                colors.table.len()
+            );
+        } else {
+            if colors.labels.len() > 1 {
+                eprintln!("Indexed {} labelled kmer sets", colors.labels.len());
+            }
+            eprintln!(
+                "Tracked the origin of the kmers in {} reference records, with {} distinct combinations of records",
+                colors.records.len(),
+                colors.table.len()
+            );
+        }
+    }
+
+    Ok(((kmer_set, colors), stats))
+}
+
+/// name of a record: the first word of its id
+fn record_name(id: &[u8]) -> String {
+    let name = id
+        .split(|c| c.is_ascii_whitespace())
+        .next()
+        .unwrap_or_default();
+    String::from_utf8_lossy(name).to_string()
+}
+
+/// name of the record of the kmers of a dump: its label, or its file name if it is not labelled
+fn dump_name<'a>(label_name: &'a str, file_name: &'a str) -> &'a str {
+    if label_name.is_empty() {
+        file_name
+    } else {
+        label_name
+    }
+}
+
+/// upper bound of the number of distinct kmers of some files (see [index_labelled_kmers()]), to
+/// size an approximate index: the number of kmer positions of the sequences of the fasta and fastq
+/// files, the number of lines of the kmer dumps, and the number of kmers of the KMC databases
+///
+/// the fasta and fastq files are parsed once more
+pub fn max_nb_kmers(files: &[(String, String)], kmer_size: usize) -> anyhow::Result<usize> {
+    let mut nb_kmers = 0;
+    for (_, file_name) in files {
+        if let Some(name) = KmcDatabase::database_name(file_name) {
+            nb_kmers += KmcDatabase::open(name)?.nb_kmers as usize;
+        } else if file_parsing::is_kmer_dump(file_name)? {
+            nb_kmers += file_parsing::open_text_file(file_name)?.lines().count();
+        } else {
+            let mut reader = parse_fastx_file(file_name)?;
+            while let Some(record) = reader.next() {
+                nb_kmers += (record?.num_bases() + 1).saturating_sub(kmer_size);
+            }
+        }
+    }
+    Ok(nb_kmers)
+}
+
+/// kmer set being built, with the parameters of the indexing, see [index_labelled_kmers()]
+struct KmerSetBuilder<'a, K, T> {
+    kmer_size: usize,
+    stranded: bool,
+    low_complexity: Option<&'a LowComplexity>,
+    seeds: Option<&'a SpacedSeeds>,
+    sampling: Option<&'a Sampling>,
+    alphabet: Alphabet,
+    kmer_set: HashMap<K, T>,
+    /// approximate index replacing `kmer_set`, if any
+    bloom: Option<&'a mut BloomFilter>,
+    colors: Option<KmerColors>,
+    degenerate: Option<&'a mut DegenerateKmers<K>>,
+    abundances: Option<&'a mut KmerAbundances<K>>,
+    min_abundance: u64,
+    progress: Progress,
+    stats: IndexStats,
+}
+
+impl<'a, K: Kmer, T: KmerCounter> KmerSetBuilder<'a, K, T> {
+    /// index the kmers of a normalized sequence, attached to `target` (a label or a record)
+    ///
+    /// if the degenerate kmers are expanded, they are read in `raw_sequence` (the original
+    /// sequence). `abundance` is added to the abundances of the kmers if provided, the sequence is
+    /// skipped if it is smaller than the minimum abundance. Returns the number of inserted kmers
+    fn insert_sequence(
+        &mut self,
+        sequence: &[u8],
+        raw_sequence: Option<&[u8]>,
+        target: u32,
+        abundance: Option<u64>,
+    ) -> usize {
+        self.progress.add(1, sequence.len());
+        if abundance.is_some_and(|abundance| abundance < self.min_abundance) {
+            self.stats.sequences += 1;
+            self.stats.kmers += 1;
+            self.stats.low_abundance += 1;
+            return 0;
+        }
+        let kmer_size = self.kmer_size;
+        let mut stats = IndexStats::default();
+        for kmer in self.sequence_kmers(sequence, &mut stats) {
+            self.insert(kmer, target, abundance);
+            if let Some(degenerate) = self.degenerate.as_deref_mut() {
+                degenerate.insert_acgt(&kmer);
+            }
+        }
 
+        // the kmers containing degenerate nucleotides are indexed as the kmers they stand for
+        let expanded = raw_sequence.and_then(|raw_sequence| {
+            let expanded = self
+                .degenerate
+                .as_deref_mut()?
+                .expand(raw_sequence, kmer_size);
+            Some((raw_sequence, expanded))
+        });
+        if let Some((raw_sequence, expanded)) = expanded {
+            for (i, probe, forward, reverse) in expanded {
+                if self.low_complexity.is_some_and(|filter| {
+                    filter.is_low_complexity(&raw_sequence[i..(i + kmer_size)])
+                }) {
+                    stats.low_complexity += 1;
                     continue;
                 }
-                kmer_set.insert(
-                    SequenceNormalizer::new(kmer, reverse_complement)
-                        .iter()
-                        .collect(),
-                    Default::default(), // RelaxedCounter::new(0) // TODO call default from kmer_counter (anthony)
+                let (kmer, is_forward) = normalize(forward, reverse, self.stranded);
+                let indexed = self.kmer_set.contains_key(&kmer);
+                if let Some(degenerate) = self.degenerate.as_deref_mut() {
+                    degenerate.insert(probe, kmer, indexed);
+                }
+                self.insert(kmer, target, abundance);
+                stats.inserted += 1;
+                stats.reverse_complements += usize::from(!is_forward);
+            }
+        }
+        self.stats.add(&stats);
+        stats.inserted
+    }
+
+    /// normalized kmers (or spaced seed keys) of a normalized sequence, to be indexed
+    ///
+    /// the sequence and its kmers (skipped or not) are counted in `stats` as they are iterated
+    fn sequence_kmers<'b>(
+        &self,
+        sequence: &'b [u8],
+        stats: &'b mut IndexStats,
+    ) -> impl Iterator<Item = K> + use<'a, 'b, K, T> {
+        let (kmer_size, stranded, low_complexity) =
+            (self.kmer_size, self.stranded, self.low_complexity);
+        let seeds = self.seeds;
+        let positions = (sequence.len() + 1).saturating_sub(kmer_size);
+        let valid = self.alphabet.nb_valid_kmers(sequence, kmer_size);
+        stats.sequences += 1;
+        stats.short_sequences += usize::from(sequence.len() < kmer_size);
+        stats.kmers += positions;
+        stats.non_acgt += positions - valid;
+        // the valid kmers are unsampled until they are returned by the sampling
+        stats.unsampled += valid;
+        let IndexStats {
+            unsampled,
+            low_complexity: low_complexity_count,
+            inserted,
+            reverse_complements,
+            ..
+        } = stats;
+        // for each kmer of the sequence (or each sampled kmer), insert it in the kmer_set
+        // (kmers containing a non acgt letter, or a non standard amino acid, are skipped by the iterator)
+        sampled_kmers::<K>(
+            self.alphabet,
+            sequence,
+            kmer_size,
+            stranded,
+            self.sampling.copied(),
+        )
+        .inspect(move |_| *unsampled -= 1)
+        // If the complexity is too low, the kmer is not inserted
+        .filter(move |(i, _, _)| {
+            let is_low = low_complexity
+                .is_some_and(|filter| filter.is_low_complexity(&sequence[*i..(*i + kmer_size)]));
+            *low_complexity_count += usize::from(is_low);
+            !is_low
+        })
+        .flat_map(move |(_, forward, reverse)| {
+            (0..seeds.map_or(1, |seeds| seeds.len())).map(move |mask| {
+                let (forward, reverse) = match seeds {
+                    Some(seeds) => seeds.keys(mask, forward, reverse),
+                    None => (forward, reverse),
+                };
+                normalize(forward, reverse, stranded)
+            })
+        })
+        .map(move |(kmer, is_forward)| {
+            *inserted += 1;
+            *reverse_complements += usize::from(!is_forward);
+            kmer
+        })
+    }
+
+    /// index the kmers of the sequences of a fasta or fastq file in parallel (neither labelled nor
+    /// expanded)
+    fn insert_fastx_par(
+        &mut self,
+        reader: Box<dyn needletail::FastxReader>,
+        file_name: &str,
+    ) -> anyhow::Result<()> {
+        let sharded_set = ShardedKmerSet::<K, T>::new(rayon::current_num_threads());
+        // the statistics of the chunks, and the kmers indexed from each record with its position
+        let stats = Mutex::new(IndexStats::default());
+        let records = Mutex::new(Vec::new());
+        let record_stats = self.stats.records.is_some();
+        Pipeline::<(), WithId>::run_chunked(
+            reader,
+            self.alphabet,
+            // map: group the kmers of a chunk by shard, so that each shard is locked once
+            |chunk| {
+                let mut shards = vec![Vec::new(); sharded_set.nb_shards()];
+                let (mut sequences, mut letters) = (0, 0);
+                let mut chunk_stats = IndexStats::default();
+                let mut chunk_records = Vec::new();
+                chunk.for_each(&mut |record| {
+                    (sequences, letters) = (sequences + 1, letters + record.seq.len());
+                    let inserted = chunk_stats.inserted;
+                    for kmer in self.sequence_kmers(record.seq, &mut chunk_stats) {
+                        // the bits of a Bloom filter are set without lock
+                        match self.bloom.as_deref() {
+                            Some(bloom) => bloom.insert(&kmer),
+                            None => shards[sharded_set.shard(&kmer)].push(kmer),
+                        }
+                    }
+                    if record_stats {
+                        chunk_records.push((
+                            record.read_id,
+                            record_name(record.id),
+                            chunk_stats.inserted - inserted,
+                        ));
+                    }
+                });
+                self.progress.add(sequences, letters);
+                for (shard, kmers) in shards.into_iter().enumerate() {
+                    sharded_set.insert(shard, kmers);
+                }
+                stats.lock().unwrap().add(&chunk_stats);
+                records.lock().unwrap().extend(chunk_records);
+            },
+            // reduce
+            (|| (), |_, _| ()),
+        )?;
+        sharded_set.merge_into(&mut self.kmer_set);
+        self.stats.add(&stats.into_inner().unwrap());
+        // the chunks are processed in no particular order
+        let mut records = records.into_inner().unwrap();
+        records.sort_unstable_by_key(|(read_id, _, _)| *read_id);
+        for (_, name, kmers) in records {
+            self.stats.add_record(file_name, &name, kmers);
+        }
+        Ok(())
+    }
+
+    /// insert a normalized kmer in the kmer set, or in the Bloom filter (not counted in the
+    /// statistics)
+    fn insert(&mut self, kmer: K, target: u32, abundance: Option<u64>) {
+        match self.bloom.as_deref_mut() {
+            Some(bloom) => bloom.insert(&kmer),
+            None => {
+                let (counter, color) = match self.kmer_set.entry(kmer) {
+                    Entry::Occupied(entry) => {
+                        let counter = entry.into_mut();
+                        let color = counter.color();
+                        (counter, color)
+                    }
+                    Entry::Vacant(entry) => (entry.insert(T::default()), None),
+                };
+                // the color of a kmer is stored with its counter
+                if let Some(colors) = self.colors.as_mut() {
+                    counter.set_color(colors.add(color, target));
+                }
+            }
+        }
+        if let (Some(abundances), Some(abundance)) = (self.abundances.as_deref_mut(), abundance) {
+            *abundances.entry(kmer).or_default() += abundance;
+        }
+    }
+}
+
+/// Kmer set split in shards by the hash of the kmers, each one behind its own lock, so that several
+/// threads can insert kmers at the same time (see [index_labelled_kmers()])
+pub struct ShardedKmerSet<K, T> {
+    shards: Vec<Mutex<HashMap<K, T>>>,
+}
+
+impl<K: Kmer, T: KmerCounter> ShardedKmerSet<K, T> {
+    /// number of shards per thread, to limit the contention on the locks
+    const SHARDS_PER_THREAD: usize = 16;
+
+    /// Create an empty set, for inserting kmers from `nb_threads` threads
+    pub fn new(nb_threads: usize) -> Self {
+        let nb_shards = (nb_threads.max(1) * Self::SHARDS_PER_THREAD).next_power_of_two();
+        Self {
+            shards: (0..nb_shards).map(|_| Mutex::new(HashMap::new())).collect(),
+        }
+    }
+
+    /// number of shards
+    pub fn nb_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// shard of a kmer
+    pub fn shard(&self, kmer: &K) -> usize {
+        // the high bits of the hash, the low ones being used by the hash maps of the shards
+        (kmer.hash64() >> 32) as usize & (self.shards.len() - 1)
+    }
+
+    /// insert some kmers of a shard, with a counter initialized to 0 (see [ShardedKmerSet::shard()])
+    pub fn insert(&self, shard: usize, kmers: Vec<K>) {
+        if kmers.is_empty() {
+            return;
+        }
+        let mut kmer_set = self.shards[shard].lock().unwrap();
+        for kmer in kmers {
+            kmer_set.insert(kmer, Default::default());
+        }
+    }
+
+    /// number of kmers
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    /// true if there is no kmer
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// move the kmers to a single kmer set (the counters of the kmers already in it are reset)
+    ///
+    /// The kmer set grows as the kmers are moved, and each shard is released once its kmers are
+    /// moved: the peak memory is about the merged kmer set and a single shard
+    pub fn merge_into(self, kmer_set: &mut HashMap<K, T>) {
+        for shard in self.shards {
+            for (kmer, counter) in shard.into_inner().unwrap() {
+                kmer_set.insert(kmer, counter);
+            }
+        }
+    }
+}
+
+/// Reader of the kmers of a KMC database, made of a `.kmc_pre` and a `.kmc_suf` file
+///
+/// Only the format of KMC 2 and KMC 3 (version 0x200) is supported. The `.kmc_pre` file is made
+/// of a `KMCP` marker, the lookup tables of the kmer prefixes of each bin (index of the first
+/// kmer of each prefix, as u64), the map of the signatures to the bins (u32), the header, the
+/// size of the header (u32) and a final `KMCP` marker. The `.kmc_suf` file is made of a `KMCS`
+/// marker, the records of the kmers (the nucleotides after the prefix, 4 per byte, then the
+/// little-endian counter), sorted by bin and by prefix, and a final `KMCS` marker.
+pub struct KmcDatabase {
+    /// size of the kmers
+    pub kmer_size: usize,
+    /// true if the kmers were counted on both strands (canonical kmers)
+    pub canonical: bool,
+    /// minimum count of the kmers in the database (the KMC cutoff)
+    pub min_count: u32,
+    /// number of kmers
+    pub nb_kmers: u64,
+    /// number of nucleotides of the prefixes
+    lut_prefix_length: usize,
+    /// size of the counters (0 if the counts are not stored)
+    counter_size: usize,
+    /// index of the first kmer of each prefix, in each bin
+    prefixes: Vec<u64>,
+    /// content of the `.kmc_suf` file
+    suffixes: memmap2::Mmap,
+}
+
+impl KmcDatabase {
+    /// version of the supported KMC databases
+    const VERSION: u32 = 0x200;
+
+    /// name of a KMC database (without extension) if `file_name` is a `.kmc_pre` or a `.kmc_suf`
+    /// file, or the name of a database (with both files)
+    pub fn database_name(file_name: &str) -> Option<&str> {
+        let name = file_name
+            .strip_suffix(".kmc_pre")
+            .or_else(|| file_name.strip_suffix(".kmc_suf"))
+            .unwrap_or(file_name);
+        std::path::Path::new(&format!("{}.kmc_pre", name))
+            .is_file()
+            .then_some(name)
+    }
+
+    /// open the KMC database `name` (without extension)
+    pub fn open(name: &str) -> anyhow::Result<Self> {
+        let prefix_file = format!("{}.kmc_pre", name);
+        let suffix_file = format!("{}.kmc_suf", name);
+        let pre = std::fs::read(&prefix_file)
+            .with_context(|| format!("Error reading {}", prefix_file))?;
+        let suf = std::fs::File::open(&suffix_file)
+            .with_context(|| format!("Error reading {}", suffix_file))?;
+        // SAFETY: the file is only read, it must not be modified by another process while loading
+        let suffixes = unsafe { memmap2::Mmap::map(&suf) }
+            .with_context(|| format!("Error mapping {}", suffix_file))?;
+
+        let u32_at = |pos: usize| u32::from_le_bytes(pre[pos..pos + 4].try_into().unwrap());
+        if pre.len() < 20 || &pre[..4] != b"KMCP" || &pre[pre.len() - 4..] != b"KMCP" {
+            anyhow::bail!("{} is not a KMC database", prefix_file);
+        }
+        if suffixes.len() < 8
+            || &suffixes[..4] != b"KMCS"
+            || &suffixes[suffixes.len() - 4..] != b"KMCS"
+        {
+            anyhow::bail!("{} is not a KMC database", suffix_file);
+        }
+        let version = u32_at(pre.len() - 12);
+        if version != Self::VERSION {
+            anyhow::bail!(
+                "unsupported version {:#x} of the KMC database {} (only the KMC 2 and 3 format, {:#x}, is supported)",
+                version,
+                name,
+                Self::VERSION
+            );
+        }
+        let header_size = u32_at(pre.len() - 8) as usize;
+        let Some(header) = (pre.len() - 8)
+            .checked_sub(header_size)
+            .filter(|header| *header >= 4 && header_size >= 37)
+        else {
+            anyhow::bail!("corrupted KMC database {}", name);
+        };
+        let kmer_size = u32_at(header) as usize;
+        let mode = u32_at(header + 4);
+        let counter_size = u32_at(header + 8) as usize;
+        let lut_prefix_length = u32_at(header + 12) as usize;
+        let signature_length = u32_at(header + 16) as usize;
+        let min_count = u32_at(header + 20);
+        let nb_kmers = u64::from_le_bytes(pre[header + 28..header + 36].try_into().unwrap());
+        // the flag is 0 for canonical kmers
+        let canonical = pre[header + 36] == 0;
+        if mode != 0 {
+            anyhow::bail!(
+                "the KMC database {} has quality-aware counters, they are not supported",
+                name
+            );
+        }
+
+        // the sizes read in the header may be corrupted: they must not overflow
+        let signature_map_size = u32::try_from(2 * signature_length)
+            .ok()
+            .and_then(|shift| 1usize.checked_shl(shift))
+            .and_then(|signatures| signatures.checked_add(1)?.checked_mul(4));
+        let lut_size = u32::try_from(2 * lut_prefix_length)
+            .ok()
+            .and_then(|shift| 1usize.checked_shl(shift));
+        let suffix_size = kmer_size.saturating_sub(lut_prefix_length) / 4;
+        let suffixes_size = ((suffix_size + counter_size) as u64)
+            .checked_mul(nb_kmers)
+            .and_then(|size| size.checked_add(8));
+        let (Some(signature_map_size), Some(lut_size), Some(suffixes_size)) =
+            (signature_map_size, lut_size, suffixes_size)
+        else {
+            anyhow::bail!("corrupted KMC database {}", name);
+        };
+        let Some(lut_end) = header.checked_sub(signature_map_size).filter(|end| {
+            *end >= 4
+                && (end - 4) % 8 == 0
+                // at least one lookup table of the prefixes (one per bin)
+                && (end - 4) / 8 >= lut_size
+                && lut_prefix_length + 4 * suffix_size == kmer_size
+                && suffixes.len() as u64 == suffixes_size
+        }) else {
+            anyhow::bail!("corrupted KMC database {}", name);
+        };
+        let prefixes = pre[4..lut_end]
+            .chunks_exact(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+
+        Ok(Self {
+            kmer_size,
+            canonical,
+            min_count,
+            nb_kmers,
+            lut_prefix_length,
+            counter_size,
+            prefixes,
+            suffixes,
+        })
+    }
+
+    /// ascii kmers of the database with their counts (None if the counts are not stored)
+    pub fn kmers(&self) -> impl Iterator<Item = (Vec<u8>, Option<u64>)> + '_ {
+        let lut_size = 1 << (2 * self.lut_prefix_length);
+        let suffix_size = (self.kmer_size - self.lut_prefix_length) / 4;
+        let record_size = suffix_size + self.counter_size;
+        (0..self.prefixes.len()).flat_map(move |index| {
+            // the kmers of a prefix are until the first kmer of the next one
+            let start = self.prefixes[index].min(self.nb_kmers);
+            let end = self
+                .prefixes
+                .get(index + 1)
+                .map_or(self.nb_kmers, |end| (*end).min(self.nb_kmers));
+            let prefix = index % lut_size;
+            (start..end.max(start)).map(move |kmer_id| {
+                let record = &self.suffixes[4 + kmer_id as usize * record_size..][..record_size];
+                let mut kmer = Vec::with_capacity(self.kmer_size);
+                kmer.extend(
+                    (0..self.lut_prefix_length)
+                        .rev()
+                        .map(|i| DECODE_MAP[(prefix >> (2 * i)) & 3]),
                 );
+                for byte in &record[..suffix_size] {
+                    kmer.extend(
+                        (0..4)
+                            .rev()
+                            .map(|i| DECODE_MAP[((byte >> (2 * i)) & 3) as usize]),
+                    );
+                }
+                let count = (self.counter_size > 0).then(|| {
+                    record[suffix_size..]
+                        .iter()
+                        .rev()
+                        .fold(0, |count, byte| (count << 8) | *byte as u64)
+                });
+                (kmer, count)
+            })
+        })
+    }
+}
+
+/// Index for the approximate search of kmers, with a few substitutions (Hamming distance)
+///
+/// The kmers are split in `max_distance + 1` segments: according to the pigeonhole principle, a
+/// kmer at distance at most `max_distance` of an indexed kmer shares at least one segment with
+/// it. The indexed kmers are thus stored by value of each segment, and the candidates for a
+/// queried kmer are the indexed kmers sharing one of its segments.
+pub struct MismatchIndex<K> {
+    kmer_size: usize,
+    max_distance: usize,
+    /// positions of the nucleotides of each segment
+    segments: Vec<Range<usize>>,
+    /// indexed kmers for each value of each segment
+    buckets: Vec<HashMap<K, Vec<K>>>,
+}
+
+impl<K: Kmer> MismatchIndex<K> {
+    /// index the segments of the given kmers, to find them with up to `max_distance` substitutions
+    pub fn new(
+        kmers: impl Iterator<Item = K>,
+        kmer_size: usize,
+        max_distance: usize,
+    ) -> anyhow::Result<Self> {
+        if max_distance >= kmer_size {
+            anyhow::bail!(
+                "the number of substitutions ({}) must be smaller than the kmer size ({})",
+                max_distance,
+                kmer_size
+            );
+        }
+        let nb_segments = max_distance + 1;
+        let segments = (0..nb_segments)
+            .map(|i| i * kmer_size / nb_segments..(i + 1) * kmer_size / nb_segments)
+            .collect::<Vec<_>>();
+        let mut buckets = vec![HashMap::<K, Vec<K>>::new(); nb_segments];
+        for kmer in kmers {
+            for (positions, bucket) in segments.iter().zip(buckets.iter_mut()) {
+                bucket
+                    .entry(kmer.segment(positions.clone(), kmer_size))
+                    .or_default()
+                    .push(kmer);
             }
-            i += 1;
         }
+        eprintln!(
+            "Indexed {} segments of the kmers, for matches with up to {} substitutions",
+            nb_segments, max_distance
+        );
+
+        Ok(Self {
+            kmer_size,
+            max_distance,
+            segments,
+            buckets,
+        })
+    }
+
+    /// find the closest indexed kmer at distance at most `max_distance` of a kmer, returns it
+    /// with its distance (the smallest kmer is returned in case of ties)
+    pub fn find(&self, kmer: &K) -> Option<(K, usize)> {
+        let mut best: Option<(usize, K)> = None;
+        for (positions, bucket) in self.segments.iter().zip(self.buckets.iter()) {
+            let Some(candidates) = bucket.get(&kmer.segment(positions.clone(), self.kmer_size))
+            else {
+                continue;
+            };
+            for candidate in candidates {
+                let distance = kmer.hamming_distance(candidate, self.kmer_size);
+                if distance <= self.max_distance
+                    && best.is_none_or(|best| (distance, *candidate) < best)
+                {
+                    best = Some((distance, *candidate));
+                }
+            }
+        }
+        best.map(|(distance, kmer)| (kmer, distance))
     }
-    eprintln!(
-        "Indexed {} kmers, each of size {}",
-        kmer_set.len(),
-        kmer_size
-    );
 
-    Ok((kmer_set, kmer_size))
+    /// maximal number of substitutions
+    pub fn max_distance(&self) -> usize {
+        self.max_distance
+    }
 }
 
 #[cfg(test)]
@@ -98,29 +935,11 @@ mod tests {
 
     /* crate use */
     use atomic_counter::AtomicCounter as _;
-    use biotest::values::Generate as _;
     use biotest::Format as _;
 
     /* project use */
     use super::*;
-
-    #[test]
-    fn non_acgt() -> anyhow::Result<()> {
-        let mut rng = biotest::rand();
-
-        let sequence = biotest::values::Nucleotides::DnaUpper.generate(&mut rng, 50)?;
-        println!("{}", String::from_utf8(sequence.clone()).unwrap());
-        assert_eq!((true, 0), first_non_acgt(&sequence));
-
-        let mut sequence = biotest::values::Nucleotides::DnaUpper.generate(&mut rng, 10)?;
-        sequence.push(b'@');
-        assert_eq!((false, 10), first_non_acgt(&sequence));
-
-        let sequence = biotest::values::Nucleotides::DnaUpper.generate(&mut rng, 0)?;
-        assert_eq!((true, 0), first_non_acgt(&sequence));
-
-        Ok(())
-    }
+    use crate::colors::Colored;
 
     #[test]
     fn build_index_kmers() -> anyhow::Result<()> {
@@ -134,16 +953,19 @@ mod tests {
 
         k_generate.create(&kmers_in_path, &mut rng, 5)?;
 
-        let (index, kmer_size) = index_kmers::<atomic_counter::RelaxedCounter>(
+        let (index, stats) = index_kmers::<u64, atomic_counter::RelaxedCounter>(
             kmers_in_path.into_os_string().into_string().unwrap(),
             15,
             false,
-            false,
+            None,
         )?;
 
-        assert_eq!(kmer_size, 15);
+        assert_eq!((stats.sequences, stats.kmers, stats.distinct), (5, 10, 10));
 
-        let mut keys = index.keys().cloned().collect::<Vec<Vec<u8>>>();
+        let mut keys = index
+            .keys()
+            .map(|k| k.to_bytes(15))
+            .collect::<Vec<Vec<u8>>>();
         keys.sort_unstable();
         assert_eq!(
             keys,
@@ -181,16 +1003,19 @@ mod tests {
 
         k_generate.create(&kmers_in_path, &mut rng, 5)?;
 
-        let (index, kmer_size) = index_kmers::<atomic_counter::RelaxedCounter>(
+        let (index, stats) = index_kmers::<u64, atomic_counter::RelaxedCounter>(
             kmers_in_path.into_os_string().into_string().unwrap(),
             15,
             true,
-            false,
+            None,
         )?;
 
-        assert_eq!(kmer_size, 15);
+        assert_eq!((stats.sequences, stats.kmers, stats.distinct), (5, 10, 10));
 
-        let mut keys = index.keys().cloned().collect::<Vec<Vec<u8>>>();
+        let mut keys = index
+            .keys()
+            .map(|k| k.to_bytes(15))
+            .collect::<Vec<Vec<u8>>>();
         keys.sort_unstable();
         assert_eq!(
             keys,
@@ -234,16 +1059,27 @@ mod tests {
 
         std::fs::File::create(&kmers_in_path)?.write_all(&fasta_data)?;
 
-        let (index, kmer_size) = index_kmers::<atomic_counter::RelaxedCounter>(
+        let (index, stats) = index_kmers::<u64, atomic_counter::RelaxedCounter>(
             kmers_in_path.into_os_string().into_string().unwrap(),
             15,
             false,
-            true,
+            Some(&LowComplexity::default()),
         )?;
 
-        assert_eq!(kmer_size, 15);
+        assert_eq!(
+            (
+                stats.sequences,
+                stats.kmers,
+                stats.low_complexity,
+                stats.distinct
+            ),
+            (6, 12, 2, 10)
+        );
 
-        let mut keys = index.keys().cloned().collect::<Vec<Vec<u8>>>();
+        let mut keys = index
+            .keys()
+            .map(|k| k.to_bytes(15))
+            .collect::<Vec<Vec<u8>>>();
         keys.sort_unstable();
         assert_eq!(
             keys,
@@ -286,16 +1122,22 @@ mod tests {
 
         std::fs::File::create(&kmers_in_path)?.write_all(&fasta_data)?;
 
-        let (index, kmer_size) = index_kmers::<atomic_counter::RelaxedCounter>(
+        let (index, stats) = index_kmers::<u64, atomic_counter::RelaxedCounter>(
             kmers_in_path.into_os_string().into_string().unwrap(),
             15,
             false,
-            false,
+            None,
         )?;
 
-        assert_eq!(kmer_size, 15);
+        assert_eq!(
+            (stats.sequences, stats.kmers, stats.non_acgt, stats.distinct),
+            (11, 27, 5, 22)
+        );
 
-        let mut keys = index.keys().cloned().collect::<Vec<Vec<u8>>>();
+        let mut keys = index
+            .keys()
+            .map(|k| k.to_bytes(15))
+            .collect::<Vec<Vec<u8>>>();
         keys.sort_unstable();
         assert_eq!(
             keys,
@@ -332,4 +1174,337 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn build_index_kmers_origins() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let kmers_in_path = temp_dir.path().join("kmers_in.fasta");
+
+        // the last kmer of gene1 is the reverse complement of the first kmer of gene2
+        std::fs::write(
+            &kmers_in_path,
+            b">gene1 description\nACGTAC\n>gene2\nGTACGA\n",
+        )?;
+
+        let parameters = IndexParameters {
+            track_origins: true,
+            record_stats: true,
+            ..IndexParameters::new(5, false)
+        };
+        let ((index, colors), stats) =
+            index_labelled_kmers::<u64, Colored<atomic_counter::RelaxedCounter>>(
+                &[("genes".to_string(), kmers_in_path.display().to_string())],
+                &parameters,
+                None,
+                None,
+                None,
+            )?;
+        let colors = colors.unwrap();
+
+        assert_eq!(index.len(), 3);
+        assert_eq!(
+            colors.records,
+            vec!["gene1".to_string(), "gene2".to_string()]
+        );
+        assert_eq!(colors.record_labels, vec![0, 0]);
+        assert_eq!(
+            colors.get(&index[&u64::from_bytes(b"ACGTA").unwrap()]),
+            &[0]
+        );
+        assert_eq!(
+            colors.get(&index[&u64::from_bytes(b"CGTAC").unwrap()]),
+            &[0, 1]
+        );
+        assert_eq!(
+            colors.get(&index[&u64::from_bytes(b"TACGA").unwrap()]),
+            &[1]
+        );
+
+        // the shared kmer is a duplicate, inserted once as its reverse complement
+        assert_eq!(
+            (
+                stats.inserted,
+                stats.reverse_complements,
+                stats.duplicates(),
+                stats.distinct
+            ),
+            (4, 1, 1, 3)
+        );
+        let records = stats.records.unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| (record.name.as_str(), record.kmers))
+                .collect::<Vec<_>>(),
+            [("gene1", 2), ("gene2", 2)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_indexing() -> anyhow::Result<()> {
+        let mut rng = biotest::rand();
+        let k_generate = biotest::Fasta::builder().sequence_len(150).build()?;
+
+        let temp_dir = tempfile::tempdir()?;
+        let kmers_in_path = temp_dir.path().join("kmers_in.fasta");
+        // enough sequences to span several chunks
+        k_generate.create(&kmers_in_path, &mut rng, 2000)?;
+
+        // the kmers of a single unlabelled file are indexed in parallel, the ones of a file whose
+        // origins are tracked are indexed sequentially
+        let low_complexity = LowComplexity::default();
+        let index = |track_origins: bool,
+                     no_low_complexity: bool|
+         -> anyhow::Result<(Vec<u64>, IndexStats)> {
+            let parameters = IndexParameters {
+                low_complexity: no_low_complexity.then_some(&low_complexity),
+                track_origins,
+                progress: true,
+                record_stats: true,
+                ..IndexParameters::new(21, false)
+            };
+            // the counters store the colors of the kmers, if they are tracked
+            let ((index, _), stats) =
+                index_labelled_kmers::<u64, Colored<atomic_counter::RelaxedCounter>>(
+                    &[(String::new(), kmers_in_path.display().to_string())],
+                    &parameters,
+                    None,
+                    None,
+                    None,
+                )?;
+            let mut kmers = index.keys().copied().collect::<Vec<_>>();
+            kmers.sort_unstable();
+            Ok((kmers, stats))
+        };
+        for no_low_complexity in [false, true] {
+            let (kmers, stats) = index(false, no_low_complexity)?;
+            assert!(kmers.len() > 250_000);
+            // the statistics of the records are in the order of the file
+            assert_eq!((kmers, stats), index(true, no_low_complexity)?);
+        }
+
+        let sharded_set = ShardedKmerSet::<u64, atomic_counter::RelaxedCounter>::new(2);
+        assert_eq!(sharded_set.nb_shards(), 32);
+        let kmer = u64::from_bytes(b"ACGTA").unwrap();
+        sharded_set.insert(sharded_set.shard(&kmer), vec![kmer, kmer]);
+        assert_eq!(sharded_set.len(), 1);
+        let mut kmer_set = HashMap::new();
+        sharded_set.merge_into(&mut kmer_set);
+        assert!(kmer_set.contains_key(&kmer));
+
+        Ok(())
+    }
+
+    #[test]
+    fn mismatch_index() -> anyhow::Result<()> {
+        let kmers = [b"ACGTACGTAC", b"TTTTTGGGGG", b"ACGTACGTTT"]
+            .iter()
+            .map(|kmer| u64::from_bytes(*kmer).unwrap())
+            .collect::<Vec<_>>();
+        let kmer = |ascii: &[u8]| u64::from_bytes(ascii).unwrap();
+
+        let index = MismatchIndex::new(kmers.iter().copied(), 10, 1)?;
+        assert_eq!(index.find(&kmer(b"ACGTACGTAC")), Some((kmers[0], 0)));
+        assert_eq!(index.find(&kmer(b"ACGTAGGTAC")), Some((kmers[0], 1)));
+        assert_eq!(index.find(&kmer(b"TTTTTGGGGA")), Some((kmers[1], 1)));
+        assert_eq!(index.find(&kmer(b"ACGTACGTAT")), Some((kmers[0], 1))); // tie, smallest kmer
+        assert_eq!(index.find(&kmer(b"ACCTACGTAA")), None);
+
+        let index = MismatchIndex::new(kmers.iter().copied(), 10, 2)?;
+        assert_eq!(index.find(&kmer(b"ACCTACGTAA")), Some((kmers[0], 2)));
+        assert_eq!(index.find(&kmer(b"TTTTTGGCCC")), None);
+
+        assert!(MismatchIndex::new(kmers.iter().copied(), 2, 2).is_err());
+
+        Ok(())
+    }
+
+    /// write a KMC database of kmers sorted by prefix, in a single bin, with 1-byte counters
+    fn write_kmc_database(
+        name: &str,
+        kmer_size: usize,
+        canonical: bool,
+        kmers: &[(&[u8], u8)],
+    ) -> anyhow::Result<()> {
+        let lut_prefix_length = kmer_size % 4;
+        let code = |nucleotides: &[u8]| {
+            nucleotides.iter().fold(0, |acc, n| {
+                (acc << 2) | crate::consts::ENCODE_MAP[*n as usize] as usize
+            })
+        };
+
+        let mut pre = b"KMCP".to_vec();
+        for prefix in 0..1 << (2 * lut_prefix_length) {
+            let first = kmers
+                .iter()
+                .position(|(kmer, _)| code(&kmer[..lut_prefix_length]) >= prefix);
+            pre.extend_from_slice(&(first.unwrap_or(kmers.len()) as u64).to_le_bytes());
+        }
+        // signatures of 1 nucleotide, all in bin 0
+        pre.extend_from_slice(&[0; 5 * 4]);
+        let header = pre.len();
+        for value in [kmer_size, 0, 1, lut_prefix_length, 1, 2, 255] {
+            pre.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        pre.extend_from_slice(&(kmers.len() as u64).to_le_bytes());
+        pre.push(!canonical as u8);
+        pre.extend_from_slice(&[0; 27]);
+        pre.extend_from_slice(&0x200u32.to_le_bytes());
+        pre.extend_from_slice(&((pre.len() - header) as u32).to_le_bytes());
+        pre.extend_from_slice(b"KMCP");
+        std::fs::write(format!("{}.kmc_pre", name), pre)?;
+
+        let mut suf = b"KMCS".to_vec();
+        for (kmer, count) in kmers {
+            suf.extend(
+                kmer[lut_prefix_length..]
+                    .chunks(4)
+                    .map(|nucleotides| code(nucleotides) as u8),
+            );
+            suf.push(*count);
+        }
+        suf.extend_from_slice(b"KMCS");
+        std::fs::write(format!("{}.kmc_suf", name), suf)?;
+        Ok(())
+    }
+
+    #[test]
+    fn kmc_database() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let name = format!("{}", temp_dir.path().join("kmers").display());
+        write_kmc_database(
+            &name,
+            5,
+            true,
+            &[(b"ACGTA", 3), (b"CGTAC", 1), (b"GAAAA", 12), (b"GGGAA", 7)],
+        )?;
+
+        assert_eq!(
+            KmcDatabase::database_name(&format!("{}.kmc_suf", name)),
+            Some(name.as_str())
+        );
+        assert_eq!(KmcDatabase::database_name(&name), Some(name.as_str()));
+        assert_eq!(KmcDatabase::database_name("kmers.fasta"), None);
+        let database = KmcDatabase::open(&name)?;
+        assert!(database.canonical);
+        assert_eq!(
+            (database.kmer_size, database.min_count, database.nb_kmers),
+            (5, 2, 4)
+        );
+        assert_eq!(
+            database.kmers().collect::<Vec<_>>(),
+            [
+                (b"ACGTA".to_vec(), Some(3)),
+                (b"CGTAC".to_vec(), Some(1)),
+                (b"GAAAA".to_vec(), Some(12)),
+                (b"GGGAA".to_vec(), Some(7)),
+            ]
+        );
+
+        // same kmer set as the one of a fasta file, without the kmers less abundant than 2
+        let mut abundances = KmerAbundances::default();
+        let index = |file: &str, stranded: bool, abundances: Option<&mut KmerAbundances<u64>>| {
+            let parameters = IndexParameters {
+                min_abundance: 2,
+                ..IndexParameters::new(5, stranded)
+            };
+            index_labelled_kmers::<u64, atomic_counter::RelaxedCounter>(
+                &[(String::new(), file.to_string())],
+                &parameters,
+                None,
+                abundances,
+                None,
+            )
+            .map(|(kmer_set, _)| kmer_set)
+        };
+        let (kmer_set, _) = index(&name, false, Some(&mut abundances))?;
+        let fasta = format!("{}", temp_dir.path().join("kmers.fasta").display());
+        std::fs::write(&fasta, b">kmers\nACGTANTTTTCNTTCCC\n")?;
+        let (fasta_set, _) = index(&fasta, false, None)?;
+        let mut kmers = kmer_set.keys().copied().collect::<Vec<_>>();
+        kmers.sort();
+        let mut fasta_kmers = fasta_set.keys().copied().collect::<Vec<_>>();
+        fasta_kmers.sort();
+        assert_eq!(kmers, fasta_kmers);
+        assert_eq!(abundances[&u64::from_bytes(b"GGGAA").unwrap()], 7);
+
+        // the kmer size and the strands must be the ones of the database
+        assert!(index(&name, true, None).is_err());
+        write_kmc_database(&name, 4, false, &[(b"ACGT", 3)])?;
+        assert!(index(&name, false, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn kmc_database_bins() -> anyhow::Result<()> {
+        // canonical 5-mers of kmc_k5.fasta seen at least twice, in 4 bins and with 1 nucleotide
+        // prefixes
+        let name = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/kmc_k5");
+        let database = KmcDatabase::open(name)?;
+        assert!(database.canonical);
+        assert_eq!(
+            (
+                database.kmer_size,
+                database.lut_prefix_length,
+                database.counter_size
+            ),
+            (5, 1, 1)
+        );
+        assert_eq!(
+            (
+                database.min_count,
+                database.nb_kmers,
+                database.prefixes.len()
+            ),
+            (2, 46, 4 * 4 + 1)
+        );
+
+        let mut expected = HashMap::<Vec<u8>, u64>::new();
+        let mut reader = parse_fastx_file(format!("{}.fasta", name))?;
+        while let Some(record) = reader.next() {
+            for kmer in record?.seq().windows(5) {
+                let mut reverse = kmer.to_vec();
+                crate::count::rev_comp(&mut reverse);
+                *expected.entry(kmer.to_vec().min(reverse)).or_default() += 1;
+            }
+        }
+        expected.retain(|_, count| *count >= 2);
+        let kmers = database
+            .kmers()
+            .map(|(kmer, count)| (kmer, count.unwrap()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(database.kmers().count(), 46);
+        assert_eq!(kmers, expected);
+
+        // the sizes read in a corrupted header do not overflow
+        let temp_dir = tempfile::tempdir()?;
+        let corrupted = format!("{}", temp_dir.path().join("corrupted").display());
+        let pre = std::fs::read(format!("{}.kmc_pre", name))?;
+        std::fs::copy(
+            format!("{}.kmc_suf", name),
+            format!("{}.kmc_suf", corrupted),
+        )?;
+        let header = pre.len()
+            - 8
+            - u32::from_le_bytes(pre[pre.len() - 8..pre.len() - 4].try_into().unwrap()) as usize;
+        for (offset, value) in [
+            (16, 40u64.to_le_bytes()),
+            (12, 40u64.to_le_bytes()),
+            (28, u64::MAX.to_le_bytes()),
+        ] {
+            let mut pre = pre.clone();
+            let size = if offset == 28 { 8 } else { 4 };
+            pre[header + offset..header + offset + size].copy_from_slice(&value[..size]);
+            std::fs::write(format!("{}.kmc_pre", corrupted), pre)?;
+            let error = KmcDatabase::open(&corrupted)
+                .err()
+                .map(|error| error.to_string());
+            assert_eq!(error, Some(format!("corrupted KMC database {}", corrupted)));
+        }
+
+        Ok(())
+    }
 }
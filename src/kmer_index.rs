@@ -0,0 +1,195 @@
+//! Backends of the index of the kmers
+//!
+//! The kmers searched in the sequences are looked up in a [KmerIndex], either:
+//!
+//! - exact: a hash map of the kmers, with the counters of their matches in the sequences (see
+//!   [KmerCounter])
+//! - approximate: a [BloomFilter] of the kmers, using about 10 bits per kmer for a false positive
+//!   rate of 1%, for the kmer sets too large to be stored in a hash map. A kmer of a sequence may
+//!   then be matched while it is not indexed, and the matches of the kmers are not counted: the
+//!   approximate index only filters the sequences
+//!
+//! The kmers loaded from an index file are looked up in the file itself (see
+//! [crate::index_file::MappedKmerSet]).
+
+/* std use */
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/* crates use */
+use ahash::AHashMap as HashMap;
+
+/* project use */
+use crate::kmer::{mix64, Kmer};
+use crate::kmer_counter::KmerCounter;
+
+/// Backend of the index of the kmers (see `--index-backend`)
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexBackend {
+    /// hash map of the kmers, with the counts of their matches
+    #[default]
+    Exact,
+    /// Bloom filter of the kmers, with false positives and without counts
+    Bloom,
+}
+
+/// Index of the kmers, searched in the sequences by [crate::count::shared_kmers_par()]
+pub trait KmerIndex<K, C>: Sync {
+    /// look a kmer up: `None` if it is not indexed, else the counter of its matches, or `None` if
+    /// the index does not count the matches of its kmers
+    fn find(&self, kmer: &K) -> Option<Option<&C>>;
+
+    /// number of indexed kmers (an estimation for an approximate index)
+    fn nb_kmers(&self) -> usize;
+}
+
+impl<K: Kmer, C: KmerCounter> KmerIndex<K, C> for HashMap<K, C> {
+    #[inline]
+    fn find(&self, kmer: &K) -> Option<Option<&C>> {
+        self.get(kmer).map(Some)
+    }
+
+    fn nb_kmers(&self) -> usize {
+        self.len()
+    }
+}
+
+/// false positive rate of a [BloomFilter] if not given (see `--false-positive-rate`)
+pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Bloom filter of kmers, which may be filled from several threads
+///
+/// The positions of the bits of a kmer are derived from two hashes of the kmer (see
+/// [Kmer::hash64()]) by double hashing.
+pub struct BloomFilter {
+    bits: Vec<AtomicU64>,
+    nb_bits: u64,
+    nb_hashes: u32,
+}
+
+impl BloomFilter {
+    /// Create an empty filter for at most `nb_kmers` kmers, with a false positive rate of
+    /// `false_positive_rate` once they are inserted
+    pub fn new(nb_kmers: usize, false_positive_rate: f64) -> anyhow::Result<Self> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            anyhow::bail!(
+                "the false positive rate must be in ]0, 1[, got {}",
+                false_positive_rate
+            );
+        }
+        let ln2 = std::f64::consts::LN_2;
+        let nb_kmers = nb_kmers.max(1) as f64;
+        let nb_words = (-nb_kmers * false_positive_rate.ln() / (ln2 * ln2) / 64.0).ceil() as usize;
+        let nb_bits = 64 * nb_words.max(1) as u64;
+        let nb_hashes = ((nb_bits as f64 / nb_kmers * ln2).round() as u32).clamp(1, 32);
+        Ok(Self {
+            bits: (0..nb_bits / 64).map(|_| AtomicU64::new(0)).collect(),
+            nb_bits,
+            nb_hashes,
+        })
+    }
+
+    /// number of bits of the filter
+    pub fn nb_bits(&self) -> u64 {
+        self.nb_bits
+    }
+
+    /// number of bits set for each kmer
+    pub fn nb_hashes(&self) -> u32 {
+        self.nb_hashes
+    }
+
+    /// positions of the bits of a kmer
+    #[inline]
+    fn positions<K: Kmer>(&self, kmer: &K) -> impl Iterator<Item = u64> + use<K> {
+        let hash = kmer.hash64();
+        // double hashing: the second hash is odd so that the k hashes are distinct, but their
+        // positions may still collide after the range reduction (a bit then counts for several)
+        let step = mix64(hash.rotate_left(32)) | 1;
+        let nb_bits = self.nb_bits;
+        (0..self.nb_hashes as u64).map(move |i| {
+            // multiply-shift range reduction of the i-th hash
+            ((hash.wrapping_add(i.wrapping_mul(step)) as u128 * nb_bits as u128) >> 64) as u64
+        })
+    }
+
+    /// insert a kmer
+    #[inline]
+    pub fn insert<K: Kmer>(&self, kmer: &K) {
+        for position in self.positions(kmer) {
+            self.bits[(position / 64) as usize].fetch_or(1 << (position % 64), Ordering::Relaxed);
+        }
+    }
+
+    /// true if a kmer was probably inserted, false if it was not
+    #[inline]
+    pub fn contains<K: Kmer>(&self, kmer: &K) -> bool {
+        self.positions(kmer).all(|position| {
+            self.bits[(position / 64) as usize].load(Ordering::Relaxed) & (1 << (position % 64))
+                != 0
+        })
+    }
+
+    /// estimation of the number of inserted kmers, from the number of set bits
+    pub fn estimated_len(&self) -> usize {
+        let set_bits = self
+            .bits
+            .iter()
+            .map(|word| word.load(Ordering::Relaxed).count_ones() as u64)
+            .sum::<u64>();
+        if set_bits == self.nb_bits {
+            return usize::MAX;
+        }
+        let (nb_bits, nb_hashes) = (self.nb_bits as f64, self.nb_hashes as f64);
+        (-nb_bits / nb_hashes * (1.0 - set_bits as f64 / nb_bits).ln()).round() as usize
+    }
+}
+
+impl<K: Kmer, C> KmerIndex<K, C> for BloomFilter {
+    #[inline]
+    fn find(&self, kmer: &K) -> Option<Option<&C>> {
+        self.contains(kmer).then_some(None)
+    }
+
+    fn nb_kmers(&self) -> usize {
+        self.estimated_len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /* project use */
+    use super::*;
+
+    #[test]
+    fn bloom_filter() -> anyhow::Result<()> {
+        let filter = BloomFilter::new(10_000, 0.01)?;
+        // about 9.6 bits and 7 hashes per kmer
+        assert_eq!(filter.nb_bits(), 95_872);
+        assert_eq!(filter.nb_hashes(), 7);
+
+        for kmer in 0..10_000u64 {
+            filter.insert(&kmer);
+        }
+        // no false negatives
+        assert!((0..10_000u64).all(|kmer| filter.contains(&kmer)));
+        // about 1% of false positives
+        let false_positives = (10_000..110_000u64)
+            .filter(|kmer| filter.contains(kmer))
+            .count();
+        assert!(
+            false_positives > 500 && false_positives < 1500,
+            "{} false positives",
+            false_positives
+        );
+        assert!(filter.estimated_len().abs_diff(10_000) < 200);
+
+        // the index of the kmers does not count their matches
+        let index: &dyn KmerIndex<u64, atomic_counter::RelaxedCounter> = &filter;
+        assert_eq!(index.find(&42).map(|counter| counter.is_none()), Some(true));
+
+        assert!(BloomFilter::new(10, 0.0).is_err());
+        assert!(BloomFilter::new(10, 1.0).is_err());
+
+        Ok(())
+    }
+}
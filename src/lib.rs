@@ -6,70 +6,741 @@
 use std::io::Write as _;
 
 /* crates use */
-use atomic_counter::{AtomicCounter, RelaxedCounter};
+use atomic_counter::RelaxedCounter;
 
 use anyhow::Context as _;
 
 /* mod declarations */
+pub mod abundance;
+pub mod alphabet;
 pub mod chunks;
+pub mod classification;
 pub mod cli;
+pub mod colors;
 pub mod consts;
 pub mod count;
 pub mod file_parsing;
+pub mod index_file;
+pub mod index_stats;
+pub mod iupac;
+pub mod kmer;
 pub mod kmer_counter;
 pub mod kmer_hash;
+pub mod kmer_index;
+pub mod low_complexity;
 pub mod matched_sequences;
-pub mod sequence_normalizer;
+pub mod options;
+pub mod output;
+pub mod progress;
+pub mod sampling;
+pub mod spaced_seeds;
 
 /* project use */
 use file_parsing::read_file_lines;
 
+use crate::alphabet::Alphabet;
+use crate::colors::{Colored, KmerColors, LabelThreshold};
+use crate::index_file::MappedKmerSet;
+use crate::iupac::DegenerateKmers;
+use crate::kmer::{with_kmer_type, Kmer};
 use crate::kmer_counter::KmerCounter;
+use crate::kmer_hash::{IndexParameters, KmerAbundances, LabelledKmerSet};
+use crate::kmer_index::{BloomFilter, IndexBackend, KmerIndex};
+use crate::low_complexity::{ComplexityMetric, LowComplexity};
+use crate::options::Options;
+use crate::output::OutputFile;
+use crate::sampling::Sampling;
+use crate::spaced_seeds::SpacedSeeds;
 
-/// Extract sequences that contain some kmers
+/// Index the kmers of the `--in-kmers` files or load them from the `--in-index` binary file, as
+/// described by `parameters`, and save them in the `--out-index` binary file if provided
 ///
-/// Also output the kmers that occur in the reads with their number of occurrences.
-#[allow(clippy::too_many_arguments)]
-pub fn back_to_sequences<T: KmerCounter>(
-    in_fasta_reads: String,
-    in_fasta_kmers: String,
-    out_fasta_reads: String,
-    out_txt_kmers: String,
-    output_mapping_positions: bool,
+/// Also returns the labels of the kmers if there are several labelled kmer sets, and their
+/// reference records if they are tracked. The degenerate kmers of the `--in-kmers` files are
+/// expanded in `degenerate` if it is provided, and the abundances of the kmers read from counted
+/// kmer dumps are stored in `abundances` if it is provided (the kmers less abundant than
+/// `--min-abundance` being skipped). The statistics of the indexing are printed, and written in the
+/// `--index-stats` JSON file if provided. The kmers are inserted in `bloom` instead of the returned
+/// kmer set if it is provided (see [get_bloom_filter()]). The kmers of `--in-index` are looked up
+/// in the mapped index file, returned apart from the (then empty) kmer set
+#[allow(clippy::type_complexity)]
+fn get_kmer_set<K: Kmer, T: KmerCounter>(
+    options: &Options,
+    parameters: &IndexParameters<'_>,
+    mut degenerate: Option<&mut DegenerateKmers<K>>,
+    mut abundances: Option<&mut KmerAbundances<K>>,
+    bloom: Option<&mut BloomFilter>,
+) -> anyhow::Result<(LabelledKmerSet<K, T>, Option<MappedKmerSet<K, T>>)> {
+    let (in_index, out_index, index_stats) =
+        (&options.in_index, &options.out_index, &options.index_stats);
+    let ((kmer_set, colors), mapped) = if !in_index.is_empty() {
+        cli::validate_non_empty_file(in_index.to_string())?;
+        let (mapped, colors, header) = index_file::load_index::<K, T>(in_index, parameters)
+            .context("Error loading the index: ")?;
+        match (parameters.low_complexity, header.low_complexity.as_ref()) {
+            (Some(_), None) => eprintln!(
+                "Warning: --no-low-complexity has no effect, the index {} was built without it",
+                in_index
+            ),
+            (Some(requested), Some(filter)) if requested != filter => eprintln!(
+                "Warning: the index {} was built without the {}, the requested low complexity filter has no effect",
+                in_index, filter
+            ),
+            _ => (),
+        }
+        if parameters.min_abundance > 0 {
+            eprintln!(
+                "Warning: --min-abundance has no effect, the kmers are loaded from the index {}",
+                in_index
+            );
+        }
+        if !index_stats.is_empty() {
+            eprintln!(
+                "Warning: --index-stats has no effect, the kmers are loaded from the index {}",
+                in_index
+            );
+        }
+        if parameters.track_origins && header.nb_records == 0 {
+            eprintln!(
+                "Warning: --track-origins has no effect, the index {} was built without it",
+                in_index
+            );
+        }
+        ((ahash::AHashMap::default(), colors), Some(mapped))
+    } else {
+        let files = options
+            .in_kmers
+            .iter()
+            .map(|spec| cli::parse_labelled_file(spec))
+            .collect::<Vec<_>>();
+        for (i, (label, file)) in files.iter().enumerate() {
+            // check that in_fasta_kmers is a non empty file (or a KMC database):
+            if kmer_hash::KmcDatabase::database_name(file).is_none() {
+                cli::validate_non_empty_file(file.clone())?;
+            }
+            if files[..i].iter().any(|(other, _)| other == label) {
+                anyhow::bail!("the label {} is used for several --in-kmers files", label);
+            }
+        }
+        let (kmer_set, stats) = kmer_hash::index_labelled_kmers::<K, T>(
+            &files,
+            &IndexParameters {
+                record_stats: !index_stats.is_empty(),
+                ..*parameters
+            },
+            degenerate.as_deref_mut(),
+            abundances.as_deref_mut(),
+            bloom,
+        )
+        .context("Error indexing kmers: ")?;
+        stats.print_summary();
+        if !index_stats.is_empty() {
+            stats.write_json(index_stats)?;
+            eprintln!("Statistics of the indexing written in file {}", index_stats);
+        }
+        (kmer_set, None)
+    };
+
+    if !out_index.is_empty() {
+        index_file::write_index(
+            out_index,
+            indexed_kmers(&kmer_set, mapped.as_ref()),
+            colors.as_ref(),
+            parameters,
+        )?;
+        eprintln!("Index of the kmers written in file {}", out_index);
+        if degenerate.is_some() {
+            eprintln!("Warning: the degenerate kmers are not stored in the index, only the kmers they stand for");
+        }
+        if abundances.is_some_and(|abundances| !abundances.is_empty()) {
+            eprintln!(
+                "Warning: the abundances of the kmers in the dumps are not stored in the index"
+            );
+        }
+    }
+
+    Ok(((kmer_set, colors), mapped))
+}
+
+/// The kmers of the kmer set, or of the mapped index file, with their counters
+fn indexed_kmers<'a, K: Kmer, T>(
+    kmer_set: &'a ahash::AHashMap<K, T>,
+    mapped: Option<&'a MappedKmerSet<K, T>>,
+) -> impl Iterator<Item = (K, &'a T)> {
+    kmer_set
+        .iter()
+        .map(|(kmer, counter)| (*kmer, counter))
+        .chain(mapped.into_iter().flat_map(|mapped| mapped.iter()))
+}
+
+/// Index the kmers of the `--exclude-kmers` files, if any
+///
+/// The excluded kmers are indexed as the kmers of `--in-kmers` (see [kmer_hash::index_kmers()]),
+/// but without spaced seeds: they are searched as they are in the sequences
+fn get_exclude_set<K: Kmer, T: KmerCounter>(
+    options: &Options,
+    parameters: &IndexParameters<'_>,
+) -> anyhow::Result<Option<ahash::AHashMap<K, T>>> {
+    if options.exclude_kmers.is_empty() {
+        if options.max_exclude_threshold > 0.0 {
+            eprintln!("Warning: --max-exclude-threshold has no effect without --exclude-kmers");
+        }
+        return Ok(None);
+    }
+    let parameters = IndexParameters {
+        track_origins: false,
+        seeds: None,
+        min_abundance: 0,
+        record_stats: false,
+        ..*parameters
+    };
+    let mut exclude_set = ahash::AHashMap::new();
+    for file in &options.exclude_kmers {
+        if kmer_hash::KmcDatabase::database_name(file).is_none() {
+            cli::validate_non_empty_file(file.clone())?;
+        }
+        let ((kmer_set, _), _) = kmer_hash::index_labelled_kmers::<K, T>(
+            &[(String::new(), file.clone())],
+            &parameters,
+            None,
+            None,
+            None,
+        )
+        .with_context(|| format!("Error indexing the excluded kmers of {}: ", file))?;
+        exclude_set.extend(kmer_set);
+    }
+    eprintln!("Indexed {} excluded kmers", exclude_set.len());
+    Ok(Some(exclude_set))
+}
+
+/// true if the kmers have a color (see [colors]): if there are several labelled `--in-kmers` sets,
+/// if their origins are tracked, or if the `--in-index` was built so
+fn is_colored<K: Kmer>(options: &Options) -> anyhow::Result<bool> {
+    if options.in_index.is_empty() {
+        return Ok(options.in_kmers.len() > 1 || options.track_origins);
+    }
+    let header =
+        index_file::read_header::<K>(&options.in_index).context("Error loading the index: ")?;
+    Ok(header.is_colored())
+}
+
+/// Create the Bloom filter of the kmers if they are stored in an approximate index (see
+/// [IndexBackend]), sized for the number of kmers of the `--in-kmers` files (see
+/// [kmer_hash::max_nb_kmers()]) and for the `--false-positive-rate`
+///
+/// The excluded kmers are still stored in an exact index (see [get_exclude_set()])
+fn get_bloom_filter(
+    options: &Options,
+    seeds: Option<&SpacedSeeds>,
+) -> anyhow::Result<Option<BloomFilter>> {
+    if options.index_backend == IndexBackend::Exact {
+        if options.false_positive_rate.is_some() {
+            eprintln!("Warning: --false-positive-rate has no effect without --index-backend bloom");
+        }
+        return Ok(None);
+    }
+    // the approximate index has neither labels nor the list of its kmers (--out-kmers, that needs
+    // the counts of the kmers, is dropped with a warning when the arguments are checked)
+    let unsupported = [
+        (!options.in_index.is_empty(), "--in-index"),
+        (!options.out_index.is_empty(), "--out-index"),
+        (options.in_kmers.len() > 1, "several --in-kmers sets"),
+        (options.track_origins, "--track-origins"),
+        (options.max_mismatches > 0, "--max-mismatches"),
+        (options.expand_iupac > 0, "--expand-iupac"),
+    ];
+    if let Some((_, option)) = unsupported.iter().find(|(used, _)| *used) {
+        anyhow::bail!("{} can not be used with --index-backend bloom", option);
+    }
+    let files = options
+        .in_kmers
+        .iter()
+        .map(|spec| cli::parse_labelled_file(spec))
+        .collect::<Vec<_>>();
+    // each kmer is indexed with its key for each spaced seed
+    let nb_kmers = kmer_hash::max_nb_kmers(&files, options.kmer_size)
+        .context("Error reading the kmers: ")?
+        * seeds.map_or(1, |seeds| seeds.len());
+    Ok(Some(BloomFilter::new(
+        nb_kmers,
+        options
+            .false_positive_rate
+            .unwrap_or(kmer_index::DEFAULT_FALSE_POSITIVE_RATE),
+    )?))
+}
+
+/// Parse the spaced seed masks, if any
+fn get_spaced_seeds<K: Kmer>(options: &Options) -> anyhow::Result<Option<SpacedSeeds>> {
+    if options.spaced_seeds.is_empty() {
+        return Ok(None);
+    }
+    let seeds = SpacedSeeds::new(&options.spaced_seeds, options.kmer_size)?;
+    if seeds.key_size() > K::MAX_KMER_SIZE {
+        anyhow::bail!(
+            "too many spaced seeds for kmers of size {}",
+            options.kmer_size
+        );
+    }
+    if options.max_mismatches > 0 {
+        anyhow::bail!("--max-mismatches can not be used with spaced seeds");
+    }
+    Ok(Some(seeds))
+}
+
+/// Parse the kmer sampling options, if any
+fn get_sampling(options: &Options) -> anyhow::Result<Option<Sampling>> {
+    let sampling = match (options.minimizers, options.syncmers) {
+        (0, 0) => return Ok(None),
+        (window, 0) => Sampling::minimizers(window)?,
+        (0, smer_size) => Sampling::syncmers(smer_size, options.syncmer_offset, options.kmer_size)?,
+        _ => anyhow::bail!("--minimizers and --syncmers can not be used together"),
+    };
+    eprintln!("Only the {} are indexed and searched", sampling);
+    Ok(Some(sampling))
+}
+
+/// Parse the low complexity filter options, if the filter is applied to the indexed kmers
+/// (`--no-low-complexity`) or to the kmers of the queried sequences (`--mask-queries`)
+fn get_low_complexity(options: &Options) -> anyhow::Result<Option<LowComplexity>> {
+    let (metric, threshold) = (
+        options.low_complexity_metric,
+        options.low_complexity_threshold,
+    );
+    if !options.no_low_complexity && !options.mask_queries {
+        if threshold.is_some() || metric != ComplexityMetric::default() {
+            eprintln!("Warning: the low complexity metric and threshold have no effect without --no-low-complexity or --mask-queries");
+        }
+        return Ok(None);
+    }
+    let low_complexity = LowComplexity::new(metric, threshold, options.alphabet)?;
+    if options.mask_queries {
+        eprintln!(
+            "The low complexity kmers ({}) of the queried sequences are skipped",
+            low_complexity
+        );
+    }
+    Ok(Some(low_complexity))
+}
+
+/// Create the set of degenerate kmers if they are expanded (`--expand-iupac` > 0)
+fn get_degenerate_kmers<K: Kmer>(
+    options: &Options,
+    seeds: Option<&SpacedSeeds>,
+    sampling: Option<&Sampling>,
+) -> anyhow::Result<Option<DegenerateKmers<K>>> {
+    if options.expand_iupac == 0 {
+        return Ok(None);
+    }
+    if options.alphabet == Alphabet::Protein {
+        anyhow::bail!("--expand-iupac can not be used with amino acid kmers");
+    }
+    if seeds.is_some() || sampling.is_some() {
+        anyhow::bail!("--expand-iupac can not be used with spaced seeds or a kmer sampling");
+    }
+    if !options.in_index.is_empty() {
+        eprintln!(
+            "Warning: --expand-iupac has no effect, the kmers are loaded from the index {}",
+            options.in_index
+        );
+        return Ok(None);
+    }
+    Ok(Some(DegenerateKmers::new(options.expand_iupac)))
+}
+
+/// Check that the options used with amino acid kmers are supported
+fn check_alphabet(options: &Options) -> anyhow::Result<()> {
+    if options.alphabet == Alphabet::Dna {
+        if options.translate {
+            anyhow::bail!("--translate requires --alphabet protein");
+        }
+        return Ok(());
+    }
+    let unsupported = [
+        (options.query_reverse, "--query-reverse"),
+        (options.max_mismatches > 0, "--max-mismatches"),
+        (!options.spaced_seeds.is_empty(), "--spaced-seed"),
+        (options.syncmers > 0, "--syncmers"),
+    ];
+    if let Some((_, option)) = unsupported.iter().find(|(used, _)| *used) {
+        anyhow::bail!("{} can not be used with amino acid kmers", option);
+    }
+    Ok(())
+}
+
+/// Convert the `LABEL=MIN[:MAX]` label thresholds into [LabelThreshold]s
+fn get_label_thresholds(
+    label_thresholds: &[String],
+    colors: Option<&KmerColors>,
+) -> anyhow::Result<Vec<LabelThreshold>> {
+    label_thresholds
+        .iter()
+        .map(|spec| {
+            let (name, min, max) = cli::parse_label_threshold(spec)?;
+            let Some(colors) = colors else {
+                anyhow::bail!("--label-threshold requires several labelled --in-kmers sets");
+            };
+            let label = colors
+                .label_id(&name)
+                .ok_or_else(|| anyhow::anyhow!("unknown label {} in --label-threshold", name))?;
+            Ok(LabelThreshold { label, min, max })
+        })
+        .collect()
+}
+
+/// Ascii representation of an indexed kmer (or of a spaced seed key)
+fn kmer_to_bytes<K: Kmer>(
+    kmer: &K,
     kmer_size: usize,
+    seeds: Option<&SpacedSeeds>,
+    alphabet: Alphabet,
+) -> Vec<u8> {
+    match seeds {
+        Some(seeds) => seeds.to_bytes(kmer),
+        None => alphabet.kmer_to_bytes(kmer, kmer_size),
+    }
+}
+
+/// Append the total abundance of some kmers in the input kmer dumps to a line of the `--out-kmers`
+/// file, if the input abundances are known
+fn write_kmer_abundance<'a, K: Kmer>(
+    output: &mut impl std::io::Write,
+    abundances: &KmerAbundances<K>,
+    kmers: impl IntoIterator<Item = &'a K>,
+) -> std::io::Result<()> {
+    if !abundances.is_empty() {
+        let abundance = kmers
+            .into_iter()
+            .filter_map(|kmer| abundances.get(kmer))
+            .sum::<u64>();
+        write!(output, " {}", abundance)?;
+    }
+    Ok(())
+}
+
+/// Append the names of the reference records some kmers were read from (comma separated) to a
+/// line of the `--out-kmers` file, given the counters of the kmers, if the origin of the kmers is
+/// tracked
+fn write_kmer_origins<'a, T: KmerCounter + 'a>(
+    output: &mut impl std::io::Write,
+    colors: Option<&KmerColors>,
+    counters: impl IntoIterator<Item = &'a T>,
+) -> std::io::Result<()> {
+    if let Some(colors) = colors.filter(|colors| colors.tracks_origins()) {
+        let mut targets = counters
+            .into_iter()
+            .flat_map(|counter| colors.get(counter))
+            .collect::<Vec<_>>();
+        targets.sort();
+        targets.dedup();
+        let records = targets
+            .iter()
+            .map(|record| colors.records[**record as usize].as_str())
+            .collect::<Vec<_>>();
+        write!(output, " {}", records.join(","))?;
+    }
+    Ok(())
+}
+
+/// Append the degenerate kmers to the `--out-kmers` file, each one with the merged counts of the
+/// kmers it stands for (see [KmerCounter::merged_to_string()])
+fn write_degenerate_kmers<K: Kmer, T: KmerCounter>(
+    output: &mut impl std::io::Write,
+    degenerate: &DegenerateKmers<K>,
+    kmer_set: &ahash::AHashMap<K, T>,
+    colors: Option<&KmerColors>,
+    abundances: &KmerAbundances<K>,
     counted_kmer_threshold: usize,
-    min_threshold: f32,
-    max_threshold: f32,
-    stranded: bool,
-    query_reverse: bool,
-    no_low_complexity: bool,
+) -> std::io::Result<()> {
+    for (probe, kmers) in &degenerate.probes {
+        let counters = kmers.iter().map(|kmer| &kmer_set[kmer]).collect::<Vec<_>>();
+        if counters
+            .iter()
+            .map(|counter| counter.get_count())
+            .sum::<usize>()
+            >= counted_kmer_threshold
+        {
+            output.write_all(probe)?;
+            write!(output, " {}", T::merged_to_string(&counters))?;
+            write_kmer_abundance(output, abundances, kmers)?;
+            write_kmer_origins(output, colors, counters)?;
+            writeln!(output)?;
+        }
+    }
+    Ok(())
+}
+
+/// check that the abundances of the reference records can be estimated, see [abundance]
+fn check_abundances(out_abundances: &str, colors: Option<&KmerColors>) -> anyhow::Result<()> {
+    if !out_abundances.is_empty() && !colors.is_some_and(|colors| colors.tracks_origins()) {
+        anyhow::bail!("--out-abundances requires --track-origins");
+    }
+    Ok(())
+}
+
+/// estimate the abundances of the reference records, given their number of kmers, from the
+/// sequences of the input file `file_name` assigned to them, and write them in the TSV table
+/// `out_abundances`
+fn write_abundances(
+    out_abundances: &str,
+    colors: &KmerColors,
+    kmers: &[usize],
+    file_name: &str,
+    assignments: &abundance::ReadAssignments,
 ) -> anyhow::Result<()> {
-    // check that in_fasta_reads is a non empty file if it exists:
-    if !in_fasta_reads.is_empty() {
-        cli::validate_non_empty_file(in_fasta_reads.clone())?;
+    (|| -> anyhow::Result<_> {
+        let mut output = OutputFile::create(out_abundances, rayon::current_num_threads())?;
+        output.write_all(abundance::HEADER.as_bytes())?;
+        abundance::estimate_abundances(assignments, kmers).write_table(
+            &mut output,
+            &colors.records,
+            kmers,
+        )?;
+        output.finish()?;
+        Ok(())
+    })()
+    .context("Error writing the abundances file")?;
+    println!(
+        "Number of sequences of {} assigned to the references {}, ambiguous {}, unassigned {}",
+        file_name,
+        assignments.assigned(),
+        assignments.ambiguous(),
+        assignments.unassigned
+    );
+    eprintln!(
+        "Abundances of the reference records in the sequences of {} are in file {}",
+        file_name, out_abundances
+    );
+    Ok(())
+}
+
+/// The kmer index of the options, and what its kmers are searched with in the sequences (see
+/// [SearchContext::search()])
+struct SearchContext<K: Kmer, T> {
+    kmer_set: ahash::AHashMap<K, T>,
+    /// kmers of the `--in-index` file, if any: `kmer_set` is then empty
+    mapped: Option<MappedKmerSet<K, T>>,
+    colors: Option<KmerColors>,
+    label_thresholds: Vec<LabelThreshold>,
+    /// number of kmers of each reference record, if their abundances are estimated
+    reference_kmers: Vec<usize>,
+    exclude_set: Option<ahash::AHashMap<K, T>>,
+    mismatch_index: Option<kmer_hash::MismatchIndex<K>>,
+    /// approximate index of the kmers, if any: `kmer_set` is then empty
+    bloom: Option<BloomFilter>,
+    degenerate: Option<DegenerateKmers<K>>,
+    /// abundances of the kmers in the input kmer dumps, only read for the kmers file
+    abundances: KmerAbundances<K>,
+    seeds: Option<SpacedSeeds>,
+    sampling: Option<Sampling>,
+    low_complexity: Option<LowComplexity>,
+    stranded: bool,
+}
+
+impl<K: Kmer, T: KmerCounter> SearchContext<K, T> {
+    /// Check the options, and index the kmers to search (see [get_kmer_set()])
+    fn new(options: &Options) -> anyhow::Result<Self> {
+        check_alphabet(options)?;
+        // amino acid kmers have no reverse complement
+        let stranded = options.stranded || options.alphabet == Alphabet::Protein;
+        let seeds = get_spaced_seeds::<K>(options)?;
+        let sampling = get_sampling(options)?;
+        let low_complexity = get_low_complexity(options)?;
+        let parameters = IndexParameters {
+            kmer_size: options.kmer_size,
+            stranded,
+            // the filter of the indexed kmers
+            low_complexity: low_complexity
+                .as_ref()
+                .filter(|_| options.no_low_complexity),
+            track_origins: options.track_origins,
+            seeds: seeds.as_ref(),
+            sampling: sampling.as_ref(),
+            alphabet: options.alphabet,
+            min_abundance: options.min_abundance,
+            progress: options.progress,
+            record_stats: false,
+        };
+        let mut degenerate = get_degenerate_kmers::<K>(options, seeds.as_ref(), sampling.as_ref())?;
+        let mut bloom = get_bloom_filter(options, seeds.as_ref())?;
+        let mut abundances = KmerAbundances::default();
+        let ((kmer_set, colors), mapped) = get_kmer_set::<K, T>(
+            options,
+            &parameters,
+            degenerate.as_mut(),
+            (!options.out_kmers.is_empty()).then_some(&mut abundances),
+            bloom.as_mut(),
+        )?;
+        let label_thresholds = get_label_thresholds(&options.label_thresholds, colors.as_ref())?;
+        let exclude_set = get_exclude_set::<K, _>(options, &parameters)?;
+        // index the segments of the kmers for the approximate matches
+        let mismatch_index = (options.max_mismatches > 0)
+            .then(|| {
+                kmer_hash::MismatchIndex::new(
+                    indexed_kmers(&kmer_set, mapped.as_ref()).map(|(kmer, _)| kmer),
+                    options.kmer_size,
+                    options.max_mismatches,
+                )
+            })
+            .transpose()?;
+        check_abundances(&options.out_abundances, colors.as_ref())?;
+        let reference_kmers = match &colors {
+            Some(colors) if !options.out_abundances.is_empty() => abundance::reference_kmers(
+                colors,
+                indexed_kmers(&kmer_set, mapped.as_ref()).map(|(_, counter)| counter),
+            ),
+            _ => Vec::new(),
+        };
+        Ok(Self {
+            kmer_set,
+            mapped,
+            colors,
+            label_thresholds,
+            reference_kmers,
+            exclude_set,
+            mismatch_index,
+            bloom,
+            degenerate,
+            abundances,
+            seeds,
+            sampling,
+            low_complexity,
+            stranded,
+        })
+    }
+
+    /// Search of the kmers of the exact kmer set (see [count::KmerSearch::in_index()] for the
+    /// Bloom filter and the mapped index file)
+    fn search(&self, options: &Options) -> count::KmerSearch<'_, K, T, ahash::AHashMap<K, T>> {
+        count::KmerSearch {
+            kmer_set: &self.kmer_set,
+            colors: self.colors.as_ref(),
+            exclude_set: self.exclude_set.as_ref(),
+            mismatch_index: self.mismatch_index.as_ref(),
+            seeds: self.seeds.as_ref(),
+            sampling: self.sampling.as_ref(),
+            // the filter of the kmers of the queried sequences
+            low_complexity: self
+                .low_complexity
+                .as_ref()
+                .filter(|_| options.mask_queries),
+            kmer_size: options.kmer_size,
+            stranded: self.stranded,
+            map_both_strands: false,
+            alphabet: options.alphabet,
+            translate: options.translate,
+        }
     }
-    cli::validate_non_empty_file(in_fasta_kmers.clone())?;
-    // check that in_fasta_kmers is a non empty file:
 
-    let (kmer_set, kmer_size) =
-        kmer_hash::index_kmers::<T>(in_fasta_kmers, kmer_size, stranded, no_low_complexity)
-            .context("Error indexing kmers: ")?;
+    /// Write the kmers counted in the sequences in the `--out-kmers` file, if provided
+    fn write_kmers(&self, options: &Options) -> anyhow::Result<()> {
+        let out_txt_kmers = &options.out_kmers;
+        if out_txt_kmers.is_empty() {
+            return Ok(());
+        }
+        (|| -> anyhow::Result<_> {
+            // prints all kmers from kmer_set
+            let mut output = OutputFile::create(out_txt_kmers, rayon::current_num_threads())?;
+            for (kmer, count) in indexed_kmers(&self.kmer_set, self.mapped.as_ref()) {
+                // the expansions of the degenerate kmers are reported with them
+                if self
+                    .degenerate
+                    .as_ref()
+                    .is_some_and(|degenerate| degenerate.is_expanded(&kmer))
+                {
+                    continue;
+                }
+                if count.get_count() >= options.counted_kmer_threshold {
+                    output.write_all(&kmer_to_bytes(
+                        &kmer,
+                        options.kmer_size,
+                        self.seeds.as_ref(),
+                        options.alphabet,
+                    ))?;
+                    write!(output, " {}", count.to_string())?;
+                    write_kmer_abundance(&mut output, &self.abundances, [&kmer])?;
+                    write_kmer_origins(&mut output, self.colors.as_ref(), [count])?;
+                    writeln!(output)?;
+                }
+            }
+            if let Some(degenerate) = &self.degenerate {
+                write_degenerate_kmers(
+                    &mut output,
+                    degenerate,
+                    &self.kmer_set,
+                    self.colors.as_ref(),
+                    &self.abundances,
+                    options.counted_kmer_threshold,
+                )?;
+            }
+            output.finish()?;
+            Ok(())
+        })()
+        .context("Error writing the kmers file")?;
 
+        eprintln!(
+            "kmers with their number of occurrences in the original sequences are in file {}",
+            out_txt_kmers
+        );
+        Ok(())
+    }
+}
+
+/// Search the kmers in the `--in-sequences` files as described by `search` (see
+/// [SearchContext::search()]), and output the sequences that contain them (see [count::kmers_in_fasta_file_par()]), or only count them if no
+/// output file is provided
+///
+/// The kmer index is a type parameter, so that its lookups are statically dispatched
+fn search_sequences<K, T, I>(
+    options: &Options,
+    search: &count::KmerSearch<'_, K, T, I>,
+    context: &SearchContext<K, T>,
+) -> anyhow::Result<()>
+where
+    K: Kmer,
+    T: KmerCounter,
+    I: KmerIndex<K, T> + ?Sized,
+{
+    let (in_fasta_reads, in_fasta_mates) = (&options.in_sequences, &options.in_mates);
+    let (out_fasta_reads, out_unmatched_reads) = (&options.out_sequences, &options.out_unmatched);
+    let (out_classification, out_abundances) =
+        (&options.out_classification, &options.out_abundances);
     if !out_fasta_reads.is_empty() {
+        // the sequences of the standard input are reported as "-"
+        let file_name = if in_fasta_reads.is_empty() {
+            "-".to_string()
+        } else {
+            in_fasta_reads.clone()
+        };
+        let mut read_assignments =
+            (!out_abundances.is_empty()).then(abundance::ReadAssignments::default);
+        let files = count::SequenceFiles {
+            input: in_fasta_reads.clone(),
+            input_mates: in_fasta_mates.clone(),
+            output: out_fasta_reads.clone(),
+            output_mates: options.out_mates.clone(),
+            unmatched: out_unmatched_reads.clone(),
+            unmatched_mates: options.out_unmatched_mates.clone(),
+            classification: out_classification.clone(),
+        };
         // if an output file is provided, we output the sequences that contain the kmers
-        if output_mapping_positions {
+        if options.output_mapping_positions {
             // if output_mapping_positions is true, we output the kmers with their count and mapping positions
-            let (total_nucleotides, total_kmer, match_kmer) =
-                count::kmers_in_fasta_file_par::<_, matched_sequences::MatchedSequencePositional>(
-                    in_fasta_reads,
-                    &kmer_set,
-                    kmer_size,
-                    out_fasta_reads.clone(),
-                    min_threshold,
-                    max_threshold,
-                    stranded,
-                    query_reverse,
-                    true, // in this case we map both strands
+            let (total_nucleotides, total_kmer, match_kmer, short_sequences) =
+                count::kmers_in_fasta_file_par::<
+                    _,
+                    _,
+                    matched_sequences::MatchedSequencePositional,
+                    _,
+                >(
+                    &files,
+                    // in this case we map both strands
+                    &count::KmerSearch {
+                        map_both_strands: true,
+                        ..*search
+                    },
+                    &context.label_thresholds,
+                    options,
+                    read_assignments.as_mut(),
                 )?;
             eprintln!(
                 "Filtered sequences with exact kmer count and mapping positions are in file {}",
@@ -79,6 +750,10 @@ pub fn back_to_sequences<T: KmerCounter>(
                 "Number of nucleotides seen {}",
                 total_nucleotides
             );
+            println!(
+                "Number of sequences shorter than the kmer size {}",
+                short_sequences
+            );
             println!(
                 "Number of kmer seen {}, number of kmer match {} kmer ratio {:.5}",
                 total_kmer,
@@ -87,17 +762,14 @@ pub fn back_to_sequences<T: KmerCounter>(
             );
         } else {
             // if output_mapping_positions is false, we output the kmers with their count
-            let (total_nucleotides, total_kmer, match_kmer) =
-                count::kmers_in_fasta_file_par::<_, matched_sequences::MachedCount>(
-                    in_fasta_reads,
-                    &kmer_set,
-                    kmer_size,
-                    out_fasta_reads.clone(),
-                    min_threshold,
-                    max_threshold,
-                    stranded,
-                    query_reverse,
-                    false, // in this case we do not map both strands
+            let (total_nucleotides, total_kmer, match_kmer, short_sequences) =
+                count::kmers_in_fasta_file_par::<_, _, matched_sequences::MachedCount, _>(
+                    &files,
+                    // in this case we do not map both strands
+                    search,
+                    &context.label_thresholds,
+                    options,
+                    read_assignments.as_mut(),
                 )?;
             eprintln!(
                 "Filtered sequences with exact kmer count are in file {}",
@@ -108,6 +780,10 @@ pub fn back_to_sequences<T: KmerCounter>(
                 "Number of nucleotides seen {}",
                 total_nucleotides
             );
+            println!(
+                "Number of sequences shorter than the kmer size {}",
+                short_sequences
+            );
             println!(
                 "Number of kmer seen {}, number of kmer match {} ratio {:.5}",
                 total_kmer,
@@ -115,22 +791,55 @@ pub fn back_to_sequences<T: KmerCounter>(
                 match_kmer as f64 / total_kmer as f64 * 100.0
             );
         }
+        if !out_unmatched_reads.is_empty() {
+            eprintln!("Rejected sequences are in file {}", out_unmatched_reads);
+        }
+        if !out_classification.is_empty() {
+            eprintln!(
+                "Classification of the sequences to their best reference is in file {}",
+                out_classification
+            );
+        }
+        if let (Some(read_assignments), Some(colors)) = (read_assignments, search.colors) {
+            write_abundances(
+                out_abundances,
+                colors,
+                &context.reference_kmers,
+                &file_name,
+                &read_assignments,
+            )?;
+        }
     } else {
         // if no output file is provided, only the kmers with their count is output
         eprintln!("No output file provided, only the kmers with their count is output");
-        let (total_nucleotides, total_kmer, match_kmer) =
-            count::only_kmers_in_fasta_file_par::<_, matched_sequences::MachedCount>(
-                in_fasta_reads,
-                &kmer_set,
-                kmer_size,
-                stranded,
-                query_reverse,
+        let (mut total_nucleotides, mut total_kmer, mut match_kmer, mut short_sequences) =
+            count::only_kmers_in_fasta_file_par::<_, _, matched_sequences::MachedCount, _>(
+                in_fasta_reads.clone(),
+                search,
+                options.query_reverse,
             )?;
+        if !in_fasta_mates.is_empty() {
+            // the second mates of paired reads are simply counted as well
+            let (mates_nucleotides, mates_kmer, mates_match_kmer, mates_short_sequences) =
+                count::only_kmers_in_fasta_file_par::<_, _, matched_sequences::MachedCount, _>(
+                    in_fasta_mates.clone(),
+                    search,
+                    options.query_reverse,
+                )?;
+            total_nucleotides += mates_nucleotides;
+            total_kmer += mates_kmer;
+            match_kmer += mates_match_kmer;
+            short_sequences += mates_short_sequences;
+        }
         
         println!(
             "Number of nucleotides seen {}",
             total_nucleotides
         );
+        println!(
+            "Number of sequences shorter than the kmer size {}",
+            short_sequences
+        );
         println!(
             "Number of kmer seen {}, number of kmer match {} ratio {:.5}",
             total_kmer,
@@ -138,92 +847,106 @@ pub fn back_to_sequences<T: KmerCounter>(
             match_kmer as f64 / total_kmer as f64 * 100.0       
         );
     }
-    // if the out_kmers_file is not empty, we output counted kmers in the out_kmers_file file
-    if !out_txt_kmers.is_empty() {
-        (|| -> std::io::Result<_> {
-            // prints all kmers from kmer_set
-            let mut output = std::fs::File::create(&out_txt_kmers)?;
-            for (kmer, count) in kmer_set.iter() {
-                if count.get_count() >= counted_kmer_threshold {
-                    output.write_all(kmer)?;
-                    writeln!(output, " {}", count.to_string())?;
-                }
-            }
-            Ok(())
-        })()
-        .context("Error writing the kmers file")?;
-
-        eprintln!(
-            "kmers with their number of occurrences in the original sequences are in file {}",
-            out_txt_kmers
-        );
-    }
     Ok(())
 }
 
 /// Extract sequences that contain some kmers
 ///
-/// Also output the kmers that occur in the reads with their number of occurrences.
-/// Instead of using a single input file takes a txt file with the path
-/// of multiple files
-#[allow(clippy::too_many_arguments)]
-pub fn back_to_multiple_sequences(
-    in_fasta_filenames: String,
-    in_fasta_kmers: String,
-    out_fasta_filenames: String,
-    out_txt_kmers: String,
-    output_mapping_positions: bool,
-    kmer_size: usize,
-    counted_kmer_threshold: usize,
-    min_threshold: f32,
-    max_threshold: f32,
-    stranded: bool,
-    query_reverse: bool,
-    no_low_complexity: bool,
-) -> anyhow::Result<()> {
+/// Also output the kmers that occur in the reads with their number of occurrences, the
+/// classification of the reads to their best reference in `--out-classification` if provided
+/// (see [classification]), and the abundances of the reference records in `--out-abundances` if
+/// provided (see [abundance]).
+pub fn back_to_sequences<T: KmerCounter>(options: &Options) -> anyhow::Result<()> {
+    with_kmer_type!(options.alphabet.packed_size(options.kmer_size), K => match is_colored::<K>(options)? {
+        // the color of a kmer is stored with its counter
+        true => back_to_sequences_impl::<K, Colored<T>>(options),
+        false => back_to_sequences_impl::<K, T>(options),
+    })
+}
+
+fn back_to_sequences_impl<K: Kmer, T: KmerCounter>(options: &Options) -> anyhow::Result<()> {
+    let (in_fasta_reads, in_fasta_mates) = (&options.in_sequences, &options.in_mates);
+    let (out_fasta_reads, out_txt_kmers) = (&options.out_sequences, &options.out_kmers);
     // check that in_fasta_reads is a non empty file if it exists:
-    if !in_fasta_filenames.is_empty() {
-        cli::validate_non_empty_file(in_fasta_filenames.clone())?;
+    if !in_fasta_reads.is_empty() {
+        cli::validate_non_empty_file(in_fasta_reads.clone())?;
+    }
+    if !in_fasta_mates.is_empty() {
+        cli::validate_non_empty_file(in_fasta_mates.clone())?;
     }
-    cli::validate_non_empty_file(in_fasta_kmers.clone())?;
-    // check that in_fasta_kmers is a non empty file:
 
-    let input_files = read_file_lines(in_fasta_filenames.as_str())
-        .map_err(|e| eprintln!("Error reading file: {}", e))
-        .unwrap();
-    let output_files = read_file_lines(out_fasta_filenames.as_str())
-        .map_err(|e| eprintln!("Error reading file: {}", e))
-        .unwrap();
+    let context = SearchContext::<K, T>::new(options)?;
+    if !options.out_classification.is_empty() && context.colors.is_none() {
+        anyhow::bail!(
+            "--out-classification requires --track-origins or several labelled --in-kmers sets"
+        );
+    }
 
-    if input_files.len() != output_files.len() {
-        // anyhow::bail!("The number of input files and output files must be the same");
-        eprintln!("Error: the number of input files and output files must be the same");
-        // exit the program
-        std::process::exit(1);
+    // if the index is the only requested output, we are done
+    if out_fasta_reads.is_empty() && out_txt_kmers.is_empty() {
+        return Ok(());
     }
 
-    let (kmer_set, kmer_size) = kmer_hash::index_kmers::<RelaxedCounter>(
-        in_fasta_kmers,
-        kmer_size,
-        stranded,
-        no_low_complexity,
-    )
-    .context("Error indexing kmers")?;
+    // the kmers of the sequences are searched either in the Bloom filter, in the mapped index file
+    // or in the kmer set
+    let search = context.search(options);
+    match (&context.bloom, &context.mapped) {
+        (Some(bloom), _) => search_sequences(options, &search.in_index(bloom), &context)?,
+        (None, Some(mapped)) => search_sequences(options, &search.in_index(mapped), &context)?,
+        (None, None) => search_sequences(options, &search, &context)?,
+    }
+    context.write_kmers(options)
+}
 
-    if output_mapping_positions {
+/// Search the kmers in each file of `input_files` as described by `search` (see
+/// [SearchContext::search()]), and output the sequences that contain them in the matching file of `output_files` (see
+/// [count::kmers_in_fasta_file_par()]), their classification in the matching file of
+/// `classification_files` and the abundances of the reference records in the matching file of
+/// `abundance_files` if any
+///
+/// The kmer index is a type parameter, so that its lookups are statically dispatched
+fn search_multiple_sequences<K, T, I>(
+    options: &Options,
+    search: &count::KmerSearch<'_, K, T, I>,
+    context: &SearchContext<K, T>,
+    input_files: &[String],
+    output_files: &[String],
+    classification_files: &[String],
+    abundance_files: &[String],
+) -> anyhow::Result<()>
+where
+    K: Kmer,
+    T: KmerCounter,
+    I: KmerIndex<K, T> + ?Sized,
+{
+    if options.output_mapping_positions {
         // if output_mapping_positions is true, we output the kmers with their count and mapping positions
-        for (in_f, out_f) in input_files.iter().zip(output_files.iter()) {
-            let (total_nucleotides, total_kmer, match_kmer) =
-                count::kmers_in_fasta_file_par::<_, matched_sequences::MatchedSequencePositional>(
-                    in_f.to_string(),
-                    &kmer_set,
-                    kmer_size,
-                    out_f.clone().to_string(),
-                    min_threshold,
-                    max_threshold,
-                    stranded,
-                    query_reverse,
-                    true, // in this case we map both strands
+        for (i, (in_f, out_f)) in input_files.iter().zip(output_files.iter()).enumerate() {
+            // the sequences of each input file are assigned to the reference records separately
+            let mut assignments =
+                (!abundance_files.is_empty()).then(abundance::ReadAssignments::default);
+            let files = count::SequenceFiles {
+                input: in_f.to_string(),
+                output: out_f.to_string(),
+                classification: classification_files.get(i).cloned().unwrap_or_default(),
+                ..Default::default()
+            };
+            let (total_nucleotides, total_kmer, match_kmer, short_sequences) =
+                count::kmers_in_fasta_file_par::<
+                    _,
+                    _,
+                    matched_sequences::MatchedSequencePositional,
+                    _,
+                >(
+                    &files,
+                    // in this case we map both strands
+                    &count::KmerSearch {
+                        map_both_strands: true,
+                        ..*search
+                    },
+                    &context.label_thresholds,
+                    options,
+                    assignments.as_mut(),
                 )?;
             eprintln!(
             "Filtered sequences from {} with exact kmer count and mapping positions are in files specified at {}",
@@ -233,27 +956,52 @@ pub fn back_to_multiple_sequences(
                 "Number of nucleotides seen {}",
                 total_nucleotides
             );
+            println!(
+                "Number of sequences shorter than the kmer size {}",
+                short_sequences
+            );
             println!(
                 "Number of kmer seen {}, number of kmer match {} ratio {:.5}",
                 total_kmer,
                 match_kmer,
                 match_kmer as f64 / total_kmer as f64 * 100.0
             );
+            if !files.classification.is_empty() {
+                eprintln!(
+                    "Classification of the sequences from {} to their best reference is in file {}",
+                    in_f, files.classification
+                );
+            }
+            if let (Some(assignments), Some(colors)) = (assignments, search.colors) {
+                write_abundances(
+                    &abundance_files[i],
+                    colors,
+                    &context.reference_kmers,
+                    in_f,
+                    &assignments,
+                )?;
+            }
         }
     } else {
         // if output_mapping_positions is false, we output the kmers with their count
-        for (in_f, out_f) in input_files.iter().zip(output_files.iter()) {
-            let (total_nucleotides, total_kmer, match_kmer) =
-                count::kmers_in_fasta_file_par::<_, matched_sequences::MachedCount>(
-                    in_f.to_string(),
-                    &kmer_set,
-                    kmer_size,
-                    out_f.clone().to_string(),
-                    min_threshold,
-                    max_threshold,
-                    stranded,
-                    query_reverse,
-                    false, // in this case we do not map both strands
+        for (i, (in_f, out_f)) in input_files.iter().zip(output_files.iter()).enumerate() {
+            // the sequences of each input file are assigned to the reference records separately
+            let mut assignments =
+                (!abundance_files.is_empty()).then(abundance::ReadAssignments::default);
+            let files = count::SequenceFiles {
+                input: in_f.to_string(),
+                output: out_f.to_string(),
+                classification: classification_files.get(i).cloned().unwrap_or_default(),
+                ..Default::default()
+            };
+            let (total_nucleotides, total_kmer, match_kmer, short_sequences) =
+                count::kmers_in_fasta_file_par::<_, _, matched_sequences::MachedCount, _>(
+                    &files,
+                    // in this case we do not map both strands
+                    search,
+                    &context.label_thresholds,
+                    options,
+                    assignments.as_mut(),
                 )?;
             eprintln!(
                 "Filtered sequences from {} with exact kmer count are in files specified at {}",
@@ -263,34 +1011,136 @@ pub fn back_to_multiple_sequences(
                 "Number of nucleotides seen {}",
                 total_nucleotides
             );
+            println!(
+                "Number of sequences shorter than the kmer size {}",
+                short_sequences
+            );
             println!(
                 "Number of kmer seen {}, number of kmer match {} ratio {:.5}",
                 total_kmer,
                 match_kmer,
                 match_kmer as f64 / total_kmer as f64 * 100.0
             );
+            if !files.classification.is_empty() {
+                eprintln!(
+                    "Classification of the sequences from {} to their best reference is in file {}",
+                    in_f, files.classification
+                );
+            }
+            if let (Some(assignments), Some(colors)) = (assignments, search.colors) {
+                write_abundances(
+                    &abundance_files[i],
+                    colors,
+                    &context.reference_kmers,
+                    in_f,
+                    &assignments,
+                )?;
+            }
         }
     }
+    Ok(())
+}
 
-    // if the out_kmers_file is not empty, we output counted kmers in the out_kmers_file file
-    if !out_txt_kmers.is_empty() {
-        (|| -> std::io::Result<_> {
-            // prints all kmers from kmer_set, whaterver their counts count
-            let mut output = std::fs::File::create(&out_txt_kmers)?;
-            for (kmer, count) in kmer_set.iter() {
-                if count.get() >= counted_kmer_threshold {
-                    output.write_all(kmer)?;
-                    writeln!(output, " {}", count.get())?;
-                }
-            }
-            Ok(())
-        })()
-        .context("Error writing the kmers file: {}")?;
+/// Read the txt file `filelist` of the `kind` output files of each of the `input_files`, if
+/// provided (one path per line, in the order of the input files)
+fn read_output_filelist(
+    filelist: &str,
+    kind: &str,
+    input_files: &[String],
+) -> anyhow::Result<Vec<String>> {
+    if filelist.is_empty() {
+        return Ok(Vec::new());
+    }
+    cli::validate_non_empty_file(filelist.to_string())?;
+    let files =
+        read_file_lines(filelist).with_context(|| format!("Error reading file {}", filelist))?;
+    if files.len() != input_files.len() {
+        anyhow::bail!(
+            "the number of {} files and input files must be the same",
+            kind
+        );
+    }
+    Ok(files)
+}
 
-        eprintln!(
-            "kmers with their number of occurrences in the original sequences are in file {}",
-            out_txt_kmers
+/// Extract sequences that contain some kmers
+///
+/// Also output the kmers that occur in the reads with their number of occurrences.
+/// Instead of using a single input file takes a txt file with the path
+/// of multiple files
+pub fn back_to_multiple_sequences(options: &Options) -> anyhow::Result<()> {
+    with_kmer_type!(options.alphabet.packed_size(options.kmer_size), K => match is_colored::<K>(options)? {
+        // the color of a kmer is stored with its counter
+        true => back_to_multiple_sequences_impl::<K, Colored<RelaxedCounter>>(options),
+        false => back_to_multiple_sequences_impl::<K, RelaxedCounter>(options),
+    })
+}
+
+fn back_to_multiple_sequences_impl<K: Kmer, T: KmerCounter>(
+    options: &Options,
+) -> anyhow::Result<()> {
+    let (in_fasta_filenames, out_fasta_filenames) = (&options.in_filelist, &options.out_filelist);
+    // check that in_fasta_reads is a non empty file if it exists:
+    if !in_fasta_filenames.is_empty() {
+        cli::validate_non_empty_file(in_fasta_filenames.clone())?;
+    }
+
+    let input_files = read_file_lines(in_fasta_filenames.as_str())
+        .map_err(|e| eprintln!("Error reading file: {}", e))
+        .unwrap();
+    let output_files = read_file_lines(out_fasta_filenames.as_str())
+        .map_err(|e| eprintln!("Error reading file: {}", e))
+        .unwrap();
+
+    if input_files.len() != output_files.len() {
+        // anyhow::bail!("The number of input files and output files must be the same");
+        eprintln!("Error: the number of input files and output files must be the same");
+        // exit the program
+        std::process::exit(1);
+    }
+    // the classification and the abundances of the sequences of each input file are written in
+    // their own files
+    let classification_files =
+        read_output_filelist(&options.out_classification, "classification", &input_files)?;
+    let abundance_files = read_output_filelist(&options.out_abundances, "abundance", &input_files)?;
+
+    let context = SearchContext::<K, T>::new(options)?;
+    if !classification_files.is_empty() && context.colors.is_none() {
+        anyhow::bail!(
+            "--out-classification requires --track-origins or several labelled --in-kmers sets"
         );
     }
-    Ok(())
+    // the kmers of the sequences are searched either in the Bloom filter, in the mapped index file
+    // or in the kmer set
+    let search = context.search(options);
+    match (&context.bloom, &context.mapped) {
+        (Some(bloom), _) => search_multiple_sequences(
+            options,
+            &search.in_index(bloom),
+            &context,
+            &input_files,
+            &output_files,
+            &classification_files,
+            &abundance_files,
+        )?,
+        (None, Some(mapped)) => search_multiple_sequences(
+            options,
+            &search.in_index(mapped),
+            &context,
+            &input_files,
+            &output_files,
+            &classification_files,
+            &abundance_files,
+        )?,
+        (None, None) => search_multiple_sequences(
+            options,
+            &search,
+            &context,
+            &input_files,
+            &output_files,
+            &classification_files,
+            &abundance_files,
+        )?,
+    }
+    context.write_kmers(options)
 }
@@ -0,0 +1,209 @@
+//! Low complexity filter of the kmers
+//!
+//! Low complexity kmers (eg. homopolymers or short tandem repeats) are shared by many unrelated
+//! sequences. They may be skipped when indexing the kmers (see `--no-low-complexity`), and when
+//! searching the kmers of the queried sequences (see `--mask-queries`), so that they do not
+//! count in the ratios of shared kmers. The complexity of a kmer is measured by one of the
+//! [ComplexityMetric]s, compared to a threshold:
+//!
+//! - entropy: the Shannon entropy of the letters of the kmer (in bits), a kmer being of low
+//!   complexity if its entropy is below the threshold (1.0 by default)
+//! - dust: the DUST score of the kmer, ie. `sum(c * (c - 1) / 2) / (l - 1)` where `c` is the
+//!   number of occurrences of each of the 64 trinucleotides and `l` the number of trinucleotides
+//!   of the kmer, a kmer being of low complexity if its score is above the threshold (2.0 by
+//!   default). Nucleotide kmers only
+//! - repeat: the length of the longest homopolymer or dinucleotide run of the kmer (eg. 6 for
+//!   `ACACAC`), a kmer being of low complexity if it is at least the threshold (12 by default)
+
+/* std use */
+use std::fmt;
+
+/* crates use */
+use entropy::shannon_entropy;
+
+/* project use */
+use crate::alphabet::Alphabet;
+
+/// Metric of the complexity of the kmers
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ComplexityMetric {
+    /// Shannon entropy of the letters
+    #[default]
+    Entropy,
+    /// DUST score of the trinucleotides
+    Dust,
+    /// length of the longest homopolymer or dinucleotide run
+    Repeat,
+}
+
+/// Low complexity filter: a metric and its threshold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LowComplexity {
+    /// metric of the complexity of the kmers
+    pub metric: ComplexityMetric,
+    /// threshold of the metric, see the module documentation
+    pub threshold: f32,
+}
+
+impl Default for LowComplexity {
+    /// the historical filter: kmers with a Shannon entropy < 1.0
+    fn default() -> Self {
+        Self {
+            metric: ComplexityMetric::Entropy,
+            threshold: 1.0,
+        }
+    }
+}
+
+impl LowComplexity {
+    /// filter of the kmers of `alphabet` with a metric, and its threshold (or the default one of
+    /// the metric)
+    pub fn new(
+        metric: ComplexityMetric,
+        threshold: Option<f32>,
+        alphabet: Alphabet,
+    ) -> anyhow::Result<Self> {
+        if metric == ComplexityMetric::Dust && alphabet == Alphabet::Protein {
+            anyhow::bail!("the dust low complexity metric can not be used with amino acid kmers");
+        }
+        let threshold = threshold.unwrap_or(match metric {
+            ComplexityMetric::Entropy => 1.0,
+            ComplexityMetric::Dust => 2.0,
+            ComplexityMetric::Repeat => 12.0,
+        });
+        if !threshold.is_finite() || threshold < 0.0 {
+            anyhow::bail!(
+                "the low complexity threshold must be a positive number, got {}",
+                threshold
+            );
+        }
+        Ok(Self { metric, threshold })
+    }
+
+    /// true if a kmer (ascii letters, normalized) is of low complexity
+    pub fn is_low_complexity(&self, kmer: &[u8]) -> bool {
+        match self.metric {
+            ComplexityMetric::Entropy => shannon_entropy(kmer) < self.threshold,
+            ComplexityMetric::Dust => dust_score(kmer) > self.threshold,
+            ComplexityMetric::Repeat => longest_repeat(kmer) as f32 >= self.threshold,
+        }
+    }
+
+    /// metric and threshold of the filter, as stored in an index file (metric 0 means no filter)
+    pub fn to_header(low_complexity: Option<&Self>) -> (u32, u32) {
+        match low_complexity {
+            None => (0, 0),
+            Some(filter) => (filter.metric as u32 + 1, filter.threshold.to_bits()),
+        }
+    }
+
+    /// filter stored in an index file, see [LowComplexity::to_header()]
+    pub fn from_header(metric: u32, threshold: u32) -> anyhow::Result<Option<Self>> {
+        let metric = match metric {
+            0 => return Ok(None),
+            1 => ComplexityMetric::Entropy,
+            2 => ComplexityMetric::Dust,
+            3 => ComplexityMetric::Repeat,
+            _ => anyhow::bail!("unknown low complexity metric {}", metric),
+        };
+        Ok(Some(Self {
+            metric,
+            threshold: f32::from_bits(threshold),
+        }))
+    }
+}
+
+impl fmt::Display for LowComplexity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.metric {
+            ComplexityMetric::Entropy => {
+                write!(f, "kmers with a Shannon entropy < {}", self.threshold)
+            }
+            ComplexityMetric::Dust => write!(f, "kmers with a DUST score > {}", self.threshold),
+            ComplexityMetric::Repeat => write!(
+                f,
+                "kmers with a homopolymer or dinucleotide run >= {}",
+                self.threshold
+            ),
+        }
+    }
+}
+
+/// DUST score of a nucleotide kmer (see the module documentation), 0 if it is smaller than 4
+pub fn dust_score(kmer: &[u8]) -> f32 {
+    if kmer.len() < 4 {
+        return 0.0;
+    }
+    // (letter >> 1) & 3 is distinct for A, C, G and T
+    let code = |letter: u8| ((letter >> 1) & 3) as usize;
+    let mut counts = [0u32; 64];
+    for triplet in kmer.windows(3) {
+        counts[code(triplet[0]) << 4 | code(triplet[1]) << 2 | code(triplet[2])] += 1;
+    }
+    let score = counts
+        .iter()
+        .map(|count| count * count.saturating_sub(1) / 2)
+        .sum::<u32>();
+    score as f32 / (kmer.len() - 3) as f32
+}
+
+/// length of the longest homopolymer or dinucleotide run of a kmer (see the module documentation)
+pub fn longest_repeat(kmer: &[u8]) -> usize {
+    (1..=2)
+        .map(|period| {
+            // length of the current run of letters equal to the letter `period` positions before
+            let mut run = 0;
+            let mut longest = 0;
+            for i in period..kmer.len() {
+                run = if kmer[i] == kmer[i - period] {
+                    run + 1
+                } else {
+                    0
+                };
+                longest = longest.max(run);
+            }
+            (longest + period).min(kmer.len())
+        })
+        .max()
+        .unwrap_or(kmer.len())
+}
+
+#[cfg(test)]
+mod tests {
+    /* project use */
+    use super::*;
+
+    #[test]
+    fn low_complexity_metrics() -> anyhow::Result<()> {
+        assert_eq!(longest_repeat(b"ACGTACGT"), 2);
+        assert_eq!(longest_repeat(b"GAAAAT"), 4);
+        assert_eq!(longest_repeat(b"TACACACG"), 6);
+        assert_eq!(longest_repeat(b"A"), 1);
+
+        // 6 times AAA
+        assert_eq!(dust_score(b"AAAAAAAA"), 15.0 / 5.0);
+        // 3 times ACA, 3 times CAC
+        assert_eq!(dust_score(b"ACACACAC"), 6.0 / 5.0);
+        assert_eq!(dust_score(b"ACGTTGCA"), 0.0);
+
+        let entropy = LowComplexity::default();
+        assert!(entropy.is_low_complexity(b"AAAAAAAAAA"));
+        assert!(!entropy.is_low_complexity(b"ACGAAAAAAT"));
+        let dust = LowComplexity::new(ComplexityMetric::Dust, Some(1.0), Alphabet::Dna)?;
+        assert!(dust.is_low_complexity(b"ACACACAC"));
+        assert!(!dust.is_low_complexity(b"ACGTTGCA"));
+        let repeat = LowComplexity::new(ComplexityMetric::Repeat, Some(6.0), Alphabet::Dna)?;
+        assert!(repeat.is_low_complexity(b"TACACACG"));
+        assert!(!repeat.is_low_complexity(b"TACACAGG"));
+
+        assert!(LowComplexity::new(ComplexityMetric::Dust, None, Alphabet::Protein).is_err());
+        assert!(LowComplexity::new(ComplexityMetric::Entropy, Some(-1.0), Alphabet::Dna).is_err());
+
+        // the filter is stored in the index files
+        let (metric, threshold) = LowComplexity::to_header(Some(&repeat));
+        assert_eq!(LowComplexity::from_header(metric, threshold)?, Some(repeat));
+        assert_eq!(LowComplexity::from_header(0, 0)?, None);
+
+        Ok(())
+    }
+}
@@ -12,22 +12,40 @@ use back_to_sequences::back_to_multiple_sequences;
 use back_to_sequences::back_to_sequences;
 use back_to_sequences::cli::Args;
 use back_to_sequences::kmer_counter::KmerCounterWithLog;
+use back_to_sequences::kmer_index::IndexBackend;
+use back_to_sequences::options::Options;
 
 ///////////////////////// MAIN /////////////////////////
 
 fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
 
     // Set the number of threads for rayon
     // If the number of threads is not set, rayon will use the number of logical cores
     env::set_var("RAYON_NUM_THREADS", args.threads.to_string());
 
-    // If out_sequences and out_kmers are not provided, we do nothing, we can quit
-    if args.out_sequences.is_empty() && args.out_filelist.is_empty() && args.out_kmers.is_empty() {
+    // The approximate index does not count the occurrences of the kmers
+    if args.index_backend == IndexBackend::Bloom && !args.out_kmers.is_empty() {
+        eprintln!("Warning: --out-kmers is unavailable with --index-backend bloom, the kmers are not counted");
+        args.out_kmers.clear();
+    }
+
+    // If out_sequences, out_kmers and out_index are not provided, we do nothing, we can quit
+    if args.out_sequences.is_empty()
+        && args.out_filelist.is_empty()
+        && args.out_kmers.is_empty()
+        && args.out_index.is_empty()
+    {
         eprintln!("Error: no output file provided, nothing to do");
         std::process::exit(1);
     }
 
+    // The kmers come either from a fasta file or from an index
+    if args.in_kmers.is_empty() == args.in_index.is_empty() {
+        eprintln!("Error: exactly one of --in-kmers and --in-index must be provided");
+        std::process::exit(1);
+    }
+
     // If out_kmers is not provided but output_kmer_positions is true, warn that it has no effect
     if args.out_kmers.is_empty() && args.output_kmer_positions {
         eprintln!("Warning: --output_kmer_positions has no effect without --out-kmers");
@@ -47,6 +65,50 @@ fn main() -> anyhow::Result<()> {
         std::process::exit(1);
     }
 
+    // Paired reads are either given as two files, or interleaved in a single file
+    let paired = args.in_sequences.len() == 2 || args.interleaved;
+    if args.in_sequences.len() == 2 && args.interleaved {
+        eprintln!("Error: --interleaved requires a single --in-sequences file");
+        std::process::exit(1);
+    }
+    if args.out_sequences.len() == 2 && !paired {
+        eprintln!("Error: two --out-sequences files require paired --in-sequences files (or --interleaved)");
+        std::process::exit(1);
+    }
+    if args.out_unmatched.len() == 2 && !paired {
+        eprintln!("Error: two --out-unmatched files require paired --in-sequences files (or --interleaved)");
+        std::process::exit(1);
+    }
+    if !args.out_unmatched.is_empty() && args.out_sequences.is_empty() {
+        eprintln!("Error: --out-unmatched requires --out-sequences");
+        std::process::exit(1);
+    }
+    if !args.out_classification.is_empty()
+        && args.out_sequences.is_empty()
+        && args.out_filelist.is_empty()
+    {
+        eprintln!("Error: --out-classification requires --out-sequences or --out-filelist");
+        std::process::exit(1);
+    }
+    if !args.out_abundances.is_empty()
+        && args.out_sequences.is_empty()
+        && args.out_filelist.is_empty()
+    {
+        eprintln!("Error: --out-abundances requires --out-sequences or --out-filelist");
+        std::process::exit(1);
+    }
+    let options = Options::from(&args);
+
+    if options.min_count > options.max_count {
+        eprintln!("Error: --min-count must be <= --max-count");
+        std::process::exit(1);
+    }
+
+    if args.syncmer_offset > 0 && args.syncmers == 0 {
+        eprintln!("Error: --syncmer-offset requires --syncmers");
+        std::process::exit(1);
+    }
+
     if args.in_sequences.is_empty() && !args.in_filelist.is_empty() {
         if args.out_filelist.is_empty() {
             eprintln!("Error: --in-filelist requires --out-filelist");
@@ -59,51 +121,13 @@ fn main() -> anyhow::Result<()> {
             );
             std::process::exit(1);
         }
-        back_to_multiple_sequences(
-            args.in_filelist,
-            args.in_kmers,
-            args.out_filelist,
-            args.out_kmers,
-            args.output_mapping_positions,
-            args.kmer_size,
-            args.counted_kmer_threshold,
-            args.min_threshold,
-            args.max_threshold,
-            args.stranded,
-            args.query_reverse,
-            args.no_low_complexity,
-        )
+
+        back_to_multiple_sequences(&options)
     } else if args.output_kmer_positions {
         // Use KmerCounterWithLog to log the match position of kmers in the reads
-        back_to_sequences::<std::sync::Mutex<KmerCounterWithLog>>(
-            args.in_sequences,
-            args.in_kmers,
-            args.out_sequences,
-            args.out_kmers,
-            args.output_mapping_positions,
-            args.kmer_size,
-            args.counted_kmer_threshold,
-            args.min_threshold,
-            args.max_threshold,
-            args.stranded,
-            args.query_reverse,
-            args.no_low_complexity,
-        )
+        back_to_sequences::<std::sync::Mutex<KmerCounterWithLog>>(&options)
     } else {
         // Use atomic_counter::RelaxedCounter to only count the number of kmers in the reads
-        back_to_sequences::<atomic_counter::RelaxedCounter>(
-            args.in_sequences,
-            args.in_kmers,
-            args.out_sequences,
-            args.out_kmers,
-            args.output_mapping_positions,
-            args.kmer_size,
-            args.counted_kmer_threshold,
-            args.min_threshold,
-            args.max_threshold,
-            args.stranded,
-            args.query_reverse,
-            args.no_low_complexity,
-        )
+        back_to_sequences::<atomic_counter::RelaxedCounter>(&options)
     }
 }
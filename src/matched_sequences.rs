@@ -2,6 +2,8 @@
 
 use std::fmt;
 
+use ahash::AHashMap as HashMap;
+
 /// round a float to a given number of decimals
 fn round(x: f32, decimals: u32) -> f32 {
     let y = 10i32.pow(decimals) as f32;
@@ -19,15 +21,42 @@ where
     /// add a match to the read
     fn add_match(&mut self, position: usize, forward: bool);
 
+    /// add an approximate match (kmer with substitutions) to the read
+    fn add_approximate_match(&mut self, position: usize, forward: bool);
+
+    /// add a kmer of the excluded set (see `--exclude-kmers`) to the read
+    fn add_excluded_match(&mut self);
+
     /// add value to number of base covered
     fn add_covered_base(&mut self, value: usize);
 
     /// return number of match in MachedCount
     fn match_count(&self) -> usize;
 
+    /// return number of bases covered by the matched kmers
+    fn covered_bases(&self) -> usize;
+
+    /// return number of approximate matches (not counted by [MatchedSequence::match_count()])
+    fn approximate_match_count(&self) -> usize;
+
+    /// return number of kmers of the read belonging to the excluded set
+    fn excluded_count(&self) -> usize;
+
     /// return number of mapped_position
     fn mapped_position_size(&self) -> usize;
 
+    /// add a match of a kmer belonging to the given labels (colored index only)
+    fn add_labels(&mut self, labels: impl Iterator<Item = u32>);
+
+    /// return number of matched kmers belonging to a label (colored index only)
+    fn label_count(&self, label: usize) -> usize;
+
+    /// add a match of a kmer read from the given reference records (tracked origins only)
+    fn add_records(&mut self, records: &[u32]);
+
+    /// return number of matched kmers of each reference record (tracked origins only)
+    fn record_counts(&self) -> &HashMap<u32, usize>;
+
     // /// prints the matched read
     // fn to_string(&self) -> String;
 
@@ -35,6 +64,96 @@ where
     fn percent_shared_kmers(&self) -> f32 {
         100.0 * self.match_count() as f32 / (self.mapped_position_size() as f32)
     }
+
+    /// returns the percentage of the read that was approximately matched
+    fn percent_approximate_kmers(&self) -> f32 {
+        100.0 * self.approximate_match_count() as f32 / (self.mapped_position_size() as f32)
+    }
+
+    /// returns the number and the percentage of approximately matched kmers, formatted as
+    /// ` approximate:count:percent` (approximate matching only)
+    fn approximate_to_string(&self) -> String {
+        format!(
+            " approximate:{}:{}",
+            self.approximate_match_count(),
+            round(self.percent_approximate_kmers(), 5)
+        )
+    }
+
+    /// returns the percentage of the read made of excluded kmers
+    fn percent_excluded_kmers(&self) -> f32 {
+        100.0 * self.excluded_count() as f32 / (self.mapped_position_size() as f32)
+    }
+
+    /// returns the number and the percentage of excluded kmers, formatted as
+    /// ` excluded:count:percent` (excluded kmers only)
+    fn excluded_to_string(&self) -> String {
+        format!(
+            " excluded:{}:{}",
+            self.excluded_count(),
+            round(self.percent_excluded_kmers(), 5)
+        )
+    }
+
+    /// returns the percentage of the read that was matched by kmers of a label
+    fn percent_label_kmers(&self, label: usize) -> f32 {
+        100.0 * self.label_count(label) as f32 / (self.mapped_position_size() as f32)
+    }
+
+    /// returns the number and the percentage of matched kmers of each label, formatted as
+    /// ` label:count:percent` (colored index only)
+    fn labels_to_string(&self, labels: &[String]) -> String {
+        labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                format!(
+                    " {}:{}:{}",
+                    label,
+                    self.label_count(i),
+                    round(self.percent_label_kmers(i), 5)
+                )
+            })
+            .collect()
+    }
+
+    /// returns the reference records sharing the most kmers with the read, with their number of
+    /// shared kmers, formatted as ` origins:record:count[,record:count]` (tracked origins only)
+    fn origins_to_string(&self, records: &[String]) -> String {
+        let Some(best) = self.record_counts().values().max() else {
+            return " origins:*".to_string();
+        };
+        let mut best_records = self
+            .record_counts()
+            .iter()
+            .filter(|(_, count)| *count == best)
+            .map(|(record, _)| *record)
+            .collect::<Vec<_>>();
+        best_records.sort_unstable();
+        let best_records = best_records
+            .iter()
+            .map(|record| format!("{}:{}", records[*record as usize], best))
+            .collect::<Vec<_>>();
+        format!(" origins:{}", best_records.join(","))
+    }
+}
+
+/// increment the counts of the given labels
+fn add_label_counts(label_counts: &mut Vec<usize>, labels: impl Iterator<Item = u32>) {
+    for label in labels {
+        let label = label as usize;
+        if label_counts.len() <= label {
+            label_counts.resize(label + 1, 0);
+        }
+        label_counts[label] += 1;
+    }
+}
+
+/// increment the counts of the given reference records
+fn add_record_counts(record_counts: &mut HashMap<u32, usize>, records: &[u32]) {
+    for record in records {
+        *record_counts.entry(*record).or_default() += 1;
+    }
 }
 
 /// a read matched by a kmer, only counting hte number of matched kmers
@@ -43,6 +162,16 @@ pub struct MachedCount {
     pub mapped_position_size: usize,
     /// number of matched kmers
     pub count: usize,
+    /// number of approximately matched kmers
+    pub approximate_count: usize,
+    /// number of kmers belonging to the excluded set
+    pub excluded_count: usize,
+    /// number of bases covered by the matched kmers
+    pub covered_base: usize,
+    /// number of matched kmers of each label (colored index only)
+    pub label_counts: Vec<usize>,
+    /// number of matched kmers of each reference record (tracked origins only)
+    pub record_counts: HashMap<u32, usize>,
 }
 
 impl fmt::Display for MachedCount {
@@ -62,6 +191,11 @@ impl MatchedSequence for MachedCount {
         MachedCount {
             mapped_position_size,
             count: 0,
+            approximate_count: 0,
+            excluded_count: 0,
+            covered_base: 0,
+            label_counts: Vec::new(),
+            record_counts: HashMap::new(),
         }
     }
 
@@ -69,7 +203,17 @@ impl MatchedSequence for MachedCount {
         self.count += 1;
     }
 
-    fn add_covered_base(&mut self, _value: usize) {}
+    fn add_approximate_match(&mut self, _position: usize, _forward: bool) {
+        self.approximate_count += 1;
+    }
+
+    fn add_excluded_match(&mut self) {
+        self.excluded_count += 1;
+    }
+
+    fn add_covered_base(&mut self, value: usize) {
+        self.covered_base += value;
+    }
 
     /// return number of mapped_position
     fn mapped_position_size(&self) -> usize {
@@ -79,6 +223,34 @@ impl MatchedSequence for MachedCount {
     fn match_count(&self) -> usize {
         self.count
     }
+
+    fn covered_bases(&self) -> usize {
+        self.covered_base
+    }
+
+    fn approximate_match_count(&self) -> usize {
+        self.approximate_count
+    }
+
+    fn excluded_count(&self) -> usize {
+        self.excluded_count
+    }
+
+    fn add_labels(&mut self, labels: impl Iterator<Item = u32>) {
+        add_label_counts(&mut self.label_counts, labels);
+    }
+
+    fn label_count(&self, label: usize) -> usize {
+        self.label_counts.get(label).copied().unwrap_or(0)
+    }
+
+    fn add_records(&mut self, records: &[u32]) {
+        add_record_counts(&mut self.record_counts, records);
+    }
+
+    fn record_counts(&self) -> &HashMap<u32, usize> {
+        &self.record_counts
+    }
 }
 
 /// a read matched by a kmer, store kmer count and position of matched kmers
@@ -90,8 +262,19 @@ pub struct MatchedSequencePositional {
     /// the boolean indicates if the kmer was mapped in the forward or reverse strand
     pub matched_positions: Vec<(usize, bool)>,
 
+    /// Position of the approximately matched kmers in the read (not output)
+    pub approximate_positions: Vec<(usize, bool)>,
+
+    /// number of kmers belonging to the excluded set (not output)
+    pub excluded_count: usize,
+
     /// number of covered_base
     pub covered_base: usize,
+
+    /// number of matched kmers of each label (colored index only)
+    pub label_counts: Vec<usize>,
+    /// number of matched kmers of each reference record (tracked origins only)
+    pub record_counts: HashMap<u32, usize>,
 }
 
 impl MatchedSequence for MatchedSequencePositional {
@@ -99,7 +282,11 @@ impl MatchedSequence for MatchedSequencePositional {
         MatchedSequencePositional {
             mapped_position_size,
             matched_positions: Vec::new(),
+            approximate_positions: Vec::new(),
+            excluded_count: 0,
             covered_base: 0,
+            label_counts: Vec::new(),
+            record_counts: HashMap::new(),
         }
     }
 
@@ -107,6 +294,14 @@ impl MatchedSequence for MatchedSequencePositional {
         self.matched_positions.push((position, forward));
     }
 
+    fn add_approximate_match(&mut self, position: usize, forward: bool) {
+        self.approximate_positions.push((position, forward));
+    }
+
+    fn add_excluded_match(&mut self) {
+        self.excluded_count += 1;
+    }
+
     fn add_covered_base(&mut self, value: usize) {
         self.covered_base += value;
     }
@@ -115,9 +310,37 @@ impl MatchedSequence for MatchedSequencePositional {
         self.matched_positions.len()
     }
 
+    fn covered_bases(&self) -> usize {
+        self.covered_base
+    }
+
+    fn approximate_match_count(&self) -> usize {
+        self.approximate_positions.len()
+    }
+
+    fn excluded_count(&self) -> usize {
+        self.excluded_count
+    }
+
     fn mapped_position_size(&self) -> usize {
         self.mapped_position_size
     }
+
+    fn add_labels(&mut self, labels: impl Iterator<Item = u32>) {
+        add_label_counts(&mut self.label_counts, labels);
+    }
+
+    fn label_count(&self, label: usize) -> usize {
+        self.label_counts.get(label).copied().unwrap_or(0)
+    }
+
+    fn add_records(&mut self, records: &[u32]) {
+        add_record_counts(&mut self.record_counts, records);
+    }
+
+    fn record_counts(&self) -> &HashMap<u32, usize> {
+        &self.record_counts
+    }
 }
 
 impl fmt::Display for MatchedSequencePositional {
@@ -158,8 +381,17 @@ mod tests {
         mc.add_match(4, true);
         mc.add_match(5, false);
         mc.add_match(6, false);
+        mc.add_covered_base(7);
+        mc.add_approximate_match(8, true);
+
+        assert_eq!(mc.to_string(), " 3 6.97674");
+        assert_eq!(mc.covered_bases(), 7);
+        assert_eq!(mc.approximate_to_string(), " approximate:1:2.32558");
 
+        mc.add_excluded_match();
+        mc.add_excluded_match();
         assert_eq!(mc.to_string(), " 3 6.97674");
+        assert_eq!(mc.excluded_to_string(), " excluded:2:4.65116");
     }
 
     #[test]
@@ -177,4 +409,49 @@ mod tests {
 
         assert_eq!(mc.to_string(), " 3 6.97674 4 -5 -6 (7)");
     }
+
+    #[test]
+    fn labelled_match() {
+        let kmer_size = 5;
+        let sequence = b"ACGTGACTACGGCATAGCATCGTAGCTGATGTGTCAGCTGTCAGTCA";
+        let mut mc = MachedCount::new(sequence.len() - kmer_size + 1);
+        mc.add_match(4, true);
+        mc.add_labels([0, 2].into_iter());
+        mc.add_match(5, false);
+        mc.add_labels([2].into_iter());
+
+        let labels = [
+            "host".to_string(),
+            "virus".to_string(),
+            "bacteria".to_string(),
+        ];
+        assert_eq!(mc.to_string(), " 2 4.65116");
+        assert_eq!(
+            mc.labels_to_string(&labels),
+            " host:1:2.32558 virus:0:0 bacteria:2:4.65116"
+        );
+    }
+
+    #[test]
+    fn origins_match() {
+        let kmer_size = 5;
+        let sequence = b"ACGTGACTACGGCATAGCATCGTAGCTGATGTGTCAGCTGTCAGTCA";
+        let records = [
+            "gene1".to_string(),
+            "gene2".to_string(),
+            "gene3".to_string(),
+        ];
+        let mut mc = MatchedSequencePositional::new(sequence.len() - kmer_size + 1);
+        assert_eq!(mc.origins_to_string(&records), " origins:*");
+
+        mc.add_match(4, true);
+        mc.add_records(&[0, 2]);
+        mc.add_match(5, true);
+        mc.add_records(&[2]);
+        assert_eq!(mc.origins_to_string(&records), " origins:gene3:2");
+
+        mc.add_match(6, true);
+        mc.add_records(&[0]);
+        assert_eq!(mc.origins_to_string(&records), " origins:gene1:2,gene3:2");
+    }
 }
@@ -0,0 +1,207 @@
+//! Options of a run, built from the command line arguments
+
+/* std use */
+
+/* crates use */
+
+/* project use */
+use crate::alphabet::Alphabet;
+use crate::cli::{Args, PairMode};
+use crate::kmer_index::IndexBackend;
+use crate::low_complexity::ComplexityMetric;
+
+/// Options of [crate::back_to_sequences()] and [crate::back_to_multiple_sequences()]
+///
+/// They are the command line arguments (see [Args] for their description), the paired files being
+/// split and the optional values being resolved. The empty file names are not used.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// input sequences, the standard input if empty
+    pub in_sequences: String,
+    /// second mates of the paired reads, if they are not interleaved
+    pub in_mates: String,
+    /// list of input sequence files, see `--in-filelist`
+    pub in_filelist: String,
+    /// `[LABEL=]FILE` kmer sets, see `--in-kmers`
+    pub in_kmers: Vec<String>,
+    /// see `--in-index`
+    pub in_index: String,
+    /// output sequences
+    pub out_sequences: String,
+    /// output second mates of the paired reads, if they are not interleaved
+    pub out_mates: String,
+    /// sequences that are not output
+    pub out_unmatched: String,
+    /// second mates of the paired reads that are not output, if they are not interleaved
+    pub out_unmatched_mates: String,
+    /// list of output sequence files, see `--out-filelist`
+    pub out_filelist: String,
+    /// see `--out-kmers`
+    pub out_kmers: String,
+    /// see `--out-index`
+    pub out_index: String,
+    /// see `--index-stats`
+    pub index_stats: String,
+    /// see `--out-classification`
+    pub out_classification: String,
+    /// see `--out-abundances`
+    pub out_abundances: String,
+    /// see `--output-mapping-positions`
+    pub output_mapping_positions: bool,
+    /// see `--counted-kmer-threshold`
+    pub counted_kmer_threshold: usize,
+    /// see `--kmer-size`
+    pub kmer_size: usize,
+    /// see `--min-threshold`
+    pub min_threshold: f32,
+    /// see `--max-threshold`
+    pub max_threshold: f32,
+    /// see `--min-count`
+    pub min_count: usize,
+    /// see `--max-count`, `usize::MAX` if not provided
+    pub max_count: usize,
+    /// see `--min-covered-bases`
+    pub min_covered_bases: usize,
+    /// `LABEL=MIN[:MAX]` thresholds, see `--label-threshold`
+    pub label_thresholds: Vec<String>,
+    /// see `--exclude-kmers`
+    pub exclude_kmers: Vec<String>,
+    /// see `--max-exclude-threshold`
+    pub max_exclude_threshold: f32,
+    /// see `--stranded`
+    pub stranded: bool,
+    /// see `--query-reverse`
+    pub query_reverse: bool,
+    /// see `--no-low-complexity`
+    pub no_low_complexity: bool,
+    /// see `--low-complexity-metric`
+    pub low_complexity_metric: ComplexityMetric,
+    /// see `--low-complexity-threshold`
+    pub low_complexity_threshold: Option<f32>,
+    /// see `--mask-queries`
+    pub mask_queries: bool,
+    /// see `--track-origins`
+    pub track_origins: bool,
+    /// see `--max-mismatches`
+    pub max_mismatches: usize,
+    /// spaced seed masks, see `--spaced-seed`
+    pub spaced_seeds: Vec<String>,
+    /// see `--minimizers`
+    pub minimizers: usize,
+    /// see `--syncmers`
+    pub syncmers: usize,
+    /// see `--syncmer-offset`
+    pub syncmer_offset: usize,
+    /// see `--alphabet`
+    pub alphabet: Alphabet,
+    /// see `--translate`
+    pub translate: bool,
+    /// see `--expand-iupac`
+    pub expand_iupac: usize,
+    /// see `--min-abundance`
+    pub min_abundance: u64,
+    /// see `--keep-original`
+    pub keep_original: bool,
+    /// see `--interleaved`
+    pub interleaved: bool,
+    /// see `--pair-mode`
+    pub pair_mode: PairMode,
+    /// see `--progress`
+    pub progress: bool,
+    /// see `--index-backend`
+    pub index_backend: IndexBackend,
+    /// see `--false-positive-rate` (None for the default rate)
+    pub false_positive_rate: Option<f64>,
+}
+
+impl From<&Args> for Options {
+    fn from(args: &Args) -> Self {
+        let nth = |files: &[String], i: usize| files.get(i).cloned().unwrap_or_default();
+        Self {
+            in_sequences: nth(&args.in_sequences, 0),
+            in_mates: nth(&args.in_sequences, 1),
+            in_filelist: args.in_filelist.clone(),
+            in_kmers: args.in_kmers.clone(),
+            in_index: args.in_index.clone(),
+            out_sequences: nth(&args.out_sequences, 0),
+            out_mates: nth(&args.out_sequences, 1),
+            out_unmatched: nth(&args.out_unmatched, 0),
+            out_unmatched_mates: nth(&args.out_unmatched, 1),
+            out_filelist: args.out_filelist.clone(),
+            out_kmers: args.out_kmers.clone(),
+            out_index: args.out_index.clone(),
+            index_stats: args.index_stats.clone(),
+            out_classification: args.out_classification.clone(),
+            out_abundances: args.out_abundances.clone(),
+            output_mapping_positions: args.output_mapping_positions,
+            counted_kmer_threshold: args.counted_kmer_threshold,
+            kmer_size: args.kmer_size,
+            min_threshold: args.min_threshold,
+            max_threshold: args.max_threshold,
+            min_count: args.min_count,
+            max_count: args.max_count.unwrap_or(usize::MAX),
+            min_covered_bases: args.min_covered_bases,
+            label_thresholds: args.label_threshold.clone(),
+            exclude_kmers: args.exclude_kmers.clone(),
+            max_exclude_threshold: args.max_exclude_threshold,
+            stranded: args.stranded,
+            query_reverse: args.query_reverse,
+            no_low_complexity: args.no_low_complexity,
+            low_complexity_metric: args.low_complexity_metric,
+            low_complexity_threshold: args.low_complexity_threshold,
+            mask_queries: args.mask_queries,
+            track_origins: args.track_origins,
+            max_mismatches: args.max_mismatches,
+            spaced_seeds: args.spaced_seed.clone(),
+            minimizers: args.minimizers,
+            syncmers: args.syncmers,
+            syncmer_offset: args.syncmer_offset,
+            alphabet: args.alphabet,
+            translate: args.translate,
+            expand_iupac: args.expand_iupac,
+            min_abundance: args.min_abundance,
+            keep_original: args.keep_original,
+            interleaved: args.interleaved,
+            pair_mode: args.pair_mode,
+            progress: args.progress,
+            index_backend: args.index_backend,
+            false_positive_rate: args.false_positive_rate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /* crates use */
+    use clap::Parser as _;
+
+    /* project use */
+    use super::*;
+
+    #[test]
+    fn from_args() {
+        let args = Args::parse_from([
+            "back_to_sequences",
+            "--in-kmers",
+            "kmers.fa",
+            "--in-sequences",
+            "R1.fq",
+            "R2.fq",
+            "--out-sequences",
+            "out.fq",
+            "--min-count",
+            "2",
+        ]);
+        let options = Options::from(&args);
+        assert_eq!(
+            (options.in_sequences.as_str(), options.in_mates.as_str()),
+            ("R1.fq", "R2.fq")
+        );
+        // the pairs are interleaved in a single output file
+        assert_eq!(
+            (options.out_sequences.as_str(), options.out_mates.as_str()),
+            ("out.fq", "")
+        );
+        assert_eq!((options.min_count, options.max_count), (2, usize::MAX));
+    }
+}
@@ -0,0 +1,396 @@
+//! Output files, compressed according to their extension
+//!
+//! - `.gz`: gzip, made of independent gzip members of [GZIP_BLOCK_SIZE] bytes that are compressed
+//!   in parallel (the result is a standard multi-member gzip file)
+//! - `.bgz`: BGZF (blocked gzip, as used by samtools/htslib), compressed in parallel
+//! - `.zst`: zstd, using the multi-threaded zstd compressor
+//! - other extensions: not compressed
+//!
+//! The compression is performed by dedicated threads, so that the thread writing the output
+//! (eg. the writer of [crate::chunks::Pipeline::run()]) does not become a bottleneck.
+
+/* std use */
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/* crates use */
+use anyhow::Context as _;
+
+/* project use */
+
+/// size of the uncompressed data of each member of a gzip output
+pub const GZIP_BLOCK_SIZE: usize = 1 << 20;
+
+/// maximal size of the uncompressed data of each block of a BGZF output
+pub const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+/// maximal size of a compressed BGZF block
+const BGZF_MAX_BLOCK_SIZE: usize = 1 << 16;
+
+/// empty BGZF block marking the end of a BGZF file
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Compression format of an output file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// plain file
+    None,
+    /// gzip (`.gz`)
+    Gzip,
+    /// blocked gzip (`.bgz`)
+    Bgzf,
+    /// zstd (`.zst`)
+    Zstd,
+}
+
+impl Compression {
+    /// compression format matching the extension of a file name
+    pub fn from_file_name(file_name: &str) -> Self {
+        match std::path::Path::new(file_name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+        {
+            Some("gz") => Self::Gzip,
+            Some("bgz") => Self::Bgzf,
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Output file, possibly compressed
+///
+/// [OutputFile::finish()] must be called once everything is written, else the end of the
+/// compressed stream may be missing
+pub enum OutputFile {
+    /// plain file
+    Plain(BufWriter<File>),
+    /// gzip or BGZF file
+    Gzip(ParallelGzWriter<BufWriter<File>>),
+    /// zstd file
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl OutputFile {
+    /// create an output file, compressed according to its extension with `threads` threads
+    pub fn create(file_name: &str, threads: usize) -> anyhow::Result<Self> {
+        let file = BufWriter::new(
+            File::create(file_name)
+                .with_context(|| format!("Error: failed to open {} for writing", file_name))?,
+        );
+        let threads = threads.max(1);
+        Ok(match Compression::from_file_name(file_name) {
+            Compression::None => Self::Plain(file),
+            Compression::Gzip => Self::Gzip(ParallelGzWriter::new(file, false, threads)),
+            Compression::Bgzf => Self::Gzip(ParallelGzWriter::new(file, true, threads)),
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+                if threads > 1 {
+                    encoder.multithread(threads as u32)?;
+                }
+                Self::Zstd(encoder)
+            }
+        })
+    }
+
+    /// write the end of the compressed stream and flush the file
+    pub fn finish(self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(mut file) => file.flush(),
+            Self::Gzip(writer) => writer.finish()?.flush(),
+            Self::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Gzip(writer) => writer.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match self {
+            Self::Plain(file) => file.write_all(buf),
+            Self::Gzip(writer) => writer.write_all(buf),
+            Self::Zstd(encoder) => encoder.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Gzip(writer) => writer.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// compress a block of data as a complete gzip member (with the BGZF extra field if `bgzf` is set)
+fn compress_block(data: &[u8], bgzf: bool) -> std::io::Result<Vec<u8>> {
+    let deflate = |level| -> std::io::Result<Vec<u8>> {
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(Vec::with_capacity(data.len() / 2), level);
+        encoder.write_all(data)?;
+        encoder.finish()
+    };
+    let mut compressed = deflate(flate2::Compression::default())?;
+    if bgzf && compressed.len() + 26 > BGZF_MAX_BLOCK_SIZE {
+        // incompressible data, the block must be stored to fit in a BGZF block
+        compressed = deflate(flate2::Compression::none())?;
+    }
+
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+
+    let mut member = Vec::with_capacity(compressed.len() + 26);
+    if bgzf {
+        let block_size = (compressed.len() + 25) as u16;
+        member.extend_from_slice(&[
+            0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0,
+        ]);
+        member.extend_from_slice(&block_size.to_le_bytes());
+    } else {
+        member.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x00, 0, 0, 0, 0, 0, 0xff]);
+    }
+    member.extend_from_slice(&compressed);
+    member.extend_from_slice(&crc.sum().to_le_bytes());
+    member.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    Ok(member)
+}
+
+/// Gzip (or BGZF) writer compressing blocks of data in parallel
+///
+/// Each block is compressed as an independent gzip member by a pool of dedicated threads, the
+/// members are written in order in the inner writer
+pub struct ParallelGzWriter<W: Write> {
+    inner: W,
+    bgzf: bool,
+    block_size: usize,
+    max_pending: usize,
+    buf: Vec<u8>,
+    jobs: Option<SyncSender<(usize, Vec<u8>)>>,
+    results: Receiver<(usize, std::io::Result<Vec<u8>>)>,
+    workers: Vec<JoinHandle<()>>,
+    /// compressed blocks received out of order
+    pending: BTreeMap<usize, Vec<u8>>,
+    /// id of the next block to be compressed
+    next_block: usize,
+    /// id of the next block to be written
+    next_write: usize,
+}
+
+impl<W: Write> ParallelGzWriter<W> {
+    /// create a gzip (or BGZF if `bgzf` is set) writer using `threads` compression threads
+    pub fn new(inner: W, bgzf: bool, threads: usize) -> Self {
+        let max_pending = 4 * threads;
+        let (jobs, jobs_rx) = sync_channel::<(usize, Vec<u8>)>(max_pending);
+        let (results_tx, results) = sync_channel(max_pending);
+        let jobs_rx = Arc::new(Mutex::new(jobs_rx));
+
+        let workers = (0..threads)
+            .map(|_| {
+                let jobs_rx = Arc::clone(&jobs_rx);
+                let results_tx = results_tx.clone();
+                std::thread::spawn(move || loop {
+                    let job = jobs_rx.lock().expect("poisoned lock").recv();
+                    let Ok((block_id, data)) = job else { break };
+                    if results_tx
+                        .send((block_id, compress_block(&data, bgzf)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        let block_size = if bgzf {
+            BGZF_BLOCK_SIZE
+        } else {
+            GZIP_BLOCK_SIZE
+        };
+        Self {
+            inner,
+            bgzf,
+            block_size,
+            max_pending,
+            buf: Vec::with_capacity(block_size),
+            jobs: Some(jobs),
+            results,
+            workers,
+            pending: BTreeMap::new(),
+            next_block: 0,
+            next_write: 0,
+        }
+    }
+
+    /// send the buffered data to the compression threads
+    fn send_block(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        // limit the number of blocks in flight
+        while self.next_block - self.next_write >= self.max_pending {
+            self.receive_block()?;
+        }
+        let data = std::mem::replace(&mut self.buf, Vec::with_capacity(self.block_size));
+        self.jobs
+            .as_ref()
+            .expect("writer already finished")
+            .send((self.next_block, data))
+            .map_err(|_| std::io::Error::other("gzip compression thread stopped"))?;
+        self.next_block += 1;
+        Ok(())
+    }
+
+    /// wait for a compressed block, and write all the blocks that are ready in order
+    fn receive_block(&mut self) -> std::io::Result<()> {
+        let (block_id, member) = self
+            .results
+            .recv()
+            .map_err(|_| std::io::Error::other("gzip compression thread stopped"))?;
+        self.pending.insert(block_id, member?);
+        while let Some(member) = self.pending.remove(&self.next_write) {
+            self.inner.write_all(&member)?;
+            self.next_write += 1;
+        }
+        Ok(())
+    }
+
+    /// compress and write all the buffered data
+    fn write_pending(&mut self) -> std::io::Result<()> {
+        self.send_block()?;
+        while self.next_write < self.next_block {
+            self.receive_block()?;
+        }
+        Ok(())
+    }
+
+    /// write all the data and the end of file marker (BGZF only), stop the compression threads
+    /// and return the inner writer
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.write_pending()?;
+        if self.bgzf {
+            self.inner.write_all(&BGZF_EOF)?;
+        }
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            worker.join().expect("panic in a gzip compression thread");
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ParallelGzWriter<W> {
+    fn write(&mut self, mut buf: &[u8]) -> std::io::Result<usize> {
+        let written = buf.len();
+        while !buf.is_empty() {
+            let len = buf.len().min(self.block_size - self.buf.len());
+            self.buf.extend_from_slice(&buf[..len]);
+            buf = &buf[len..];
+            if self.buf.len() == self.block_size {
+                self.send_block()?;
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_pending()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /* std use */
+    use std::io::Read as _;
+
+    /* crate use */
+    use biotest::values::Generate as _;
+
+    /* project use */
+    use super::*;
+
+    #[test]
+    fn compression_from_extension() {
+        assert_eq!(
+            Compression::from_file_name("reads.fasta"),
+            Compression::None
+        );
+        assert_eq!(
+            Compression::from_file_name("reads.fasta.gz"),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_file_name("reads.fq.bgz"),
+            Compression::Bgzf
+        );
+        assert_eq!(
+            Compression::from_file_name("out/kmers.txt.zst"),
+            Compression::Zstd
+        );
+        assert_eq!(Compression::from_file_name("gz"), Compression::None);
+    }
+
+    #[test]
+    fn compressed_outputs() -> anyhow::Result<()> {
+        let mut rng = biotest::rand();
+        let mut data =
+            biotest::values::Nucleotides::Dna.generate(&mut rng, GZIP_BLOCK_SIZE + 12345)?;
+        // incompressible data
+        let mut state = 0x2545f4914f6cdd1du64;
+        data.extend((0..2 * BGZF_BLOCK_SIZE).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }));
+
+        let temp_dir = tempfile::tempdir()?;
+        for name in ["out.txt", "out.gz", "out.bgz", "out.zst"] {
+            let path = temp_dir.path().join(name);
+            let path = path.to_str().unwrap();
+            let mut output = OutputFile::create(path, 3)?;
+            for part in data.chunks(1000) {
+                output.write_all(part)?;
+            }
+            output.finish()?;
+
+            let content = std::fs::read(path)?;
+            let mut decoded = vec![];
+            match Compression::from_file_name(path) {
+                Compression::None => decoded = content.clone(),
+                Compression::Gzip | Compression::Bgzf => {
+                    flate2::read::MultiGzDecoder::new(&content[..]).read_to_end(&mut decoded)?;
+                }
+                Compression::Zstd => decoded = zstd::decode_all(&content[..])?,
+            }
+            assert_eq!(decoded, data, "{}", name);
+
+            if name == "out.bgz" {
+                assert!(content.ends_with(&BGZF_EOF));
+                // walk through the blocks using their BSIZE field
+                let mut pos = 0;
+                while pos < content.len() {
+                    assert_eq!(&content[pos + 12..pos + 14], b"BC");
+                    pos += u16::from_le_bytes([content[pos + 16], content[pos + 17]]) as usize + 1;
+                }
+                assert_eq!(pos, content.len());
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! Progress and memory reports of the long running steps (see `--progress`)
+
+/* std use */
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/* crates use */
+
+/* project use */
+
+/// Progress of the reading of some sequences, reported on stderr every [Progress::STEP] letters
+///
+/// It may be updated from several threads. Nothing is reported if it is disabled.
+pub struct Progress {
+    /// name of the step, eg. `Indexing`
+    step: &'static str,
+    enabled: bool,
+    start: Instant,
+    sequences: AtomicUsize,
+    letters: AtomicUsize,
+}
+
+impl Progress {
+    /// number of letters (nucleotides or amino acids) between two reports
+    pub const STEP: usize = 100_000_000;
+
+    /// Start reporting the progress of a step, if `enabled`
+    pub fn new(step: &'static str, enabled: bool) -> Self {
+        Self {
+            step,
+            enabled,
+            start: Instant::now(),
+            sequences: AtomicUsize::new(0),
+            letters: AtomicUsize::new(0),
+        }
+    }
+
+    /// true if the progress is reported
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// count some read sequences, with their total number of letters
+    pub fn add(&self, sequences: usize, letters: usize) {
+        if !self.enabled {
+            return;
+        }
+        let sequences = self.sequences.fetch_add(sequences, Ordering::Relaxed) + sequences;
+        let before = self.letters.fetch_add(letters, Ordering::Relaxed);
+        if (before + letters) / Self::STEP > before / Self::STEP {
+            eprintln!(
+                "{}: {} sequences, {} million letters read in {:.1}s",
+                self.step,
+                sequences,
+                (before + letters) / 1_000_000,
+                self.start.elapsed().as_secs_f64()
+            );
+        }
+    }
+
+    /// report the total time of the step and the peak memory of the process
+    pub fn finish(&self) {
+        if !self.enabled {
+            return;
+        }
+        eprintln!(
+            "{}: {} sequences read in {:.1}s",
+            self.step,
+            self.sequences.load(Ordering::Relaxed),
+            self.start.elapsed().as_secs_f64()
+        );
+        match peak_memory() {
+            Some(bytes) => eprintln!("Peak memory: {:.1} MB", bytes as f64 / 1_000_000.0),
+            None => eprintln!("Peak memory: unknown (only available on Linux)"),
+        }
+    }
+}
+
+/// peak resident memory of the process in bytes (`VmHWM` of `/proc/self/status`), if known
+pub fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    /* project use */
+    use super::*;
+
+    #[test]
+    fn progress() {
+        let progress = Progress::new("Indexing", true);
+        progress.add(2, Progress::STEP - 1);
+        progress.add(1, 1);
+        assert_eq!(progress.sequences.load(Ordering::Relaxed), 3);
+        assert_eq!(progress.letters.load(Ordering::Relaxed), Progress::STEP);
+
+        // nothing is counted if the progress is not reported
+        let progress = Progress::new("Indexing", false);
+        progress.add(2, 10);
+        assert_eq!(progress.letters.load(Ordering::Relaxed), 0);
+
+        if cfg!(target_os = "linux") {
+            assert!(peak_memory().is_some_and(|bytes| bytes > 0));
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! Sampling of the kmers: minimizers and open syncmers
+//!
+//! To shrink the index of large kmer sets, only a subset of the kmers of the sequences may be
+//! indexed and searched. The same sampling is applied on the indexed sequences and on the queried
+//! ones, so that a kmer shared by both is (mostly) sampled in both:
+//!
+//! - minimizers: the smallest kmer (in the order of [Kmer::hash64()]) of each window of `window`
+//!   consecutive kmers. A shared stretch of `window + kmer_size - 1` nucleotides always shares a
+//!   minimizer, and about 2 / (`window` + 1) of the kmers are sampled.
+//! - open syncmers: the kmers whose smallest s-mer (of size `smer_size`) is at the position
+//!   `offset`. The selection of a kmer does not depend on its neighbours, and about
+//!   1 / (`kmer_size` - `smer_size` + 1) of the kmers are sampled.
+//!
+//! Unless the kmers are stranded, the kmers are ordered by their canonical value (and the s-mers
+//! too, an open syncmer being then also selected if its smallest s-mer is at the symmetric
+//! offset), so that a kmer and its reverse complement are sampled alike.
+
+/* std use */
+use std::collections::VecDeque;
+
+/* crates use */
+
+/* project use */
+use crate::alphabet::Alphabet;
+use crate::kmer::{normalize, Kmer};
+
+/// Kmer sampling scheme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    /// smallest kmer of each window of consecutive kmers
+    Minimizers {
+        /// number of kmers of the windows
+        window: usize,
+    },
+    /// kmers whose smallest s-mer is at a given position
+    Syncmers {
+        /// size of the s-mers
+        smer_size: usize,
+        /// position of the smallest s-mer in the kmers
+        offset: usize,
+    },
+}
+
+impl Sampling {
+    /// minimizers of the windows of `window` consecutive kmers
+    pub fn minimizers(window: usize) -> anyhow::Result<Self> {
+        if window == 0 {
+            anyhow::bail!("the window of the minimizers must contain at least one kmer");
+        }
+        Ok(Self::Minimizers { window })
+    }
+
+    /// open syncmers of kmers of size `kmer_size`, with s-mers of size `smer_size`
+    pub fn syncmers(smer_size: usize, offset: usize, kmer_size: usize) -> anyhow::Result<Self> {
+        if smer_size == 0 || smer_size > kmer_size {
+            anyhow::bail!(
+                "the size of the s-mers of the syncmers must be between 1 and the kmer size ({}), got {}",
+                kmer_size,
+                smer_size
+            );
+        }
+        if offset > kmer_size - smer_size {
+            anyhow::bail!(
+                "the offset of the syncmers must be between 0 and {} (kmer size - s-mer size), got {}",
+                kmer_size - smer_size,
+                offset
+            );
+        }
+        Ok(Self::Syncmers { smer_size, offset })
+    }
+
+    /// kind and parameters of the sampling, as stored in an index file (kind 0 means no sampling)
+    pub fn to_header(sampling: Option<&Self>) -> (u32, u32, u32) {
+        match sampling {
+            None => (0, 0, 0),
+            Some(Self::Minimizers { window }) => (1, *window as u32, 0),
+            Some(Self::Syncmers { smer_size, offset }) => (2, *smer_size as u32, *offset as u32),
+        }
+    }
+
+    /// sampling stored in an index file, see [Sampling::to_header()]
+    pub fn from_header(kind: u32, first: u32, second: u32) -> anyhow::Result<Option<Self>> {
+        match kind {
+            0 => Ok(None),
+            1 => Ok(Some(Self::Minimizers {
+                window: first as usize,
+            })),
+            2 => Ok(Some(Self::Syncmers {
+                smer_size: first as usize,
+                offset: second as usize,
+            })),
+            _ => anyhow::bail!("unknown kmer sampling {}", kind),
+        }
+    }
+
+    /// keep the sampled kmers of an iterator of `(position, forward kmer, reverse complement kmer)`
+    ///
+    /// the kmers of the same sequence must be given in increasing positions (eg. by
+    /// [crate::kmer::KmerIter]), the sampling restarts after each gap in the positions
+    pub fn sample<K, I>(&self, kmers: I, kmer_size: usize, stranded: bool) -> Sampled<K, I>
+    where
+        K: Kmer,
+        I: Iterator<Item = (usize, K, K)>,
+    {
+        Sampled {
+            kmers,
+            sampling: *self,
+            kmer_size,
+            stranded,
+            window: VecDeque::new(),
+            run: 0,
+            previous: None,
+            emitted: None,
+            pending: None,
+        }
+    }
+}
+
+/// kmers of a sequence (see [Alphabet::kmers()]), only the sampled ones if a sampling is given
+#[auto_enums::auto_enum(Iterator)]
+pub fn sampled_kmers<K: Kmer>(
+    alphabet: Alphabet,
+    sequence: &[u8],
+    kmer_size: usize,
+    stranded: bool,
+    sampling: Option<Sampling>,
+) -> impl Iterator<Item = (usize, K, K)> + '_ {
+    let kmers = alphabet.kmers(sequence, kmer_size);
+    match sampling {
+        Some(sampling) => sampling.sample(kmers, alphabet.packed_size(kmer_size), stranded),
+        None => kmers,
+    }
+}
+
+impl std::fmt::Display for Sampling {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Minimizers { window } => write!(f, "minimizers (window of {} kmers)", window),
+            Self::Syncmers { smer_size, offset } => {
+                write!(
+                    f,
+                    "open syncmers (s-mers of size {}, offset {})",
+                    smer_size, offset
+                )
+            }
+        }
+    }
+}
+
+/// Iterator on the sampled kmers, see [Sampling::sample()]
+pub struct Sampled<K, I> {
+    kmers: I,
+    sampling: Sampling,
+    kmer_size: usize,
+    stranded: bool,
+    /// minimizers: candidate kmers of the current window `(position, hash, forward, reverse)`,
+    /// with increasing hashes
+    window: VecDeque<(usize, u64, K, K)>,
+    /// minimizers: number of consecutive kmers read since the last gap
+    run: usize,
+    /// position of the last kmer read
+    previous: Option<usize>,
+    /// position of the last sampled kmer
+    emitted: Option<usize>,
+    /// kmer read after a gap, processed once the minimizer of the previous run is returned
+    pending: Option<(usize, K, K)>,
+}
+
+impl<K: Kmer, I: Iterator<Item = (usize, K, K)>> Sampled<K, I> {
+    /// true if the kmer is an open syncmer
+    fn is_syncmer(&self, forward: K, smer_size: usize, offset: usize) -> bool {
+        let span = self.kmer_size - smer_size;
+        let smallest = (0..=span)
+            .min_by_key(|p| {
+                let smer = forward.segment(*p..*p + smer_size, self.kmer_size);
+                normalize(smer, smer.rev_comp(smer_size), self.stranded)
+                    .0
+                    .hash64()
+            })
+            .unwrap_or_default();
+        smallest == offset || (!self.stranded && smallest == span - offset)
+    }
+
+    /// minimizer of the current run if it is shorter than a window (it was never returned)
+    fn short_run_minimizer(&mut self) -> Option<(usize, K, K)> {
+        let window = match self.sampling {
+            Sampling::Minimizers { window } => window,
+            Sampling::Syncmers { .. } => return None,
+        };
+        if self.run == 0 || self.run >= window {
+            return None;
+        }
+        self.window
+            .front()
+            .map(|(position, _, forward, reverse)| (*position, *forward, *reverse))
+    }
+
+    /// add a kmer to the window of the minimizers, returns the minimizer of the window if it changed
+    fn push_minimizer(
+        &mut self,
+        (position, forward, reverse): (usize, K, K),
+        window: usize,
+    ) -> Option<(usize, K, K)> {
+        let hash = normalize(forward, reverse, self.stranded).0.hash64();
+        // the kmers with a larger hash can not be the minimizer of the next windows
+        while self
+            .window
+            .back()
+            .is_some_and(|(_, other, _, _)| *other > hash)
+        {
+            self.window.pop_back();
+        }
+        self.window.push_back((position, hash, forward, reverse));
+        while self
+            .window
+            .front()
+            .is_some_and(|(other, _, _, _)| other + window <= position)
+        {
+            self.window.pop_front();
+        }
+        self.run += 1;
+
+        if self.run < window {
+            return None;
+        }
+        let (position, _, forward, reverse) = self.window.front()?;
+        (self.emitted != Some(*position)).then_some((*position, *forward, *reverse))
+    }
+}
+
+impl<K: Kmer, I: Iterator<Item = (usize, K, K)>> Iterator for Sampled<K, I> {
+    type Item = (usize, K, K);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(kmer) = self.pending.take().or_else(|| self.kmers.next()) else {
+                // the last run of kmers may be shorter than a window
+                let last = self.short_run_minimizer();
+                self.run = 0;
+                return last;
+            };
+            let sampled = match self.sampling {
+                Sampling::Syncmers { smer_size, offset } => {
+                    self.is_syncmer(kmer.1, smer_size, offset).then_some(kmer)
+                }
+                Sampling::Minimizers { window } => {
+                    if self.previous.is_some_and(|previous| previous + 1 != kmer.0) {
+                        // a gap in the kmers (eg. a N in the sequence), the windows restart after it
+                        let last = self.short_run_minimizer();
+                        self.window.clear();
+                        self.run = 0;
+                        if last.is_some() {
+                            self.pending = Some(kmer);
+                            self.emitted = last.map(|(position, _, _)| position);
+                            return last;
+                        }
+                    }
+                    self.previous = Some(kmer.0);
+                    self.push_minimizer(kmer, window)
+                }
+            };
+            if let Some(sampled) = sampled {
+                self.emitted = Some(sampled.0);
+                return Some(sampled);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /* crate use */
+    use biotest::values::Generate as _;
+
+    /* project use */
+    use super::*;
+    use crate::count::rev_comp;
+    use crate::kmer::KmerIter;
+
+    fn sampled_kmers(sampling: &Sampling, sequence: &[u8], kmer_size: usize) -> Vec<u64> {
+        sampling
+            .sample(KmerIter::<u64>::new(sequence, kmer_size), kmer_size, false)
+            .map(|(_, forward, reverse)| normalize(forward, reverse, false).0)
+            .collect()
+    }
+
+    #[test]
+    fn minimizers() -> anyhow::Result<()> {
+        let mut rng = biotest::rand();
+        let sequence = biotest::values::Nucleotides::Dna.generate(&mut rng, 1000)?;
+        let (kmer_size, window) = (15, 10);
+        let sampling = Sampling::minimizers(window)?;
+
+        let kmers = KmerIter::<u64>::new(&sequence, kmer_size).collect::<Vec<_>>();
+        let sampled = sampling
+            .sample(kmers.iter().copied(), kmer_size, false)
+            .collect::<Vec<_>>();
+        // each window contains its minimizer, and the minimizers are returned once
+        for window in kmers.windows(window) {
+            let smallest = window
+                .iter()
+                .map(|(_, forward, reverse)| normalize(*forward, *reverse, false).0.hash64())
+                .min()
+                .unwrap();
+            assert!(window.iter().any(|kmer| sampled.contains(kmer)
+                && normalize(kmer.1, kmer.2, false).0.hash64() == smallest));
+        }
+        assert!(sampled.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(sampled.len() < kmers.len() / 3);
+
+        // the canonical minimizers of the reverse complement are the same
+        let mut reverse = sequence.to_ascii_uppercase();
+        rev_comp(&mut reverse);
+        let mut forward_minimizers = sampled_kmers(&sampling, &sequence, kmer_size);
+        let mut reverse_minimizers = sampled_kmers(&sampling, &reverse, kmer_size);
+        forward_minimizers.sort();
+        reverse_minimizers.sort();
+        assert_eq!(forward_minimizers, reverse_minimizers);
+
+        // a sequence shorter than a window still has a minimizer, on each side of a N
+        let mut short = sequence[..30].to_vec();
+        short[20] = b'N';
+        assert_eq!(sampled_kmers(&sampling, &short, kmer_size).len(), 1);
+        let mut short = sequence[..40].to_vec();
+        short[17] = b'N';
+        assert_eq!(sampled_kmers(&sampling, &short, kmer_size).len(), 2);
+
+        assert!(Sampling::minimizers(0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn syncmers() -> anyhow::Result<()> {
+        let mut rng = biotest::rand();
+        let sequence = biotest::values::Nucleotides::Dna.generate(&mut rng, 1000)?;
+        let kmer_size = 15;
+        let sampling = Sampling::syncmers(5, 2, kmer_size)?;
+
+        let kmers = KmerIter::<u64>::new(&sequence, kmer_size).count();
+        let sampled = sampled_kmers(&sampling, &sequence, kmer_size);
+        assert!(!sampled.is_empty() && sampled.len() < kmers / 2);
+
+        let mut reverse = sequence.to_ascii_uppercase();
+        rev_comp(&mut reverse);
+        let mut reverse_sampled = sampled_kmers(&sampling, &reverse, kmer_size);
+        let mut sampled = sampled;
+        sampled.sort();
+        reverse_sampled.sort();
+        assert_eq!(sampled, reverse_sampled);
+
+        assert!(Sampling::syncmers(0, 0, kmer_size).is_err());
+        assert!(Sampling::syncmers(16, 0, kmer_size).is_err());
+        assert!(Sampling::syncmers(5, 11, kmer_size).is_err());
+        assert_eq!(
+            Sampling::from_header(2, 5, 2)?,
+            Some(Sampling::Syncmers {
+                smer_size: 5,
+                offset: 2
+            })
+        );
+
+        Ok(())
+    }
+}
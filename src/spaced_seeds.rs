@@ -0,0 +1,204 @@
+//! Spaced seeds: kmers with "don't care" positions
+//!
+//! A spaced seed is a mask of the size of the kmers (eg. `11011011`), only the nucleotides at the
+//! positions of the `1` are indexed and searched, so that a substitution at a `0` position does not
+//! prevent a match. Several masks may be used at once, a kmer of a sequence being matched if it
+//! is matched with any of them.
+//!
+//! The key of a kmer for a mask is a packed [Kmer] made of the id of the mask (encoded on a few
+//! leading nucleotides if there are several masks), followed by the selected nucleotides. Like
+//! contiguous kmers, the canonical key is the smallest of the keys of the forward kmer and of its
+//! reverse complement.
+
+/* std use */
+
+/* crates use */
+
+/* project use */
+use crate::consts::DECODE_MAP;
+use crate::kmer::Kmer;
+
+/// Set of spaced seed masks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpacedSeeds {
+    /// masks, as given by the user
+    masks: Vec<String>,
+    /// selected positions of each mask
+    positions: Vec<Vec<usize>>,
+    /// size of the kmers (and of the masks)
+    kmer_size: usize,
+    /// number of nucleotides encoding the mask id at the start of the keys
+    prefix_size: usize,
+    /// number of nucleotides of the keys
+    key_size: usize,
+}
+
+impl SpacedSeeds {
+    /// parse the masks (made of `0` and `1`), they must all be of size `kmer_size`
+    pub fn new(masks: &[String], kmer_size: usize) -> anyhow::Result<Self> {
+        let mut positions = Vec::with_capacity(masks.len());
+        for (i, mask) in masks.iter().enumerate() {
+            if mask.len() != kmer_size {
+                anyhow::bail!(
+                    "the spaced seed {} must be of the size of the kmers ({})",
+                    mask,
+                    kmer_size
+                );
+            }
+            if mask.bytes().any(|c| c != b'0' && c != b'1') || !mask.contains('1') {
+                anyhow::bail!(
+                    "invalid spaced seed {}, expected a mask of 0 and 1 (with at least one 1)",
+                    mask
+                );
+            }
+            if masks[..i].contains(mask) {
+                anyhow::bail!("the spaced seed {} is given several times", mask);
+            }
+            positions.push(
+                mask.bytes()
+                    .enumerate()
+                    .filter(|(_, c)| *c == b'1')
+                    .map(|(p, _)| p)
+                    .collect::<Vec<_>>(),
+            );
+        }
+        // each nucleotide of the prefix encodes 4 masks
+        let mut prefix_size = 0;
+        while 4usize.pow(prefix_size as u32) < masks.len() {
+            prefix_size += 1;
+        }
+        let weight = positions.iter().map(|p| p.len()).max().unwrap_or(0);
+
+        Ok(Self {
+            masks: masks.to_vec(),
+            positions,
+            kmer_size,
+            prefix_size,
+            key_size: prefix_size + weight,
+        })
+    }
+
+    /// masks, as given by the user
+    pub fn masks(&self) -> &[String] {
+        &self.masks
+    }
+
+    /// number of masks
+    pub fn len(&self) -> usize {
+        self.masks.len()
+    }
+
+    /// true if there is no mask
+    pub fn is_empty(&self) -> bool {
+        self.masks.is_empty()
+    }
+
+    /// number of nucleotides of the keys, it must not exceed [Kmer::MAX_KMER_SIZE]
+    pub fn key_size(&self) -> usize {
+        self.key_size
+    }
+
+    /// key of a kmer for a given mask
+    fn extract<K: Kmer>(&self, mask: usize, kmer: K) -> K {
+        let positions = &self.positions[mask];
+        let prefix = (0..self.prefix_size)
+            .rev()
+            .map(|i| ((mask >> (2 * i)) & 3) as u8)
+            // the keys of the masks with fewer selected positions are padded
+            .chain(std::iter::repeat_n(
+                0,
+                self.key_size - self.prefix_size - positions.len(),
+            ));
+        let selected = positions.iter().map(|p| kmer.code(*p, self.kmer_size));
+        prefix
+            .chain(selected)
+            .fold(K::default(), |acc, code| acc.push(code, self.key_size))
+    }
+
+    /// keys of a kmer and of its reverse complement for a given mask
+    ///
+    /// the key used in the index is selected among them by [crate::kmer::normalize()]
+    #[inline]
+    pub fn keys<K: Kmer>(&self, mask: usize, forward: K, reverse: K) -> (K, K) {
+        (self.extract(mask, forward), self.extract(mask, reverse))
+    }
+
+    /// ascii representation of a key: the kmer with a `N` at each position ignored by its mask
+    pub fn to_bytes<K: Kmer>(&self, key: &K) -> Vec<u8> {
+        let mask = (0..self.prefix_size)
+            .fold(0, |acc, i| (acc << 2) | key.code(i, self.key_size) as usize);
+        let positions = &self.positions[mask];
+        let mut out = vec![b'N'; self.kmer_size];
+        let start = self.key_size - positions.len();
+        for (i, p) in positions.iter().enumerate() {
+            out[*p] = DECODE_MAP[key.code(start + i, self.key_size) as usize];
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /* project use */
+    use super::*;
+    use crate::count::rev_comp;
+    use crate::kmer::normalize;
+
+    #[test]
+    fn spaced_seeds() -> anyhow::Result<()> {
+        let masks = ["1101011".to_string(), "1110111".to_string()];
+        let seeds = SpacedSeeds::new(&masks, 7)?;
+        assert_eq!(seeds.len(), 2);
+        assert_eq!(seeds.key_size(), 7);
+
+        let kmer = |ascii: &[u8]| -> (u64, u64) {
+            let mut rc = ascii.to_vec();
+            rev_comp(&mut rc);
+            (
+                u64::from_bytes(ascii).unwrap(),
+                u64::from_bytes(&rc).unwrap(),
+            )
+        };
+
+        let canonical = |mask, (forward, reverse)| {
+            let (forward, reverse) = seeds.keys(mask, forward, reverse);
+            normalize(forward, reverse, false).0
+        };
+
+        // a substitution at an ignored position does not change the key
+        let (forward, reverse) = kmer(b"ACGTACG");
+        let (mutated, mutated_rc) = kmer(b"ACTTACG");
+        assert_eq!(
+            seeds.keys(0, forward, reverse),
+            seeds.keys(0, mutated, mutated_rc)
+        );
+        assert_ne!(
+            seeds.keys(1, forward, reverse),
+            seeds.keys(1, mutated, mutated_rc)
+        );
+        assert_eq!(
+            seeds.to_bytes(&seeds.keys(0, forward, reverse).0),
+            b"ACNTNCG"
+        );
+        assert_eq!(
+            seeds.to_bytes(&seeds.keys(1, forward, reverse).0),
+            b"ACGNACG"
+        );
+        assert_eq!(
+            seeds.to_bytes(&seeds.keys(0, forward, reverse).1),
+            b"CGNANGT"
+        );
+
+        // the canonical key is the same for both strands, even with an asymmetric mask
+        let key = canonical(0, (forward, reverse));
+        assert_eq!(canonical(0, (reverse, forward)), key);
+        assert_ne!(canonical(1, (forward, reverse)), key);
+
+        assert!(SpacedSeeds::new(&["1101".to_string()], 5).is_err());
+        assert!(SpacedSeeds::new(&["11021".to_string()], 5).is_err());
+        assert!(SpacedSeeds::new(&["00000".to_string()], 5).is_err());
+        assert!(SpacedSeeds::new(&["11011".to_string(), "11011".to_string()], 5).is_err());
+
+        Ok(())
+    }
+}
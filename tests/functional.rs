@@ -631,3 +631,2034 @@ kmers with their number of occurrences in the original sequences are in file {}
 
     Ok(())
 }
+
+#[test]
+fn index_reuse() -> std::result::Result<(), anyhow::Error> {
+    let mut rng = biotest::rand();
+    let s_generate = biotest::Fasta::builder().build()?;
+    let k_generate = biotest::Fasta::builder().sequence_len(10).build()?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let index_path = temp_path.join("kmers.idx");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+
+    let mut reads = vec![];
+    s_generate.records(&mut reads, &mut rng, 100)?;
+    k_generate.create(&kmers_in_path, &mut rng, 500)?;
+
+    // build the index only
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--out-index",
+        &format!("{}", index_path.display()),
+    ]);
+
+    let out = format!(
+        "Indexed 499 kmers, each of size 10
+Index of the kmers written in file {}
+",
+        index_path.display(),
+    );
+
+    cmd.assert().stderr(out).success();
+
+    // query with the index
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-index",
+        &format!("{}", index_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+    ])
+    .write_stdin(reads.clone());
+
+    let out = format!(
+        "Loaded 499 kmers, each of size 10, from index {}
+Filtered sequences with exact kmer count are in file {}
+",
+        index_path.display(),
+        reads_out_path.display(),
+    );
+
+    cmd.assert().stderr(out).success();
+
+    let mut reads_out_content = vec![];
+    std::fs::File::open(&reads_out_path)?.read_to_end(&mut reads_out_content)?;
+    let mut reads_out_truth = vec![];
+    std::fs::File::open("tests/data/reads_out.fasta")?.read_to_end(&mut reads_out_truth)?;
+
+    assert_eq!(reads_out_content, reads_out_truth);
+
+    // mismatched kmer size
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "11",
+        "--in-index",
+        &format!("{}", index_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+    ])
+    .write_stdin(reads);
+
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn labelled_kmer_sets() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let host_path = temp_path.join("host.fasta");
+    let virus_path = temp_path.join("virus.fa.gz");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+
+    std::fs::File::create(&host_path)?
+        .write_all(b">host\nCCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n")?;
+    std::fs::write(
+        &virus_path,
+        b">virus\nTGTCGAGCGACGGAATTAGATCAGTTAAATGGCAGAAAAC\n",
+    )?;
+    let reads = b">read1
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read2
+CCGTAATGCCTTTCCCTAACATCAGTTAAATGGCAGAAAAC
+>read3
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
+";
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-kmers",
+        &format!("{}", host_path.display()),
+        "--in-kmers",
+        &format!("pathogen={}", virus_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+    ])
+    .write_stdin(reads.to_vec());
+
+    cmd.assert().success();
+
+    let mut reads_out_content = String::new();
+    std::fs::File::open(&reads_out_path)?.read_to_string(&mut reads_out_content)?;
+    assert_eq!(
+        reads_out_content,
+        ">read1 31 100 host:31:100 pathogen:0:0
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read2 24 75 host:12:37.5 pathogen:12:37.5
+CCGTAATGCCTTTCCCTAACATCAGTTAAATGGCAGAAAAC
+"
+    );
+
+    // per label threshold
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-kmers",
+        &format!("{}", host_path.display()),
+        "--in-kmers",
+        &format!("pathogen={}", virus_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+        "--label-threshold",
+        "pathogen=10",
+    ])
+    .write_stdin(reads.to_vec());
+
+    cmd.assert().success();
+
+    let mut reads_out_content = String::new();
+    std::fs::File::open(&reads_out_path)?.read_to_string(&mut reads_out_content)?;
+    assert_eq!(
+        reads_out_content,
+        ">read2 24 75 host:12:37.5 pathogen:12:37.5
+CCGTAATGCCTTTCCCTAACATCAGTTAAATGGCAGAAAAC
+"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn track_origins() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let genes_path = temp_path.join("genes.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let kmers_out_path = temp_path.join("kmers_out.txt");
+
+    std::fs::File::create(&genes_path)?.write_all(
+        b">gene1 first gene
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>gene2
+TGTCGAGCGACGGAATTAGATCAGTTAAATGGCAGAAAAC
+",
+    )?;
+    let reads = b">read1
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read2
+CCGTAATGCCTTTCCCTAACATCAGTTAAATGGCAGAAAAC
+>read3
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
+";
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-kmers",
+        &format!("{}", genes_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+        "--out-kmers",
+        &format!("{}", kmers_out_path.display()),
+        "--track-origins",
+    ])
+    .write_stdin(reads.to_vec());
+
+    cmd.assert().success();
+
+    let mut reads_out_content = String::new();
+    std::fs::File::open(&reads_out_path)?.read_to_string(&mut reads_out_content)?;
+    assert_eq!(
+        reads_out_content,
+        ">read1 31 100 origins:gene1:31
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read2 24 75 origins:gene1:12,gene2:12
+CCGTAATGCCTTTCCCTAACATCAGTTAAATGGCAGAAAAC
+"
+    );
+
+    let mut kmers_out_content = String::new();
+    std::fs::File::open(&kmers_out_path)?.read_to_string(&mut kmers_out_content)?;
+    let mut lines = kmers_out_content.lines().collect::<Vec<_>>();
+    lines.sort_unstable();
+    assert_eq!(lines.len(), 62);
+    assert!(lines.contains(&"CCGTAATGCC 2 gene1"));
+    assert!(lines.contains(&"AAATGGCAGA 1 gene2"));
+
+    Ok(())
+}
+
+#[test]
+fn fastq_output() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fastq");
+
+    std::fs::File::create(&kmers_in_path)?
+        .write_all(b">kmers\nCCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n")?;
+    let reads = b"@read1 first read
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
++
+IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIII#
+@read2
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
++
+IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIII
+@read3
+acgtCCGTAATGCCTTTCCCTAACA
++
+#####IIIIIIIIIIIIIIIIIII5
+";
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+    ])
+    .write_stdin(reads.to_vec());
+
+    cmd.assert().success();
+
+    let mut reads_out_content = String::new();
+    std::fs::File::open(&reads_out_path)?.read_to_string(&mut reads_out_content)?;
+    assert_eq!(
+        reads_out_content,
+        "@read1 first read 31 100
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
++
+IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIII#
+@read3 12 75
+ACGTCCGTAATGCCTTTCCCTAACA
++
+#####IIIIIIIIIIIIIIIIIII5
+"
+    );
+
+    // the quality line is reversed with the sequence
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+        "--stranded",
+        "--query-reverse",
+    ])
+    .write_stdin(b"@read3\nTGTTAGGGAAAGGCATTACGGacgt\n+\n5IIIIIIIIIIIIIIIIIII#####\n".to_vec());
+
+    cmd.assert().success();
+
+    let mut reads_out_content = String::new();
+    std::fs::File::open(&reads_out_path)?.read_to_string(&mut reads_out_content)?;
+    assert_eq!(
+        reads_out_content,
+        "@read3 12 75
+ACGTCCGTAATGCCTTTCCCTAACA
++
+#####IIIIIIIIIIIIIIIIIII5
+"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn keep_original() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+
+    std::fs::File::create(&kmers_in_path)?
+        .write_all(b">kmers\nCCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n")?;
+    let reads = b">read1
+CCGTAATGCCTTTCCCTAAC
+agagtttttcgaacRYGTGT
+>read2
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
+>read3
+tgttagggaaaggcattacgg
+";
+
+    let run = |options: &[&str]| -> std::result::Result<String, anyhow::Error> {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "10",
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+        ])
+        .args(options)
+        .write_stdin(reads.to_vec());
+        cmd.assert().success();
+
+        let mut reads_out_content = String::new();
+        std::fs::File::open(&reads_out_path)?.read_to_string(&mut reads_out_content)?;
+        Ok(reads_out_content)
+    };
+
+    assert_eq!(
+        run(&[])?,
+        ">read1 25 80.64517
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACNNGTGT
+>read3 12 100
+TGTTAGGGAAAGGCATTACGG
+"
+    );
+    assert_eq!(
+        run(&["--keep-original"])?,
+        ">read1 25 80.64517
+CCGTAATGCCTTTCCCTAAC
+agagtttttcgaacRYGTGT
+>read3 12 100
+tgttagggaaaggcattacgg
+"
+    );
+    assert_eq!(
+        run(&["--keep-original", "--stranded", "--query-reverse"])?,
+        ">read3 12 100
+tgttagggaaaggcattacgg
+"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn compressed_output() -> std::result::Result<(), anyhow::Error> {
+    let mut rng = biotest::rand();
+    let s_generate = biotest::Fasta::builder().sequence_len(150).build()?;
+    let k_generate = biotest::Fasta::builder().sequence_len(31).build()?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_in_path = temp_path.join("reads_in.fasta");
+
+    k_generate.create(&kmers_in_path, &mut rng, 500)?;
+    s_generate.create(&reads_in_path, &mut rng, 2000)?;
+
+    let run = |reads_out: &str, kmers_out: &str| -> std::result::Result<(), anyhow::Error> {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "11",
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+            "--in-sequences",
+            &format!("{}", reads_in_path.display()),
+            "--out-sequences",
+            &format!("{}", temp_path.join(reads_out).display()),
+            "--out-kmers",
+            &format!("{}", temp_path.join(kmers_out).display()),
+        ]);
+        cmd.assert().success();
+        Ok(())
+    };
+
+    let decode = |name: &str| -> std::result::Result<Vec<u8>, anyhow::Error> {
+        let content = std::fs::read(temp_path.join(name))?;
+        let mut decoded = vec![];
+        if name.ends_with(".zst") {
+            decoded = zstd::decode_all(&content[..])?;
+        } else if name.ends_with("gz") {
+            flate2::read::MultiGzDecoder::new(&content[..]).read_to_end(&mut decoded)?;
+        } else {
+            decoded = content;
+        }
+        Ok(decoded)
+    };
+
+    // the kmers are output in hash order, compare them sorted
+    let sorted_lines = |data: Vec<u8>| {
+        let mut lines = String::from_utf8(data)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        lines.sort_unstable();
+        lines
+    };
+
+    run("reads_out.fasta", "kmers_out.txt")?;
+    let reads = decode("reads_out.fasta")?;
+    let kmers = sorted_lines(decode("kmers_out.txt")?);
+    assert!(!reads.is_empty());
+
+    for extension in ["gz", "bgz", "zst"] {
+        let reads_out = format!("reads_out.fasta.{}", extension);
+        let kmers_out = format!("kmers_out.txt.{}", extension);
+        run(&reads_out, &kmers_out)?;
+        assert_ne!(std::fs::read(temp_path.join(&reads_out))?, reads);
+        assert_eq!(decode(&reads_out)?, reads);
+        assert_eq!(sorted_lines(decode(&kmers_out)?), kmers);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn paired_reads() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads1_in_path = temp_path.join("reads_1.fasta");
+    let reads2_in_path = temp_path.join("reads_2.fasta");
+    let reads1_out_path = temp_path.join("reads_out_1.fasta");
+    let reads2_out_path = temp_path.join("reads_out_2.fasta");
+
+    let matching = "CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT";
+    let not_matching = "TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG";
+    let short = "CCGTAATGCCTTTCCCTAACA";
+
+    std::fs::File::create(&kmers_in_path)?
+        .write_all(format!(">kmers\n{}\n", matching).as_bytes())?;
+    std::fs::File::create(&reads1_in_path)?.write_all(
+        format!(
+            ">pair1/1\n{}\n>pair2/1\n{}\n>pair3/1\n{}\n",
+            matching, not_matching, short
+        )
+        .as_bytes(),
+    )?;
+    std::fs::File::create(&reads2_in_path)?.write_all(
+        format!(
+            ">pair1/2\n{}\n>pair2/2\n{}\n>pair3/2\n{}\n",
+            not_matching, not_matching, short
+        )
+        .as_bytes(),
+    )?;
+
+    let run = |args: &[&str], stdin: Option<String>| -> anyhow::Result<()> {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "10",
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+        ])
+        .args(args);
+        if let Some(stdin) = stdin {
+            cmd.write_stdin(stdin);
+        }
+        cmd.assert().success();
+        Ok(())
+    };
+    let read = |path: &std::path::Path| -> anyhow::Result<String> {
+        let mut content = String::new();
+        std::fs::File::open(path)?.read_to_string(&mut content)?;
+        Ok(content)
+    };
+    let in1 = format!("{}", reads1_in_path.display());
+    let in2 = format!("{}", reads2_in_path.display());
+    let out1 = format!("{}", reads1_out_path.display());
+    let out2 = format!("{}", reads2_out_path.display());
+
+    // the pairs are kept as a whole, the ratio being computed on both mates (31 / 62)
+    run(
+        &[
+            "--in-sequences",
+            &in1,
+            &in2,
+            "--out-sequences",
+            &out1,
+            &out2,
+        ],
+        None,
+    )?;
+    assert_eq!(
+        read(&reads1_out_path)?,
+        format!(
+            ">pair1/1 31 100\n{}\n>pair3/1 12 100\n{}\n",
+            matching, short
+        )
+    );
+    assert_eq!(
+        read(&reads2_out_path)?,
+        format!(
+            ">pair1/2 0 0\n{}\n>pair3/2 12 100\n{}\n",
+            not_matching, short
+        )
+    );
+
+    // interleaved input and output
+    run(
+        &["--interleaved", "-m", "60", "--out-sequences", &out1],
+        Some(format!(
+            ">pair1/1\n{}\n>pair1/2\n{}\n>pair3/1\n{}\n>pair3/2\n{}\n",
+            matching, not_matching, short, short
+        )),
+    )?;
+    assert_eq!(
+        read(&reads1_out_path)?,
+        format!(">pair3/1 12 100\n{}\n>pair3/2 12 100\n{}\n", short, short)
+    );
+
+    // per-mate thresholds
+    run(
+        &[
+            "--in-sequences",
+            &in1,
+            &in2,
+            "-m",
+            "60",
+            "--pair-mode",
+            "either",
+            "--out-sequences",
+            &out1,
+        ],
+        None,
+    )?;
+    assert_eq!(
+        read(&reads1_out_path)?,
+        format!(
+            ">pair1/1 31 100\n{}\n>pair1/2 0 0\n{}\n>pair3/1 12 100\n{}\n>pair3/2 12 100\n{}\n",
+            matching, not_matching, short, short
+        )
+    );
+    run(
+        &[
+            "--in-sequences",
+            &in1,
+            &in2,
+            "-m",
+            "60",
+            "--pair-mode",
+            "both",
+            "--out-sequences",
+            &out1,
+        ],
+        None,
+    )?;
+    assert_eq!(
+        read(&reads1_out_path)?,
+        format!(">pair3/1 12 100\n{}\n>pair3/2 12 100\n{}\n", short, short)
+    );
+
+    // the mates must be in sync
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--in-sequences",
+        &in1,
+        &format!("{}", kmers_in_path.display()),
+        "--out-sequences",
+        &out1,
+    ]);
+    cmd.assert().failure();
+
+    // and have the same names
+    let shuffled_path = temp_path.join("reads_2_shuffled.fasta");
+    std::fs::File::create(&shuffled_path)?.write_all(
+        format!(
+            ">pair2/2\n{}\n>pair1/2\n{}\n>pair3/2\n{}\n",
+            not_matching, not_matching, short
+        )
+        .as_bytes(),
+    )?;
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--in-sequences",
+        &in1,
+        &format!("{}", shuffled_path.display()),
+        "--out-sequences",
+        &out1,
+    ]);
+    let output = cmd.output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("the mates of pair 1 have different names: pair1 and pair2"));
+
+    Ok(())
+}
+
+#[test]
+fn unmatched_output() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let unmatched_out_path = temp_path.join("unmatched_out.fasta");
+
+    std::fs::File::create(&kmers_in_path)?
+        .write_all(b">kmers\nCCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n")?;
+    let reads = b">read1
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read2
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
+>read3
+ACGTCCGTAATGCCTTTCCCTAACA
+";
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "-m",
+        "80",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+        "--out-unmatched",
+        &format!("{}", unmatched_out_path.display()),
+    ])
+    .write_stdin(reads.to_vec());
+
+    cmd.assert().success();
+
+    let mut reads_out_content = String::new();
+    std::fs::File::open(&reads_out_path)?.read_to_string(&mut reads_out_content)?;
+    assert_eq!(
+        reads_out_content,
+        ">read1 31 100
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+"
+    );
+    let mut unmatched_out_content = String::new();
+    std::fs::File::open(&unmatched_out_path)?.read_to_string(&mut unmatched_out_content)?;
+    assert_eq!(
+        unmatched_out_content,
+        ">read2 0 0
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
+>read3 12 75
+ACGTCCGTAATGCCTTTCCCTAACA
+"
+    );
+
+    // the rejected pairs are kept together
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--interleaved",
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+        "--out-unmatched",
+        &format!("{}", unmatched_out_path.display()),
+    ])
+    .write_stdin(
+        b">read1/1
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read1/2
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
+"
+        .to_vec(),
+    );
+
+    cmd.assert().success();
+
+    let mut reads_out_content = String::new();
+    std::fs::File::open(&reads_out_path)?.read_to_string(&mut reads_out_content)?;
+    assert_eq!(
+        reads_out_content,
+        ">read1/1 31 100
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read1/2 0 0
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
+"
+    );
+    let mut unmatched_out_content = String::new();
+    std::fs::File::open(&unmatched_out_path)?.read_to_string(&mut unmatched_out_content)?;
+    assert_eq!(unmatched_out_content, "");
+
+    // --out-unmatched requires --out-sequences
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--out-kmers",
+        &format!("{}", reads_out_path.display()),
+        "--out-unmatched",
+        &format!("{}", unmatched_out_path.display()),
+    ]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn count_thresholds() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+
+    std::fs::File::create(&kmers_in_path)?
+        .write_all(b">kmers\nCCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n")?;
+    let reads = b">read1
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read2
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
+>read3
+ACGTCCGTAATGCCTTTCCCTAACA
+";
+    let read1 = ">read1 31 100\nCCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n";
+    let read3 = ">read3 12 75\nACGTCCGTAATGCCTTTCCCTAACA\n";
+
+    for (thresholds, expected) in [
+        (vec!["--min-count", "20"], read1.to_string()),
+        (vec!["--max-count", "20"], read3.to_string()),
+        (
+            vec!["--min-count", "12", "--max-count", "31"],
+            format!("{}{}", read1, read3),
+        ),
+        (
+            vec!["--min-count", "12", "--max-threshold", "80"],
+            read3.to_string(),
+        ),
+        // read3 has 21 bases covered by indexed kmers
+        (vec!["--min-covered-bases", "22"], read1.to_string()),
+        (
+            vec!["--min-covered-bases", "21"],
+            format!("{}{}", read1, read3),
+        ),
+    ] {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "10",
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+        ])
+        .args(&thresholds)
+        .write_stdin(reads.to_vec());
+
+        cmd.assert().success();
+
+        let mut reads_out_content = String::new();
+        std::fs::File::open(&reads_out_path)?.read_to_string(&mut reads_out_content)?;
+        assert_eq!(reads_out_content, expected, "with {:?}", thresholds);
+    }
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+        "--min-count",
+        "3",
+        "--max-count",
+        "2",
+    ]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn approximate_matches() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+
+    std::fs::File::create(&kmers_in_path)?
+        .write_all(b">kmers\nCCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n")?;
+    // one substitution in read1 (and its reverse complement), two distant ones in read2
+    let reads = b">read1
+CCGTAATGCCTTTCCCTAACTGAGTTTTTCGAACTCGTGT
+>read1_rc
+ACACGAGTTCGAAAAACTCAGTTAGGGAAAGGCATTACGG
+>read2
+CCGTAATGCCTTTCCCTAACTGAGTTTTTCGTACTCGTGT
+";
+
+    for (args, expected) in [
+        (
+            vec!["--max-mismatches", "1"],
+            ">read1 21 67.74194 approximate:10:32.25807
+CCGTAATGCCTTTCCCTAACTGAGTTTTTCGAACTCGTGT
+>read1_rc 21 67.74194 approximate:10:32.25807
+ACACGAGTTCGAAAAACTCAGTTAGGGAAAGGCATTACGG
+>read2 12 38.70968 approximate:19:61.29032
+CCGTAATGCCTTTCCCTAACTGAGTTTTTCGTACTCGTGT
+",
+        ),
+        // the approximate matches are counted by the thresholds
+        (
+            vec!["--max-mismatches", "1", "-m", "90"],
+            ">read1 21 67.74194 approximate:10:32.25807
+CCGTAATGCCTTTCCCTAACTGAGTTTTTCGAACTCGTGT
+>read1_rc 21 67.74194 approximate:10:32.25807
+ACACGAGTTCGAAAAACTCAGTTAGGGAAAGGCATTACGG
+>read2 12 38.70968 approximate:19:61.29032
+CCGTAATGCCTTTCCCTAACTGAGTTTTTCGTACTCGTGT
+",
+        ),
+        (
+            vec!["-m", "60"],
+            ">read1 21 67.74194
+CCGTAATGCCTTTCCCTAACTGAGTTTTTCGAACTCGTGT
+>read1_rc 21 67.74194
+ACACGAGTTCGAAAAACTCAGTTAGGGAAAGGCATTACGG
+",
+        ),
+    ] {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "10",
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+        ])
+        .args(&args)
+        .write_stdin(reads.to_vec());
+
+        cmd.assert().success();
+
+        let mut reads_out_content = String::new();
+        std::fs::File::open(&reads_out_path)?.read_to_string(&mut reads_out_content)?;
+        assert_eq!(reads_out_content, expected, "with {:?}", args);
+    }
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+        "--max-mismatches",
+        "3",
+    ]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn spaced_seeds() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_in_path = temp_path.join("reads_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let kmers_out_path = temp_path.join("kmers_out.txt");
+    let index_path = temp_path.join("kmers.idx");
+
+    std::fs::File::create(&kmers_in_path)?.write_all(b">kmers\nACGTG\n")?;
+    // read2 has a substitution at the position ignored by the mask, read3 is the reverse
+    // complement of read2 and read4 does not match
+    std::fs::File::create(&reads_in_path)?
+        .write_all(b">read1\nACGTG\n>read2\nACTTG\n>read3\nCAAGT\n>read4\nAAAAA\n")?;
+
+    let run = |args: &[&str]| -> anyhow::Result<(String, String)> {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "5",
+            "--in-sequences",
+            &format!("{}", reads_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+            "--out-kmers",
+            &format!("{}", kmers_out_path.display()),
+        ])
+        .args(args);
+        cmd.assert().success();
+
+        Ok((
+            std::fs::read_to_string(&reads_out_path)?,
+            std::fs::read_to_string(&kmers_out_path)?,
+        ))
+    };
+
+    let kmers_in = format!("{}", kmers_in_path.display());
+    assert_eq!(
+        run(&["--in-kmers", &kmers_in])?,
+        (">read1 1 100\nACGTG\n".to_string(), "ACGTG 1\n".to_string())
+    );
+
+    let matched = ">read1 1 100\nACGTG\n>read2 1 100\nACTTG\n>read3 1 100\nCAAGT\n".to_string();
+    assert_eq!(
+        run(&["--in-kmers", &kmers_in, "--spaced-seed", "11011"])?,
+        (matched.clone(), "ACNTG 3\n".to_string())
+    );
+    // a kmer is matched if it is matched with any mask
+    let (reads_out, kmers_out) = run(&[
+        "--in-kmers",
+        &kmers_in,
+        "--spaced-seed",
+        "11011",
+        "--spaced-seed",
+        "10111",
+    ])?;
+    assert_eq!(reads_out, matched);
+    let mut kmers_out = kmers_out.lines().collect::<Vec<_>>();
+    kmers_out.sort();
+    assert_eq!(kmers_out, vec!["ACNTG 3", "ANGTG 0"]);
+
+    // the masks are stored in the index
+    let index = format!("{}", index_path.display());
+    run(&[
+        "--in-kmers",
+        &kmers_in,
+        "--spaced-seed",
+        "11011",
+        "--out-index",
+        &index,
+    ])?;
+    assert_eq!(
+        run(&["--in-index", &index, "--spaced-seed", "11011"])?.0,
+        matched
+    );
+
+    for args in [
+        vec!["--in-index", &index],
+        vec!["--in-kmers", &kmers_in, "--spaced-seed", "1101"],
+        vec![
+            "--in-kmers",
+            &kmers_in,
+            "--spaced-seed",
+            "11011",
+            "--max-mismatches",
+            "1",
+        ],
+    ] {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "5",
+            "--in-sequences",
+            &format!("{}", reads_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+        ])
+        .args(&args);
+        cmd.assert().failure();
+    }
+
+    Ok(())
+}
+
+#[test]
+fn kmer_sampling() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_in_path = temp_path.join("reads_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let index_path = temp_path.join("kmers.idx");
+
+    std::fs::File::create(&kmers_in_path)?.write_all(
+        b">ref
+GCTAAAGACAATTACATAACATACACGTCAGCACGAAACTTGTTGGCCCAGTGTGAATCGCTTAAGGGTTAAGTAAGTGTGATGCATACGCCTTTACTTGCTGTGTCCACCCCATCGGAC
+",
+    )?;
+    // read1 and the reverse complement of read2 are in the reference, read3 is not
+    std::fs::File::create(&reads_in_path)?.write_all(
+        b">read1
+ATTACATAACATACACGTCAGCACGAAACTTGTTGGCCCAGTGTGAATCGCTTAAGGGTTAAGTAAGTGTGATGCATACG
+>read2
+GTGGACACAGCAAGTAAAGGCGTATGCATCACACTTACTTAACCCTTAAGCGATTCACACTGGGCCAACAAGTTTCGTGC
+>read3
+TGGCATTTTTATTACACTCAGAAACAGAACTCGGGTAATTTTGACAGGTCACGCAGAGGC
+",
+    )?;
+
+    let kmers_in = format!("{}", kmers_in_path.display());
+    let index = format!("{}", index_path.display());
+    let run = |args: &[&str]| -> anyhow::Result<String> {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "15",
+            "--in-sequences",
+            &format!("{}", reads_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+        ])
+        .args(args);
+        cmd.assert().success();
+
+        Ok(std::fs::read_to_string(&reads_out_path)?)
+    };
+    let expected = |count1: usize, count2: usize| {
+        format!(
+            ">read1 {} 100
+ATTACATAACATACACGTCAGCACGAAACTTGTTGGCCCAGTGTGAATCGCTTAAGGGTTAAGTAAGTGTGATGCATACG
+>read2 {} 100
+GTGGACACAGCAAGTAAAGGCGTATGCATCACACTTACTTAACCCTTAAGCGATTCACACTGGGCCAACAAGTTTCGTGC
+",
+            count1, count2
+        )
+    };
+
+    // the ratios are relative to the number of sampled kmers
+    assert_eq!(run(&["--in-kmers", &kmers_in])?, expected(66, 66));
+    assert_eq!(
+        run(&["--in-kmers", &kmers_in, "--minimizers", "8"])?,
+        expected(19, 17)
+    );
+    let syncmers = ["--syncmers", "7", "--syncmer-offset", "1"];
+    assert_eq!(
+        run(&[
+            &["--in-kmers", &kmers_in, "--out-index", &index][..],
+            &syncmers
+        ]
+        .concat())?,
+        expected(14, 16)
+    );
+
+    // the sampling is stored in the index
+    assert_eq!(
+        run(&[&["--in-index", &index][..], &syncmers].concat())?,
+        expected(14, 16)
+    );
+    for args in [
+        vec!["--in-index", &index],
+        vec!["--in-index", &index, "--minimizers", "8"],
+        vec![
+            "--in-kmers",
+            &kmers_in,
+            "--minimizers",
+            "8",
+            "--syncmers",
+            "7",
+        ],
+        vec!["--in-kmers", &kmers_in, "--syncmers", "16"],
+        vec!["--in-kmers", &kmers_in, "--syncmer-offset", "1"],
+    ] {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "15",
+            "--in-sequences",
+            &format!("{}", reads_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+        ])
+        .args(&args);
+        cmd.assert().failure();
+    }
+
+    Ok(())
+}
+
+#[test]
+fn protein_kmers() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let proteins_in_path = temp_path.join("proteins_in.fasta");
+    let reads_in_path = temp_path.join("reads_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let kmers_out_path = temp_path.join("kmers_out.txt");
+
+    std::fs::File::create(&kmers_in_path)?.write_all(b">ref\nMKVLAWYQGHKVEDRST\n")?;
+    std::fs::File::create(&proteins_in_path)?.write_all(b">p1\nmkvlAWYQ\n>p2\nPPPPPPPP\n")?;
+    // read1 codes for MKVLAWYQ in its second frame, read2 in its reverse complement, read4 in its
+    // first frame and GHKVED in its second frame
+    std::fs::File::create(&reads_in_path)?.write_all(
+        b">read1
+CATGAAAGTTCTGGCTTGGTATCAG
+>read2
+CTGATACCAAGCCAGAACTTTCAT
+>read3
+ACGTACGTACGTACGTACGTACG
+>read4
+ATGAAAGTTCTGGCTTGGTATCAGAGGCCATAAAGTTGAAGAT
+",
+    )?;
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "5",
+        "--alphabet",
+        "protein",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--in-sequences",
+        &format!("{}", proteins_in_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+        "--out-kmers",
+        &format!("{}", kmers_out_path.display()),
+    ]);
+    cmd.assert().success();
+
+    assert_eq!(
+        std::fs::read_to_string(&reads_out_path)?,
+        ">p1 4 100\nMKVLAWYQ\n"
+    );
+    let mut kmers = std::fs::read_to_string(&kmers_out_path)?
+        .lines()
+        .filter(|line| line.ends_with(" 1"))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    kmers.sort();
+    assert_eq!(kmers, ["KVLAW 1", "LAWYQ 1", "MKVLA 1", "VLAWY 1"]);
+
+    // the DNA reads are translated, the positions are those of their best frame
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "5",
+        "--alphabet",
+        "protein",
+        "--translate",
+        "--output-mapping-positions",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--in-sequences",
+        &format!("{}", reads_in_path.display()),
+        "--out-sequences",
+        &format!("{}", reads_out_path.display()),
+        "--out-kmers",
+        &format!("{}", kmers_out_path.display()),
+    ]);
+    cmd.assert().success();
+
+    assert_eq!(
+        std::fs::read_to_string(&reads_out_path)?,
+        ">read1 4 100 0 1 2 3 (8)
+CATGAAAGTTCTGGCTTGGTATCAG
+>read2 4 100 0 1 2 3 (8)
+CTGATACCAAGCCAGAACTTTCAT
+>read4 4 40 0 1 2 3 (8)
+ATGAAAGTTCTGGCTTGGTATCAGAGGCCATAAAGTTGAAGAT
+"
+    );
+    // only the kmers of the best frame of each read are counted, not GHKVE and HKVED
+    let mut kmers = std::fs::read_to_string(&kmers_out_path)?
+        .lines()
+        .filter(|line| !line.ends_with(" 0"))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    kmers.sort();
+    assert_eq!(kmers, ["KVLAW 3", "LAWYQ 3", "MKVLA 3", "VLAWY 3"]);
+
+    for args in [
+        vec!["--translate"],
+        vec!["--alphabet", "protein", "--max-mismatches", "1"],
+        vec!["--alphabet", "protein", "--query-reverse"],
+    ] {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "5",
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+            "--in-sequences",
+            &format!("{}", reads_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+        ])
+        .args(&args);
+        cmd.assert().failure();
+    }
+
+    Ok(())
+}
+
+#[test]
+fn degenerate_kmers() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_in_path = temp_path.join("reads_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let kmers_out_path = temp_path.join("kmers_out.txt");
+
+    std::fs::File::create(&kmers_in_path)?
+        .write_all(b">probe1\nACGTRCTTAG\n>probe2\nTTGCNNNNAAT\n")?;
+    // read1 contains ACGTACTTAG, read2 the reverse complement of ACGTGCTTAG
+    std::fs::File::create(&reads_in_path)?.write_all(
+        b">read1
+GGACGTACTTAGGG
+>read2
+GGCTAAGCACGTT
+>read3
+CCCCCCCCCCCCC
+",
+    )?;
+
+    let run = |args: &[&str]| -> anyhow::Result<(String, String)> {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "8",
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+            "--in-sequences",
+            &format!("{}", reads_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+            "--out-kmers",
+            &format!("{}", kmers_out_path.display()),
+        ])
+        .args(args);
+        cmd.assert().success();
+
+        let mut kmers = std::fs::read_to_string(&kmers_out_path)?
+            .lines()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        kmers.sort();
+        Ok((std::fs::read_to_string(&reads_out_path)?, kmers.join("\n")))
+    };
+
+    // without expansion, the degenerate kmers are not indexed
+    assert_eq!(run(&[])?, (String::new(), String::new()));
+
+    // the kmers with Ns stand for too many kmers, the others are reported per degenerate kmer
+    assert_eq!(
+        run(&["--expand-iupac", "4"])?,
+        (
+            ">read1 3 42.85715
+GGACGTACTTAGGG
+>read2 3 50
+GGCTAAGCACGTT
+"
+            .to_string(),
+            "ACGTRCTT 2\nCGTRCTTA 2\nGTRCTTAG 2".to_string()
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn kmer_dumps() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let dump_path = temp_path.join("kmers.txt");
+    let list_path = temp_path.join("kmers.lst");
+    let reads_in_path = temp_path.join("reads_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let kmers_out_path = temp_path.join("kmers_out.txt");
+
+    // TTTTTCCCCC is the reverse complement of GGGGGAAAAA, their counts are summed
+    std::fs::File::create(&dump_path)?
+        .write_all(b"ACGTACTTAG\t5\nTTTTTCCCCC\t2\nGGGGGAAAAA 7\n")?;
+    std::fs::File::create(&list_path)?.write_all(b"ACGTACTTAG\nCCCCCCCCCC\n")?;
+    std::fs::File::create(&reads_in_path)?
+        .write_all(b">read1\nGGACGTACTTAGGG\n>read2\nGGGGGGAAAAAC\n")?;
+
+    let run = |kmer_size: &str, in_kmers: &[(&str, &std::path::Path)]| {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            kmer_size,
+            "--in-sequences",
+            &format!("{}", reads_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+            "--out-kmers",
+            &format!("{}", kmers_out_path.display()),
+        ]);
+        for (label, path) in in_kmers {
+            cmd.args(["--in-kmers", &format!("{}={}", label, path.display())]);
+        }
+        cmd.assert()
+    };
+    let kmers_out = || -> anyhow::Result<Vec<String>> {
+        let mut kmers = std::fs::read_to_string(&kmers_out_path)?
+            .lines()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        kmers.sort();
+        Ok(kmers)
+    };
+
+    run("10", &[("dump", &dump_path)]).success();
+    assert_eq!(
+        std::fs::read_to_string(&reads_out_path)?,
+        ">read1 1 20\nGGACGTACTTAGGG\n>read2 1 33.33333\nGGGGGGAAAAAC\n"
+    );
+    assert_eq!(kmers_out()?, ["ACGTACTTAG 1 5", "GGGGGAAAAA 1 9"]);
+
+    // the kmers without count have no abundance
+    run("10", &[("list", &list_path)]).success();
+    assert_eq!(kmers_out()?, ["ACGTACTTAG 1", "CCCCCCCCCC 0"]);
+    run("10", &[("dump", &dump_path), ("list", &list_path)]).success();
+    assert_eq!(
+        kmers_out()?,
+        ["ACGTACTTAG 1 5", "CCCCCCCCCC 0 0", "GGGGGAAAAA 1 9"]
+    );
+
+    // the kmer size of the dump must be the one of the index
+    run("9", &[("dump", &dump_path)]).failure();
+
+    Ok(())
+}
+
+#[test]
+fn exclude_kmers() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let exclude_path = temp_path.join("exclude.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let unmatched_out_path = temp_path.join("unmatched_out.fasta");
+
+    std::fs::File::create(&kmers_in_path)?
+        .write_all(b">kmers\nCCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n")?;
+    // a single excluded kmer, shared by read1 and read3 (and by the reverse complement of read2)
+    std::fs::File::create(&exclude_path)?.write_all(b">host\nCCGTAATGCC\n")?;
+    let reads = b">read1
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read2
+GGCATTACGG
+>read3
+ACGTCCGTAATGCCTTTCCCTAACA
+";
+
+    let run = |max_exclude_threshold: &str| {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "10",
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+            "--exclude-kmers",
+            &format!("{}", exclude_path.display()),
+            "--max-exclude-threshold",
+            max_exclude_threshold,
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+            "--out-unmatched",
+            &format!("{}", unmatched_out_path.display()),
+        ])
+        .write_stdin(reads.to_vec());
+        cmd.assert().success();
+    };
+
+    // by default, the sequences sharing any excluded kmer are rejected
+    run("0");
+    assert_eq!(std::fs::read_to_string(&reads_out_path)?, "");
+    assert_eq!(
+        std::fs::read_to_string(&unmatched_out_path)?,
+        ">read1 31 100 excluded:1:3.22581
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read2 1 100 excluded:1:100
+GGCATTACGG
+>read3 12 75 excluded:1:6.25
+ACGTCCGTAATGCCTTTCCCTAACA
+"
+    );
+
+    run("5");
+    assert_eq!(
+        std::fs::read_to_string(&reads_out_path)?,
+        ">read1 31 100 excluded:1:3.22581
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn indexing_progress() -> std::result::Result<(), anyhow::Error> {
+    let mut rng = biotest::rand();
+    let k_generate = biotest::Fasta::builder().sequence_len(10).build()?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let index_path = temp_path.join("index.b2s");
+    k_generate.create(&kmers_in_path, &mut rng, 500)?;
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "10",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--out-index",
+        &format!("{}", index_path.display()),
+        "--progress",
+    ]);
+    let assert = cmd.assert().success();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    assert!(stderr.contains("kmers, each of size 10\nIndexing: 500 sequences read in "));
+    if cfg!(target_os = "linux") {
+        assert!(stderr.contains("\nPeak memory: "));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn low_complexity_filter() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+
+    // the 8 last kmers of the reference have a run of at least 8 A
+    let sequence = b">ref\nGATTACAGGCAAAAAAAAAAAAAAA\n";
+    std::fs::File::create(&kmers_in_path)?.write_all(sequence)?;
+
+    let run = |args: &[&str]| -> anyhow::Result<String> {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "10",
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+            "--no-low-complexity",
+            "--low-complexity-metric",
+            "repeat",
+            "--low-complexity-threshold",
+            "8",
+        ])
+        .args(args)
+        .write_stdin(sequence.to_vec());
+        cmd.assert().success();
+        Ok(std::fs::read_to_string(&reads_out_path)?)
+    };
+
+    // the low complexity kmers are not indexed, but dilute the ratio of the sequence
+    assert_eq!(run(&[])?, ">ref 8 50\nGATTACAGGCAAAAAAAAAAAAAAA\n");
+    // unless they are also skipped in the sequence
+    assert_eq!(
+        run(&["--mask-queries"])?,
+        ">ref 8 100\nGATTACAGGCAAAAAAAAAAAAAAA\n"
+    );
+
+    // the DUST score is only defined for nucleotides
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "5",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--out-index",
+        &format!("{}", temp_path.join("index.b2s").display()),
+        "--alphabet",
+        "protein",
+        "--no-low-complexity",
+        "--low-complexity-metric",
+        "dust",
+    ]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn index_stats() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let index_path = temp_path.join("index.b2s");
+    let stats_path = temp_path.join("stats.json");
+
+    // the first kmer of gene2 is the reverse complement of the last kmer of gene1
+    std::fs::File::create(&kmers_in_path)?
+        .write_all(b">gene1 description\nACGTACNNGT\n>short\nACG\n>gene2\nGTACGA\n")?;
+
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+    cmd.args([
+        "-k",
+        "5",
+        "--in-kmers",
+        &format!("{}", kmers_in_path.display()),
+        "--out-index",
+        &format!("{}", index_path.display()),
+        "--index-stats",
+        &format!("{}", stats_path.display()),
+    ]);
+    cmd.assert()
+        .stdout(
+            "Number of indexed sequences 3, 1 shorter than the kmer size
+Number of kmers read 8, skipped: 4 with a non ACGT letter, 0 not sampled, 0 of low complexity, 0 of low abundance
+Number of kmers inserted 4 (1 as their reverse complement), 1 duplicates, 3 distinct kmers
+",
+        )
+        .success();
+
+    let file = format!("{}", kmers_in_path.display());
+    assert_eq!(
+        std::fs::read_to_string(&stats_path)?,
+        format!(
+            "{{
+  \"sequences\": 3,
+  \"short_sequences\": 1,
+  \"kmers\": 8,
+  \"non_acgt_kmers\": 4,
+  \"unsampled_kmers\": 0,
+  \"low_complexity_kmers\": 0,
+  \"low_abundance_kmers\": 0,
+  \"inserted_kmers\": 4,
+  \"reverse_complement_kmers\": 1,
+  \"duplicate_kmers\": 1,
+  \"distinct_kmers\": 3,
+  \"records\": [
+    {{\"file\": \"{file}\", \"name\": \"gene1\", \"kmers\": 2}},
+    {{\"file\": \"{file}\", \"name\": \"short\", \"kmers\": 0}},
+    {{\"file\": \"{file}\", \"name\": \"gene2\", \"kmers\": 2}}
+  ]
+}}
+"
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn kmer_sizes() -> std::result::Result<(), anyhow::Error> {
+    let mut rng = biotest::rand();
+    let s_generate = biotest::Sequence::builder().sequence_len(300).build()?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+
+    let mut sequence = vec![];
+    s_generate.record(&mut sequence, &mut rng)?;
+    let mut kmers = b">ref\n".to_vec();
+    kmers.extend(&sequence);
+    kmers.push(b'\n');
+    std::fs::File::create(&kmers_in_path)?.write_all(&kmers)?;
+    // the second sequence is shorter than the kmers
+    let mut reads = kmers.clone();
+    reads.extend(b">short\nACGT\n");
+
+    let run = |kmer_size: usize| {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            &kmer_size.to_string(),
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+        ])
+        .write_stdin(reads.clone());
+        cmd.assert()
+    };
+
+    for kmer_size in [5, 31, 32, 33, 64, 65, 128, 255, 256] {
+        let assert = run(kmer_size).success();
+        let stdout = String::from_utf8(assert.get_output().stdout.clone())?;
+        assert!(stdout.contains("Number of sequences shorter than the kmer size 1\n"));
+        assert_eq!(
+            std::fs::read_to_string(&reads_out_path)?,
+            format!(
+                ">ref {} 100\n{}\n",
+                300 - kmer_size + 1,
+                String::from_utf8(sequence.to_ascii_uppercase())?
+            )
+        );
+    }
+    // single nucleotides
+    let assert = run(1).success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone())?;
+    assert!(stdout.contains("Number of sequences shorter than the kmer size 0\n"));
+
+    // kmer sizes out of range
+    run(0).failure();
+    run(257).failure();
+
+    // sequences that are all shorter than the kmers can not be indexed
+    std::fs::File::create(&kmers_in_path)?.write_all(b">short\nACGT\n")?;
+    let assert = run(5).failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    assert!(stderr.contains("are all shorter than the kmer size (5)"));
+
+    Ok(())
+}
+
+#[test]
+fn bloom_index() -> std::result::Result<(), anyhow::Error> {
+    let mut rng = biotest::rand();
+    let s_generate = biotest::Fasta::builder().sequence_len(150).build()?;
+    let k_generate = biotest::Fasta::builder().sequence_len(21).build()?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let kmers_in_path = temp_path.join("kmers_in.fasta");
+    let reads_in_path = temp_path.join("reads_in.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let kmers_out_path = temp_path.join("kmers_out.txt");
+
+    // the kmers of some of the reads are indexed
+    let mut reads = vec![];
+    s_generate.records(&mut reads, &mut rng, 200)?;
+    let mut kmers = vec![];
+    k_generate.records(&mut kmers, &mut rng, 500)?;
+    kmers.extend(&reads[..reads.len() / 4]);
+    std::fs::File::create(&kmers_in_path)?.write_all(&kmers)?;
+    std::fs::File::create(&reads_in_path)?.write_all(&reads)?;
+
+    let run = |extra_args: &[&str]| {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "21",
+            "--in-kmers",
+            &format!("{}", kmers_in_path.display()),
+            "--in-sequences",
+            &format!("{}", reads_in_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+        ])
+        .args(extra_args);
+        cmd.assert()
+    };
+
+    run(&[]).success();
+    let exact = std::fs::read_to_string(&reads_out_path)?;
+    assert!(!exact.is_empty());
+
+    // without false positives, the same reads are output with the same ratios
+    run(&[
+        "--index-backend",
+        "bloom",
+        "--false-positive-rate",
+        "0.0000001",
+    ])
+    .success();
+    assert_eq!(std::fs::read_to_string(&reads_out_path)?, exact);
+
+    // the kmers are not counted
+    let assert = run(&[
+        "--index-backend",
+        "bloom",
+        "--out-kmers",
+        &format!("{}", kmers_out_path.display()),
+    ])
+    .success();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    assert!(stderr.contains("Warning: --out-kmers is unavailable with --index-backend bloom"));
+    assert!(!kmers_out_path.exists());
+
+    let assert = run(&[
+        "--index-backend",
+        "bloom",
+        "--out-index",
+        &format!("{}", temp_path.join("index").display()),
+    ])
+    .failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    assert!(stderr.contains("--out-index can not be used with --index-backend bloom"));
+    run(&["--index-backend", "bloom", "--false-positive-rate", "1"]).failure();
+
+    // even the default rate is ignored by the exact backend
+    let assert = run(&["--false-positive-rate", "0.01"]).success();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    assert!(stderr.contains("Warning: --false-positive-rate has no effect"));
+
+    Ok(())
+}
+
+#[test]
+fn classification() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let genes_path = temp_path.join("genes.fasta");
+    let virus_path = temp_path.join("virus.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let classification_path = temp_path.join("classification.tsv");
+
+    std::fs::File::create(&genes_path)?.write_all(
+        b">gene1 first gene
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>gene2
+TGTCGAGCGACGGAATTAGATCAGTTAAATGGCAGAAAAC
+",
+    )?;
+    std::fs::File::create(&virus_path)?
+        .write_all(b">virus\nTGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG\n")?;
+    let reads = b">read1 first read
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read2
+CCGTAATGCCTTTCCCTAACATCAGTTAAATGGCAGAAAAC
+>read3
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
+>read4
+CCGTAATGCCTTTCGAGCGACGGAATTAGATCAGTTAAATGG
+>read5
+ATATATATATATATATATATATATATATAT
+";
+
+    let run = |extra_args: &[&str]| {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "10",
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+            "--out-classification",
+            &format!("{}", classification_path.display()),
+        ])
+        .args(extra_args)
+        .write_stdin(reads.to_vec());
+        cmd.assert()
+    };
+
+    // classification to the reference records
+    run(&[
+        "--in-kmers",
+        &format!("{}", genes_path.display()),
+        "--track-origins",
+    ])
+    .success();
+    assert_eq!(
+        std::fs::read_to_string(&classification_path)?,
+        "read_id\treference\tscore\trunner_up\trunner_up_score\tambiguous
+read1\tgene1\t31\t*\t0\t0
+read2\tgene1\t12\tgene2\t12\t1
+read3\tunclassified\t0\t*\t0\t0
+read4\tgene2\t21\tgene1\t5\t0
+read5\tunclassified\t0\t*\t0\t0
+"
+    );
+
+    // classification to the labelled kmer sets
+    run(&[
+        "--in-kmers",
+        &format!("genes={}", genes_path.display()),
+        "--in-kmers",
+        &format!("virus={}", virus_path.display()),
+    ])
+    .success();
+    assert_eq!(
+        std::fs::read_to_string(&classification_path)?,
+        "read_id\treference\tscore\trunner_up\trunner_up_score\tambiguous
+read1\tgenes\t31\t*\t0\t0
+read2\tgenes\t24\t*\t0\t0
+read3\tvirus\t31\t*\t0\t0
+read4\tgenes\t26\t*\t0\t0
+read5\tunclassified\t0\t*\t0\t0
+"
+    );
+
+    // the reads can only be classified with the origins or the labels of the kmers
+    let assert = run(&["--in-kmers", &format!("{}", genes_path.display())]).failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    assert!(stderr.contains(
+        "--out-classification requires --track-origins or several labelled --in-kmers sets"
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn classification_filelist() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let genes_path = temp_path.join("genes.fasta");
+    let in_filelist = temp_path.join("in_file.lst");
+    let out_filelist = temp_path.join("out_file.lst");
+    let classification_filelist = temp_path.join("classification_file.lst");
+
+    std::fs::File::create(&genes_path)?.write_all(
+        b">gene1
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>gene2
+TGTCGAGCGACGGAATTAGATCAGTTAAATGGCAGAAAAC
+",
+    )?;
+    let reads = [
+        &b">read1\nCCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n"[..],
+        &b">read1\nTGTCGAGCGACGGAATTAGATCAGTTAAATGG\n>read2\nATATATATATATATATATATATATATATAT\n"[..],
+    ];
+    let mut in_files = String::new();
+    let mut out_files = String::new();
+    let mut classification_files = String::new();
+    for (i, reads) in reads.iter().enumerate() {
+        let in_path = temp_path.join(format!("reads{}.fasta", i));
+        std::fs::File::create(&in_path)?.write_all(reads)?;
+        in_files.push_str(&format!("{}\n", in_path.display()));
+        out_files.push_str(&format!(
+            "{}\n",
+            temp_path.join(format!("reads{}_out.fasta", i)).display()
+        ));
+        classification_files.push_str(&format!(
+            "{}\n",
+            temp_path.join(format!("classification{}.tsv", i)).display()
+        ));
+    }
+    std::fs::File::create(&in_filelist)?.write_all(in_files.as_bytes())?;
+    std::fs::File::create(&out_filelist)?.write_all(out_files.as_bytes())?;
+
+    let run = |classification_files: &str| -> std::result::Result<_, anyhow::Error> {
+        std::fs::File::create(&classification_filelist)?
+            .write_all(classification_files.as_bytes())?;
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "10",
+            "--in-kmers",
+            &format!("{}", genes_path.display()),
+            "--track-origins",
+            "--in-filelist",
+            &format!("{}", in_filelist.display()),
+            "--out-filelist",
+            &format!("{}", out_filelist.display()),
+            "--out-classification",
+            &format!("{}", classification_filelist.display()),
+        ]);
+        Ok(cmd.assert())
+    };
+
+    // the sequences of each input file are classified in their own file
+    run(&classification_files)?.success();
+    assert_eq!(
+        std::fs::read_to_string(temp_path.join("classification0.tsv"))?,
+        "read_id\treference\tscore\trunner_up\trunner_up_score\tambiguous
+read1\tgene1\t31\t*\t0\t0
+"
+    );
+    assert_eq!(
+        std::fs::read_to_string(temp_path.join("classification1.tsv"))?,
+        "read_id\treference\tscore\trunner_up\trunner_up_score\tambiguous
+read1\tgene2\t23\t*\t0\t0
+read2\tunclassified\t0\t*\t0\t0
+"
+    );
+
+    // one classification file per input file
+    let assert = run(classification_files.lines().next().unwrap())?.failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    assert!(stderr.contains("the number of classification files and input files must be the same"));
+
+    Ok(())
+}
+
+#[test]
+fn abundances() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let genes_path = temp_path.join("genes.fasta");
+    let reads_out_path = temp_path.join("reads_out.fasta");
+    let abundances_path = temp_path.join("abundances.tsv");
+
+    std::fs::File::create(&genes_path)?.write_all(
+        b">gene1 first gene
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>gene2
+TGTCGAGCGACGGAATTAGATCAGTTAAATGGCAGAAAAC
+",
+    )?;
+    // two reads of gene1, one of gene2, one shared by both genes, and one of none
+    let reads = b">read1
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>read2
+CCGTAATGCCTTTCCCTAACAGAGTTTTT
+>read3
+CCGTAATGCCTTTCCCTAACATCAGTTAAATGGCAGAAAAC
+>read4
+CCGTAATGCCTTTCGAGCGACGGAATTAGATCAGTTAAATGG
+>read5
+TGGCAGGGCTTTTAGTCGTGGGATGATCAGTGGGTAAAGG
+";
+
+    let run = |extra_args: &[&str]| {
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "10",
+            "--in-kmers",
+            &format!("{}", genes_path.display()),
+            "--out-sequences",
+            &format!("{}", reads_out_path.display()),
+            "--out-abundances",
+            &format!("{}", abundances_path.display()),
+        ])
+        .args(extra_args)
+        .write_stdin(reads.to_vec());
+        cmd.assert()
+    };
+
+    // the shared read is split 2 to 1 among the genes, as their unique reads
+    let assert = run(&["--track-origins"]).success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone())?;
+    assert!(stdout.contains(
+        "Number of sequences of - assigned to the references 4, ambiguous 1, unassigned 1"
+    ));
+    assert_eq!(
+        std::fs::read_to_string(&abundances_path)?,
+        "reference\tkmers\tunique_reads\treads\tabundance
+gene1\t31\t2\t2.67\t0.666667
+gene2\t31\t1\t1.33\t0.333333
+"
+    );
+
+    // the abundances are estimated for the records of the kmers
+    let assert = run(&[]).failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    assert!(stderr.contains("--out-abundances requires --track-origins"));
+
+    Ok(())
+}
+
+#[test]
+fn abundances_filelist() -> std::result::Result<(), anyhow::Error> {
+    let temp_dir = tempfile::tempdir()?;
+    let temp_path = temp_dir.path();
+    let genes_path = temp_path.join("genes.fasta");
+    let in_filelist = temp_path.join("in_file.lst");
+    let out_filelist = temp_path.join("out_file.lst");
+    let abundances_filelist = temp_path.join("abundances_file.lst");
+
+    std::fs::File::create(&genes_path)?.write_all(
+        b">gene1
+CCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT
+>gene2
+TGTCGAGCGACGGAATTAGATCAGTTAAATGGCAGAAAAC
+",
+    )?;
+    // the first file only has reads of gene1, the second one as many reads of each gene
+    let reads = [
+        &b">read1\nCCGTAATGCCTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n>read2\nCCGTAATGCCTTTCCCTAACAGAGTTTTT\n"[..],
+        &b">read1\nCCGTAATGCCTTTCCCTAACAGAGTTTTT\n>read2\nTGTCGAGCGACGGAATTAGATCAGTTAAATGG\n>read3\nCGACGGAATTAGATCAGTTAAATGGCAGAAAAC\n>read4\nTTTCCCTAACAGAGTTTTTCGAACTCGTGT\n"[..],
+    ];
+    let mut in_files = String::new();
+    let mut out_files = String::new();
+    let mut abundance_files = String::new();
+    for (i, reads) in reads.iter().enumerate() {
+        let in_path = temp_path.join(format!("reads{}.fasta", i));
+        std::fs::File::create(&in_path)?.write_all(reads)?;
+        in_files.push_str(&format!("{}\n", in_path.display()));
+        out_files.push_str(&format!(
+            "{}\n",
+            temp_path.join(format!("reads{}_out.fasta", i)).display()
+        ));
+        abundance_files.push_str(&format!(
+            "{}\n",
+            temp_path.join(format!("abundances{}.tsv", i)).display()
+        ));
+    }
+    std::fs::File::create(&in_filelist)?.write_all(in_files.as_bytes())?;
+    std::fs::File::create(&out_filelist)?.write_all(out_files.as_bytes())?;
+
+    let run = |abundance_files: &str| -> std::result::Result<_, anyhow::Error> {
+        std::fs::File::create(&abundances_filelist)?.write_all(abundance_files.as_bytes())?;
+        let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("back_to_sequences");
+        cmd.args([
+            "-k",
+            "10",
+            "--in-kmers",
+            &format!("{}", genes_path.display()),
+            "--track-origins",
+            "--in-filelist",
+            &format!("{}", in_filelist.display()),
+            "--out-filelist",
+            &format!("{}", out_filelist.display()),
+            "--out-abundances",
+            &format!("{}", abundances_filelist.display()),
+        ]);
+        Ok(cmd.assert())
+    };
+
+    // a table per input file, the abundances of each file being normalized independently
+    run(&abundance_files)?.success();
+    assert_eq!(
+        std::fs::read_to_string(temp_path.join("abundances0.tsv"))?,
+        "reference\tkmers\tunique_reads\treads\tabundance
+gene1\t31\t2\t2.00\t1.000000
+gene2\t31\t0\t0.00\t0.000000
+"
+    );
+    assert_eq!(
+        std::fs::read_to_string(temp_path.join("abundances1.tsv"))?,
+        "reference\tkmers\tunique_reads\treads\tabundance
+gene1\t31\t2\t2.00\t0.500000
+gene2\t31\t2\t2.00\t0.500000
+"
+    );
+
+    // one abundance table per input file
+    let assert = run(abundance_files.lines().next().unwrap())?.failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    assert!(stderr.contains("the number of abundance files and input files must be the same"));
+
+    Ok(())
+}